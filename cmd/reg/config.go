@@ -0,0 +1,124 @@
+package main
+
+import (
+	"bytes"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"os"
+	"path/filepath"
+	"sort"
+	"strings"
+
+	"github.com/BurntSushi/toml"
+	"github.com/spf13/cobra"
+	"github.com/spf13/pflag"
+)
+
+// loadConfigFile applies a JSON config file on top of the command flags. The file is an object keyed
+// by flag names, e.g. {"bucket": "images", "db-busy-timeout": "10s"}; repeatable flags take arrays.
+// Files ending in .toml are read as TOML instead, with the same keys at the top level.
+// Flags explicitly given on the command line take precedence over the file. Options belonging to
+// other commands are ignored, so that one file can be shared by all of them.
+func loadConfigFile(cmd *cobra.Command, path string) error {
+	flags := cmd.Flags()
+	data, err := os.ReadFile(path)
+	if err != nil {
+		return fmt.Errorf("failed to read config file: %w", err)
+	}
+
+	var values map[string]any
+	if strings.EqualFold(filepath.Ext(path), ".toml") {
+		err = toml.Unmarshal(data, &values)
+	} else {
+		decoder := json.NewDecoder(bytes.NewReader(data))
+		decoder.UseNumber()
+		err = decoder.Decode(&values)
+	}
+	if err != nil {
+		return fmt.Errorf("failed to parse config file: %w", err)
+	}
+
+	names := make([]string, 0, len(values))
+	for name := range values {
+		names = append(names, name)
+	}
+	sort.Strings(names)
+
+	var errs []error
+	for _, name := range names {
+		flag := flags.Lookup(name)
+		if flag == nil && isFlagOfAnyCommand(cmd.Root(), name) {
+			continue
+		}
+		if flag == nil || name == "config" {
+			errs = append(errs, fmt.Errorf("%s: unknown option", name))
+			continue
+		}
+		if flag.Changed {
+			continue
+		}
+		if err := setFlagFromConfig(flags, name, values[name]); err != nil {
+			errs = append(errs, fmt.Errorf("%s: %w", name, err))
+		}
+	}
+	return errors.Join(errs...)
+}
+
+// envPrefix prefixes the environment variables mirroring flags: --db-busy-timeout is REG_DB_BUSY_TIMEOUT.
+const envPrefix = "REG_"
+
+func envName(flag string) string {
+	return envPrefix + strings.ToUpper(strings.ReplaceAll(flag, "-", "_"))
+}
+
+// loadEnv applies the environment variables of all flags not given on the command line, and returns
+// the names of the flags it set. Marking them as changed makes them take precedence over the config file.
+// Repeatable flags take comma-separated lists.
+func loadEnv(flags *pflag.FlagSet) ([]string, error) {
+	var applied []string
+	var errs []error
+	flags.VisitAll(func(flag *pflag.Flag) {
+		value, ok := os.LookupEnv(envName(flag.Name))
+		if !ok || flag.Changed {
+			return
+		}
+		values := []string{value}
+		if strings.HasSuffix(flag.Value.Type(), "Array") || strings.HasSuffix(flag.Value.Type(), "Slice") {
+			values = strings.Split(value, ",")
+		}
+		for _, v := range values {
+			if err := flags.Set(flag.Name, strings.TrimSpace(v)); err != nil {
+				errs = append(errs, fmt.Errorf("%s: %w", envName(flag.Name), err))
+				return
+			}
+		}
+		applied = append(applied, flag.Name)
+	})
+	return applied, errors.Join(errs...)
+}
+
+func isFlagOfAnyCommand(root *cobra.Command, name string) bool {
+	for _, cmd := range root.Commands() {
+		if cmd.Flags().Lookup(name) != nil {
+			return true
+		}
+	}
+	return false
+}
+
+func setFlagFromConfig(flags *pflag.FlagSet, name string, value any) error {
+	switch v := value.(type) {
+	case []any:
+		for _, item := range v {
+			if err := flags.Set(name, fmt.Sprint(item)); err != nil {
+				return err
+			}
+		}
+		return nil
+	case map[string]any, nil:
+		return fmt.Errorf("unsupported value %v", value)
+	default:
+		return flags.Set(name, fmt.Sprint(v))
+	}
+}
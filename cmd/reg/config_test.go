@@ -0,0 +1,36 @@
+package main
+
+import (
+	"os"
+	"path/filepath"
+	"reflect"
+	"testing"
+	"time"
+
+	"github.com/spf13/cobra"
+)
+
+func TestLoadConfigFileTOML(t *testing.T) {
+	cmd := &cobra.Command{Use: "test"}
+	bucket := cmd.Flags().String("bucket", "", "")
+	timeout := cmd.Flags().Duration("db-busy-timeout", 0, "")
+	workers := cmd.Flags().Int("bootstrap-workers", 0, "")
+	readOnly := cmd.Flags().Bool("read-only", false, "")
+	overrides := cmd.Flags().StringArray("cache-ttl-override", nil, "")
+
+	path := filepath.Join(t.TempDir(), "reg.toml")
+	data := "bucket = \"images\"\ndb-busy-timeout = \"10s\"\nbootstrap-workers = 16\nread-only = true\ncache-ttl-override = [\"dev/*=0\", \"release/*=60\"]\n"
+	if err := os.WriteFile(path, []byte(data), 0o644); err != nil {
+		t.Fatalf("failed to write config: %v", err)
+	}
+	if err := loadConfigFile(cmd, path); err != nil {
+		t.Fatalf("failed to load config: %v", err)
+	}
+
+	if *bucket != "images" || *timeout != 10*time.Second || *workers != 16 || !*readOnly {
+		t.Fatalf("got bucket %q, timeout %s, workers %d, read-only %v", *bucket, *timeout, *workers, *readOnly)
+	}
+	if want := []string{"dev/*=0", "release/*=60"}; !reflect.DeepEqual(*overrides, want) {
+		t.Fatalf("got overrides %v, want %v", *overrides, want)
+	}
+}
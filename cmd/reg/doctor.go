@@ -0,0 +1,63 @@
+package main
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"os"
+
+	"github.com/psarna/reg/pkg/reg"
+	"github.com/spf13/cobra"
+)
+
+func newDoctorCommand() *cobra.Command {
+	var cfg reg.Config
+	doctorCmd := &cobra.Command{
+		Use:   "doctor",
+		Short: "Check the storage, credentials and database the registry is configured with",
+		Long: `Check the storage, credentials and database the registry is configured with.
+
+Verifies that AWS credentials resolve, every bucket is reachable, the registry layout exists
+under --storage-prefix and the database is writable, printing one line per check. Exits with
+status 1 if any of them fails, so it can gate deployments.`,
+		Run: func(cmd *cobra.Command, args []string) {
+			loadConfig(cmd, func() error { return cfg.Validate() })
+			if !runDoctor(&cfg) {
+				os.Exit(1)
+			}
+		},
+	}
+
+	addRegistryFlags(doctorCmd.Flags(), &cfg)
+	return doctorCmd
+}
+
+// runDoctor prints the outcome of every check and reports whether all of them passed.
+func runDoctor(cfg *reg.Config) bool {
+	checks := reg.Doctor(context.Background(), *cfg)
+
+	passed := true
+	for _, check := range checks {
+		passed = passed && check.OK
+	}
+	if cfg.ProgressFormat == reg.ProgressFormatJSON {
+		if err := json.NewEncoder(os.Stdout).Encode(checks); err != nil {
+			log.Fatalf("Failed to write doctor result: %v", err)
+		}
+		return passed
+	}
+
+	ok, failed, reset := "[ok]", "[FAIL]", ""
+	if info, err := os.Stdout.Stat(); err == nil && info.Mode()&os.ModeCharDevice != 0 {
+		ok, failed, reset = "\033[32m✔\033[0m", "\033[31m✘", "\033[0m"
+	}
+	for _, check := range checks {
+		if check.OK {
+			fmt.Printf("%s %s\n", ok, check.Name)
+		} else {
+			fmt.Printf("%s %s: %s%s\n", failed, check.Name, check.Detail, reset)
+		}
+	}
+	return passed
+}
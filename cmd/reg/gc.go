@@ -0,0 +1,71 @@
+package main
+
+import (
+	"context"
+	"fmt"
+	"log"
+	"log/slog"
+	"time"
+
+	"github.com/psarna/reg/pkg/reg"
+	"github.com/spf13/cobra"
+)
+
+func newGCCommand() *cobra.Command {
+	var cfg reg.Config
+	var dryRun bool
+	var gracePeriod time.Duration
+	gcCmd := &cobra.Command{
+		Use:   "gc",
+		Short: "Delete blobs which are not referenced by any manifest",
+		Long: `Delete blobs which are not referenced by any manifest.
+
+The set of referenced blobs is computed from the database, so it needs to be in sync with
+the storage - pass --bootstrap if manifests might have been pushed around this registry.
+Blobs younger than --grace-period are kept, since they may belong to a push in progress.`,
+		Run: func(cmd *cobra.Command, args []string) {
+			loadConfig(cmd, func() error { return cfg.Validate() })
+			runGC(cmd, &cfg, dryRun, gracePeriod)
+		},
+	}
+
+	addRegistryFlags(gcCmd.Flags(), &cfg)
+	gcCmd.Flags().BoolP("bootstrap", "B", false, "Bootstrap the database from storage before collecting")
+	gcCmd.Flags().BoolVar(&dryRun, "dry-run", false, "Only report the blobs which would be deleted")
+	gcCmd.Flags().DurationVar(&gracePeriod, "grace-period", 24*time.Hour, "Keep unreferenced blobs younger than this")
+	return gcCmd
+}
+
+func runGC(cmd *cobra.Command, cfg *reg.Config, dryRun bool, gracePeriod time.Duration) {
+	bootstrap, err := cmd.Flags().GetBool("bootstrap")
+	if err != nil {
+		log.Fatalf("Failed to get bootstrap flag: %v", err)
+	}
+
+	ctx := context.Background()
+	registry, err := reg.NewRegistry(ctx, *cfg)
+	if err != nil {
+		log.Fatalf("Failed to create registry: %v", err)
+	}
+	defer registry.Close()
+
+	if bootstrap {
+		if err := registry.Bootstrap(ctx); err != nil {
+			log.Fatalf("Failed to bootstrap registry: %v", err)
+		}
+		slog.Info("Bootstrap completed")
+	}
+
+	result, err := registry.GarbageCollect(ctx, dryRun, gracePeriod)
+	if err != nil {
+		log.Fatalf("Garbage collection failed: %v", err)
+	}
+
+	verb := "Deleted"
+	if dryRun {
+		verb = "Would delete"
+	}
+	fmt.Printf("Scanned %d blobs, %d referenced by manifests, %d kept within the grace period\n",
+		result.Scanned, result.Referenced, result.Recent)
+	fmt.Printf("%s %d unreferenced blobs (%d bytes)\n", verb, len(result.Deleted), result.DeletedBytes)
+}
@@ -2,6 +2,9 @@ package main
 
 import (
 	"context"
+	"crypto/tls"
+	"crypto/x509"
+	"errors"
 	"fmt"
 	"log"
 	"log/slog"
@@ -9,30 +12,69 @@ import (
 	"os"
 	"os/signal"
 	"syscall"
+	"time"
 
 	"github.com/psarna/reg/pkg/reg"
 	"github.com/spf13/cobra"
+	"github.com/spf13/pflag"
 )
 
 func main() {
 	var rootCmd = &cobra.Command{
 		Use:   "reg",
 		Short: "reg is a registry server",
+		Long: `reg is a registry server.
+
+Every flag can also be set with an environment variable named REG_ followed by the flag name
+in upper case with dashes replaced by underscores, e.g. REG_DB_BUSY_TIMEOUT=10s; repeatable
+flags take comma-separated lists. Flags given on the command line take precedence over
+environment variables, which take precedence over the config file given with --config.`,
 	}
 
+	var cfg reg.Config
 	var serveCmd = &cobra.Command{
 		Use:   "serve",
 		Short: "Start the registry server",
-		Run:   runServe,
+		Run: func(cmd *cobra.Command, args []string) {
+			runServe(cmd, &cfg)
+		},
 	}
 
-	var bucket string
-	var bootstrap bool
-	serveCmd.Flags().StringVarP(&bucket, "bucket", "b", "", "Bucket name (required)")
-	serveCmd.Flags().BoolVarP(&bootstrap, "bootstrap", "B", false, "Bootstrap the registry from S3 (might take a few centuries for large registries)")
-	serveCmd.MarkFlagRequired("bucket")
+	addRegistryFlags(serveCmd.Flags(), &cfg)
+	serveCmd.Flags().StringVarP(&cfg.Listen, "listen", "l", ":2137", "Address to listen on")
+	serveCmd.Flags().StringVar(&cfg.TLSCert, "tls-cert", "", "Path to a PEM certificate (chain) to serve HTTPS with, requires --tls-key")
+	serveCmd.Flags().StringVar(&cfg.TLSKey, "tls-key", "", "Path to the PEM private key for --tls-cert")
+	serveCmd.Flags().StringVar(&cfg.ClientCA, "client-ca", "", "Path to PEM CA certificates; clients must present a certificate signed by one of them, requires --tls-cert")
+	serveCmd.Flags().BoolVar(&cfg.HTTP2, "http2", false, "Also accept HTTP/2, over plaintext connections (h2c) too")
+	serveCmd.Flags().BoolVar(&cfg.Compress, "compress", false, "Gzip JSON listings (tags, catalog, stats...) for clients sending Accept-Encoding: gzip")
+	serveCmd.Flags().Bool("self-check", false, "Run the checks of the doctor command before serving and exit if any fails")
+	serveCmd.Flags().BoolP("bootstrap", "B", false, "Bootstrap the registry from S3 (might take a few centuries for large registries)")
+	serveCmd.Flags().Int64Var(&cfg.MaxUploadBytes, "max-upload-bytes", 0, "Largest blob accepted by uploads, chunked or not, and largest body of any push request; larger ones fail with 413. 0 disables the limit")
+	serveCmd.Flags().DurationVar(&cfg.RequestTimeout, "request-timeout", 0, "Fail requests taking longer than this with 504, except blob downloads and uploads; 0 disables it")
+	serveCmd.Flags().IntVar(&cfg.ListingCacheSeconds, "listing-cache-seconds", 30, "Cache-Control max-age of the catalog, tag list and stats responses, letting clients and CDNs reuse them; 0 disables it")
+	serveCmd.Flags().BoolVar(&cfg.ReadOnly, "read-only", false, "Reject every push and delete with 405 and never write to storage or the database, only serve reads")
+	serveCmd.Flags().BoolVar(&cfg.DBOnly, "db-only", false, "Serve from the database alone without ever reaching storage, answering 404 for anything not in it; requires --read-only")
+	serveCmd.Flags().BoolVar(&cfg.EnableDelete, "enable-delete", false, "Allow deleting manifests through the API")
+	serveCmd.Flags().BoolVar(&cfg.ProxyFallback, "proxy-fallback", false, "Stream blobs through the registry when presigning a redirect fails")
+	serveCmd.Flags().BoolVar(&cfg.VerifyBlobs, "verify-blobs", false, "Hash blobs streamed through the registry and abort transfers not matching their digest; costs CPU per proxied byte")
+	serveCmd.Flags().BoolVar(&cfg.InternalBucket, "internal-bucket", false, "The bucket is not reachable by clients, so always stream blobs instead of redirecting")
+	serveCmd.Flags().StringSliceVar(&cfg.CORSAllowOrigins, "cors-allow-origin", nil, "Origin allowed to call the API from a browser, repeatable; * allows any")
+	serveCmd.Flags().StringVar(&cfg.ACLPath, "acl", "", "Path to a JSON file with per-repository allow/deny rules")
+	serveCmd.Flags().StringVar(&cfg.OTLPEndpoint, "otlp-endpoint", "", "OTLP/HTTP collector to export request and S3 spans to, e.g. http://localhost:4318; tracing is off when empty")
+	serveCmd.Flags().StringVar(&cfg.AdminToken, "admin-token", "", "Bearer token required by the /admin endpoints, which are disabled when empty")
+	serveCmd.Flags().IntVar(&cfg.ManifestCacheSize, "manifest-cache-size", 1024, "Number of manifests kept in the in-memory cache in front of the database, 0 to disable")
+	serveCmd.Flags().BoolVar(&cfg.PrefetchChildren, "prefetch-children", false, "When caching an image index, store its per-platform manifests in the database and the manifest cache in the background")
+	serveCmd.Flags().IntVar(&cfg.PresignCacheSize, "presign-cache-size", 4096, "Number of presigned blob URLs reused for repeated pulls, 0 to disable")
+	serveCmd.Flags().DurationVar(&cfg.CacheTTL, "cache-ttl", 0, "Resolve a tag from storage again once its cached manifest was last checked this long ago; 0 serves cached tags indefinitely")
+	serveCmd.Flags().StringArrayVar(&cfg.CacheTTLOverrides, "cache-ttl-override", nil, "Cache TTL of the repositories matching a glob, as glob=seconds, repeatable; the first match wins and 0 never serves them from the cache")
+	serveCmd.Flags().DurationVar(&cfg.RefreshInterval, "refresh-interval", 0, "Re-check cached tags against storage in the background this often; 0 disables it")
+	serveCmd.Flags().DurationVar(&cfg.WALCheckpointInterval, "wal-checkpoint-interval", 0, "Checkpoint and truncate the database write-ahead log this often and on shutdown, bounding its size; 0 disables it")
+	serveCmd.Flags().BoolVar(&cfg.NoCache, "no-cache", false, "Resolve manifests and tags from storage on every request, bypassing the database and in-memory caches")
 
 	rootCmd.AddCommand(serveCmd)
+	rootCmd.AddCommand(newGCCommand())
+	rootCmd.AddCommand(newScanCommand())
+	rootCmd.AddCommand(newDoctorCommand())
 
 	if err := rootCmd.Execute(); err != nil {
 		log.Fatalf("Failed to execute command: %v", err)
@@ -50,32 +92,140 @@ $$ |      \$$$$$$$\ \$$$$$$$ |
                     \$$$$$$  |
                      \______/ `
 
-func runServe(cmd *cobra.Command, args []string) {
-	bucket, err := cmd.Flags().GetString("bucket")
+// shutdownTimeout bounds how long a shutdown waits for requests in flight, e.g. blob downloads.
+const shutdownTimeout = 30 * time.Second
+
+// addRegistryFlags binds the flags shared by all commands which open the registry storage and database.
+func addRegistryFlags(flags *pflag.FlagSet, cfg *reg.Config) {
+	flags.StringP("config", "c", "", "Path to a JSON (or .toml) config file keyed by flag names; flags given on the command line take precedence")
+	flags.StringVar(&cfg.LogFormat, "log-format", reg.LogFormatText, "Log output format: text or json")
+	flags.StringVar(&cfg.ProgressFormat, "progress-format", reg.ProgressFormatText, "Bootstrap progress format: text (logged) or json (one object per line on stdout, ending with a summary)")
+	flags.StringVar(&cfg.Storage, "storage", reg.StorageS3, "Storage backend: s3 or fs")
+	flags.StringVarP(&cfg.Bucket, "bucket", "b", "", "Bucket name (required for s3 storage)")
+	flags.StringArrayVar(&cfg.BucketRoutes, "bucket-route", nil, "Store repositories starting with a prefix in another bucket, as prefix=bucket, repeatable; the longest prefix wins")
+	flags.StringVar(&cfg.AWSRegion, "aws-region", "", "AWS region, overriding the one from the environment or shared config")
+	flags.StringVar(&cfg.AWSProfile, "aws-profile", "", "Named profile from the shared AWS config and credentials files")
+	flags.StringVar(&cfg.AWSAccessKeyID, "aws-access-key-id", "", "Static AWS access key id, requires --aws-secret-access-key")
+	flags.StringVar(&cfg.AWSSecretAccessKey, "aws-secret-access-key", "", "Static AWS secret access key, requires --aws-access-key-id")
+	flags.DurationVar(&cfg.S3Timeout, "s3-timeout", 30*time.Second, "Timeout for connecting to S3 and waiting for its responses, per attempt; 0 disables it")
+	flags.IntVar(&cfg.MaxS3Concurrency, "max-s3-concurrency", 256, "Most S3 requests in flight at once across all buckets, others queue up; 0 disables the limit")
+	flags.StringVar(&cfg.StoragePrefix, "storage-prefix", reg.DefaultStoragePrefix, "Key prefix of the registry layout in the bucket or root directory, for trees not stored under the default")
+	flags.StringVar(&cfg.StorageRoot, "root", "", "Root directory of the registry layout (required for fs storage)")
+	flags.StringVar(&cfg.DBPath, "db", "registry.db", "Path to the SQLite database")
+	flags.DurationVar(&cfg.DBBusyTimeout, "db-busy-timeout", 5*time.Second, "How long database writers wait for a lock before failing with SQLITE_BUSY")
+	flags.BoolVar(&cfg.CompressCache, "compress-cache", false, "Gzip the manifests stored in the database from now on, trading some CPU for a smaller database file")
+	flags.Int64Var(&cfg.MaxManifestBytes, "max-manifest-bytes", 4<<20, "Largest manifest read into memory, when pushed or fetched from storage")
+	flags.StringArrayVar(&cfg.AllowMediaTypes, "allow-media-type", nil, "Manifest media type allowed to be pushed, served and cached, repeatable; defaults to the OCI and Docker image manifests and indexes")
+	flags.IntVar(&cfg.BootstrapWorkers, "bootstrap-workers", 64, "Number of tags resolved from storage concurrently while bootstrapping")
+	flags.StringArrayVar(&cfg.SQLitePragmas, "sqlite-pragma", nil, "SQLite pragma set on every connection, as name=value, repeatable: synchronous (default NORMAL), cache_size, mmap_size, temp_store, wal_autocheckpoint or journal_size_limit")
+}
+
+// loadConfig applies the environment variables and the config file given with --config (if any),
+// in that order of precedence, validates the result and sets up logging according to it.
+func loadConfig(cmd *cobra.Command, validate func() error) {
+	fromEnv, err := loadEnv(cmd.Flags())
+	if err != nil {
+		log.Fatalf("Invalid environment variables:\n%v", err)
+	}
+	configPath, err := cmd.Flags().GetString("config")
+	if err != nil {
+		log.Fatalf("Failed to get config flag: %v", err)
+	}
+	if configPath != "" {
+		if err := loadConfigFile(cmd, configPath); err != nil {
+			log.Fatalf("Invalid config file %s:\n%v", configPath, err)
+		}
+	}
+	if err := validate(); err != nil {
+		log.Fatalf("Invalid configuration:\n%v", err)
+	}
+
+	logFormat, err := cmd.Flags().GetString("log-format")
+	if err != nil {
+		log.Fatalf("Failed to get log-format flag: %v", err)
+	}
+	handlerOpts := &slog.HandlerOptions{
+		Level: slog.LevelDebug,
+	}
+	if logFormat == reg.LogFormatJSON {
+		slog.SetDefault(slog.New(reg.NewContextLogHandler(slog.NewJSONHandler(os.Stdout, handlerOpts))))
+	} else {
+		slog.SetDefault(slog.New(reg.NewContextLogHandler(slog.NewTextHandler(os.Stdout, handlerOpts))))
+	}
+	slog.Info("Configuration loaded", "precedence", "flags > environment > config file > defaults", "from_env", fromEnv, "config_file", configPath)
+}
+
+func runServe(cmd *cobra.Command, cfg *reg.Config) {
+	loadConfig(cmd, func() error { return cfg.ValidateServe() })
+
+	var tlsConfig *tls.Config
+	if cfg.TLSCert != "" {
+		cert, err := tls.LoadX509KeyPair(cfg.TLSCert, cfg.TLSKey)
+		if err != nil {
+			log.Fatalf("Failed to load TLS certificate %s and key %s: %v", cfg.TLSCert, cfg.TLSKey, err)
+		}
+		tlsConfig = &tls.Config{
+			Certificates: []tls.Certificate{cert},
+			MinVersion:   tls.VersionTLS12,
+		}
+	}
+	if cfg.ClientCA != "" {
+		caPEM, err := os.ReadFile(cfg.ClientCA)
+		if err != nil {
+			log.Fatalf("Failed to read client CA %s: %v", cfg.ClientCA, err)
+		}
+		clientCAs := x509.NewCertPool()
+		if !clientCAs.AppendCertsFromPEM(caPEM) {
+			log.Fatalf("Failed to load client CA %s: no PEM certificates found", cfg.ClientCA)
+		}
+		// NOTICE: enforced during the handshake, so it covers every endpoint, probes included
+		tlsConfig.ClientCAs = clientCAs
+		tlsConfig.ClientAuth = tls.RequireAndVerifyClientCert
+	}
+
+	selfCheck, err := cmd.Flags().GetBool("self-check")
 	if err != nil {
-		log.Fatalf("Failed to get bucket flag: %v", err)
+		slog.Error("Failed to get self-check flag", "err", err)
+	}
+	if selfCheck && !runDoctor(cfg) {
+		log.Fatalf("Self-check failed, see above")
 	}
+
 	bootstrap, err := cmd.Flags().GetBool("bootstrap")
 	if err != nil {
 		slog.Error("Failed to get bootstrap flag", "err", err)
 	}
+	if bootstrap && cfg.ReadOnly {
+		log.Fatalf("Invalid configuration:\nread-only: conflicts with bootstrap, which writes to the database")
+	}
 
-	ctx := context.Background()
-	registry, err := reg.NewRegistry(ctx, bucket)
+	// NOTICE: a signal cancels ctx, which stops the background loops and shuts the server down
+	ctx, stop := signal.NotifyContext(context.Background(), syscall.SIGINT, syscall.SIGTERM)
+	defer stop()
+	shutdownTracing := func(context.Context) error { return nil }
+	if cfg.OTLPEndpoint != "" {
+		shutdownTracing, err = reg.SetupTracing(cfg.OTLPEndpoint)
+		if err != nil {
+			log.Fatalf("Failed to set up tracing: %v", err)
+		}
+		slog.Info("Exporting traces", "endpoint", cfg.OTLPEndpoint)
+	}
+	flushTraces := func() {
+		flushCtx, cancel := context.WithTimeout(context.Background(), 5*time.Second)
+		defer cancel()
+		if err := shutdownTracing(flushCtx); err != nil {
+			slog.Warn("Failed to flush traces", "err", err)
+		}
+	}
+	defer flushTraces()
+
+	// NOTICE: the registry (S3 clients included) is created after tracing is set up, so that storage requests are traced
+	registry, err := reg.NewRegistry(ctx, *cfg)
 	if err != nil {
 		log.Fatalf("Failed to create registry: %v", err)
 	}
 	defer registry.Close()
 
-	signalChan := make(chan os.Signal, 1)
-	signal.Notify(signalChan, syscall.SIGINT)
-	go func() {
-		sig := <-signalChan
-		fmt.Printf("Received signal: %v, running cleanup\n", sig)
-		registry.Close()
-		os.Exit(0)
-	}()
-
 	if bootstrap {
 		if err := registry.Bootstrap(ctx); err != nil {
 			slog.Error("Failed to bootstrap registry", "err", err)
@@ -84,17 +234,65 @@ func runServe(cmd *cobra.Command, args []string) {
 		slog.Info("Bootstrap completed")
 	}
 
+	if cfg.RefreshInterval > 0 {
+		go registry.RunTagRefresh(ctx, cfg.RefreshInterval)
+	}
+	if cfg.WALCheckpointInterval > 0 {
+		go registry.RunWALCheckpoints(ctx, cfg.WALCheckpointInterval)
+	}
+
 	r, err := reg.NewRouter(ctx, registry)
 	if err != nil {
 		log.Fatalf("Failed to create router: %v", err)
 	}
 
-	slog.SetDefault(slog.New(slog.NewTextHandler(os.Stdout, &slog.HandlerOptions{
-		Level: slog.LevelDebug,
-	})))
-	port := ":2137"
+	scheme := "http"
+	if tlsConfig != nil {
+		scheme = "https"
+	}
 	fmt.Println(splash)
 	fmt.Println()
-	fmt.Printf("Server starting on %s with bucket '%s'...\n", port, bucket)
-	log.Fatal(http.ListenAndServe(port, r))
+	if cfg.Storage == reg.StorageFS {
+		fmt.Printf("Server starting on %s (%s) with storage root '%s'...\n", cfg.Listen, scheme, cfg.StorageRoot)
+	} else {
+		fmt.Printf("Server starting on %s (%s) with bucket '%s'...\n", cfg.Listen, scheme, cfg.Bucket)
+	}
+	server := &http.Server{
+		Addr:      cfg.Listen,
+		Handler:   r,
+		TLSConfig: tlsConfig,
+	}
+	if cfg.HTTP2 {
+		// NOTICE: multiplexing lets clients pulling many manifests at once reuse a single
+		// connection instead of opening one per request, which mostly saves on handshakes
+		// and connection slots in front proxies; large blob transfers are unaffected.
+		var protocols http.Protocols
+		protocols.SetHTTP1(true)
+		protocols.SetHTTP2(true)
+		protocols.SetUnencryptedHTTP2(true)
+		server.Protocols = &protocols
+	}
+
+	// NOTICE: requests in flight are let finish before main returns and the deferred cleanup
+	// closes the registry under them
+	shutdownDone := make(chan struct{})
+	go func() {
+		defer close(shutdownDone)
+		<-ctx.Done()
+		slog.Info("Received signal, shutting down")
+		shutdownCtx, cancel := context.WithTimeout(context.Background(), shutdownTimeout)
+		defer cancel()
+		if err := server.Shutdown(shutdownCtx); err != nil {
+			slog.Warn("Failed to shut down gracefully", "err", err)
+		}
+	}()
+	if tlsConfig != nil {
+		err = server.ListenAndServeTLS("", "")
+	} else {
+		err = server.ListenAndServe()
+	}
+	if !errors.Is(err, http.ErrServerClosed) {
+		log.Fatal(err)
+	}
+	<-shutdownDone
 }
@@ -0,0 +1,50 @@
+package main
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"log"
+	"os"
+
+	"github.com/psarna/reg/pkg/reg"
+	"github.com/spf13/cobra"
+)
+
+func newScanCommand() *cobra.Command {
+	var cfg reg.Config
+	scanCmd := &cobra.Command{
+		Use:   "scan",
+		Short: "Report what the storage holds, without touching the database",
+		Long: `Report what the storage holds, without touching the database.
+
+This is a read-only dry run of --bootstrap: it counts repositories, tags, unique manifests
+and blobs from object listings alone, without fetching any manifest, to estimate how long
+a bootstrap would take.`,
+		Run: func(cmd *cobra.Command, args []string) {
+			loadConfig(cmd, func() error { return cfg.Validate() })
+			runScan(&cfg)
+		},
+	}
+
+	addRegistryFlags(scanCmd.Flags(), &cfg)
+	return scanCmd
+}
+
+func runScan(cfg *reg.Config) {
+	result, err := reg.Scan(context.Background(), *cfg)
+	if err != nil {
+		log.Fatalf("Scan failed: %v", err)
+	}
+
+	if cfg.ProgressFormat == reg.ProgressFormatJSON {
+		if err := json.NewEncoder(os.Stdout).Encode(result); err != nil {
+			log.Fatalf("Failed to write scan result: %v", err)
+		}
+		return
+	}
+	fmt.Printf("Repositories: %d\n", result.Repositories)
+	fmt.Printf("Tags:         %d\n", result.Tags)
+	fmt.Printf("Manifests:    %d\n", result.Manifests)
+	fmt.Printf("Blobs:        %d (%d bytes)\n", result.Blobs, result.BlobBytes)
+}
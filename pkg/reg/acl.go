@@ -0,0 +1,89 @@
+package reg
+
+import (
+	"bytes"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"net/http"
+	"os"
+	"path"
+
+	"github.com/gorilla/mux"
+)
+
+const (
+	aclAllow = "allow"
+	aclDeny  = "deny"
+)
+
+type aclRule struct {
+	Pattern string `json:"pattern"`
+	Access  string `json:"access"`
+}
+
+// ACL decides which repositories can be accessed. Rules are checked in order and the first one
+// whose pattern matches the repository name wins; patterns use path.Match syntax, so "team/*"
+// matches "team/app" but not "team/app/nested".
+type ACL struct {
+	Default string    `json:"default"`
+	Rules   []aclRule `json:"rules"`
+}
+
+func LoadACL(p string) (*ACL, error) {
+	data, err := os.ReadFile(p)
+	if err != nil {
+		return nil, fmt.Errorf("failed to read acl file: %w", err)
+	}
+	var acl ACL
+	decoder := json.NewDecoder(bytes.NewReader(data))
+	decoder.DisallowUnknownFields()
+	if err := decoder.Decode(&acl); err != nil {
+		return nil, fmt.Errorf("failed to parse acl file: %w", err)
+	}
+
+	var errs []error
+	if acl.Default != aclAllow && acl.Default != aclDeny {
+		errs = append(errs, fmt.Errorf("default: expected allow or deny, got %q", acl.Default))
+	}
+	for i, rule := range acl.Rules {
+		if rule.Access != aclAllow && rule.Access != aclDeny {
+			errs = append(errs, fmt.Errorf("rules[%d].access: expected allow or deny, got %q", i, rule.Access))
+		}
+		if _, err := path.Match(rule.Pattern, ""); err != nil {
+			errs = append(errs, fmt.Errorf("rules[%d].pattern: %w", i, err))
+		}
+	}
+	if err := errors.Join(errs...); err != nil {
+		return nil, err
+	}
+	return &acl, nil
+}
+
+// Allowed reports whether a repository can be accessed, always without an ACL.
+func (a *ACL) Allowed(name string) bool {
+	if a == nil {
+		return true
+	}
+	for _, rule := range a.Rules {
+		if ok, _ := path.Match(rule.Pattern, name); ok {
+			return rule.Access == aclAllow
+		}
+	}
+	return a.Default == aclAllow
+}
+
+func (h *Handler) checkACL(next http.Handler) http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		vars := mux.Vars(r)
+		for _, key := range []string{"name", "other_name"} {
+			name, ok := vars[key]
+			if !ok || h.registry.acl.Allowed(name) {
+				continue
+			}
+			writeOCIError(w, r, http.StatusForbidden, errCodeDenied, "requested access to the resource is denied", map[string]string{"name": name})
+			return
+		}
+		next.ServeHTTP(w, r)
+	})
+}
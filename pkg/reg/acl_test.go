@@ -0,0 +1,72 @@
+package reg
+
+import (
+	"context"
+	"os"
+	"path/filepath"
+	"slices"
+	"testing"
+)
+
+func newTestACLRegistry(t *testing.T) *Registry {
+	t.Helper()
+	aclPath := filepath.Join(t.TempDir(), "acl.json")
+	acl := `{"default": "allow", "rules": [{"pattern": "b*", "access": "deny"}]}`
+	if err := os.WriteFile(aclPath, []byte(acl), 0o644); err != nil {
+		t.Fatalf("failed to write acl: %v", err)
+	}
+	r := newTestRegistry(t, func(cfg *Config) { cfg.ACLPath = aclPath })
+	for _, repo := range []string{"a", "b1", "b2", "b3", "b4", "b5", "c", "d"} {
+		if err := r.db.PutTags(repo, []string{"latest"}); err != nil {
+			t.Fatalf("failed to put tags: %v", err)
+		}
+	}
+	return r
+}
+
+func TestListCatalogFiltersDeniedRepositories(t *testing.T) {
+	ctx := context.Background()
+	r := newTestACLRegistry(t)
+
+	for _, tc := range []struct {
+		last string
+		want []string
+		more bool
+	}{
+		{last: "", want: []string{"a", "c"}, more: true},
+		{last: "c", want: []string{"d"}, more: false},
+	} {
+		repos, more, err := r.listCatalog(ctx, tc.last, 2)
+		if err != nil {
+			t.Fatalf("failed to list catalog: %v", err)
+		}
+		if !slices.Equal(repos, tc.want) || more != tc.more {
+			t.Errorf("last=%q: got %v (more: %v), want %v (more: %v)", tc.last, repos, more, tc.want, tc.more)
+		}
+	}
+}
+
+func TestListRepositoriesFiltersDeniedRepositories(t *testing.T) {
+	ctx := context.Background()
+	r := newTestACLRegistry(t)
+
+	var got []string
+	var token *string
+	for range 10 {
+		repos, next, err := r.listRepositories(ctx, token, 2)
+		if err != nil {
+			t.Fatalf("failed to list repositories: %v", err)
+		}
+		if next == nil {
+			break
+		}
+		if len(repos) == 0 || len(repos) > 2 {
+			t.Fatalf("got a page of %d repositories, want 1 or 2", len(repos))
+		}
+		got = append(got, repos...)
+		token = next
+	}
+	if want := []string{"a", "c", "d"}; !slices.Equal(got, want) {
+		t.Fatalf("got %v, want %v", got, want)
+	}
+}
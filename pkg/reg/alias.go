@@ -0,0 +1,70 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"fmt"
+)
+
+var (
+	ErrInvalidAlias = errors.New("invalid alias")
+	ErrPushToAlias  = errors.New("aliases are read-only, push to the repository they stand for")
+)
+
+// loadAliases reads the repository aliases into memory, they are looked up on every pull.
+func (r *Registry) loadAliases() error {
+	aliases, err := r.db.ListAliases()
+	if err != nil {
+		return err
+	}
+	r.aliasesMu.Lock()
+	r.aliases = aliases
+	r.aliasesMu.Unlock()
+	return nil
+}
+
+// canonicalName returns the repository an alias stands for, or name itself if it isn't one.
+// Pulls through an alias are served from the canonical repository's storage and cache entries.
+func (r *Registry) canonicalName(name string) string {
+	r.aliasesMu.RLock()
+	defer r.aliasesMu.RUnlock()
+	if canonical, ok := r.aliases[name]; ok {
+		return canonical
+	}
+	return name
+}
+
+// SetAlias makes alias resolve to repository. An alias of an alias points at the final
+// repository, so that lookups never chain.
+func (r *Registry) SetAlias(_ context.Context, alias string, repository string) (string, error) {
+	repository = r.canonicalName(repository)
+	if alias == repository {
+		return "", fmt.Errorf("%w: %s would point at itself", ErrInvalidAlias, alias)
+	}
+	r.aliasesMu.Lock()
+	defer r.aliasesMu.Unlock()
+	for other, canonical := range r.aliases {
+		if canonical == alias {
+			return "", fmt.Errorf("%w: %s is the target of alias %s", ErrInvalidAlias, alias, other)
+		}
+	}
+	if err := r.db.PutAlias(alias, repository); err != nil {
+		return "", err
+	}
+	if r.aliases == nil {
+		r.aliases = make(map[string]string)
+	}
+	r.aliases[alias] = repository
+	return repository, nil
+}
+
+// DeleteAlias removes an alias, reporting fs.ErrNotExist if there is none.
+func (r *Registry) DeleteAlias(_ context.Context, alias string) error {
+	r.aliasesMu.Lock()
+	defer r.aliasesMu.Unlock()
+	if err := r.db.DeleteAlias(alias); err != nil {
+		return err
+	}
+	delete(r.aliases, alias)
+	return nil
+}
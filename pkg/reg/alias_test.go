@@ -0,0 +1,57 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"net/http"
+	"net/http/httptest"
+	"slices"
+	"strings"
+	"testing"
+
+	"github.com/opencontainers/go-digest"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+func TestAliasResolvesToRepository(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t)
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+	if _, err := r.SetAlias(ctx, "bar", "foo"); err != nil {
+		t.Fatalf("failed to set alias: %v", err)
+	}
+
+	tags, err := r.listTags(ctx, "bar")
+	if err != nil {
+		t.Fatalf("failed to list tags: %v", err)
+	}
+	if !slices.Equal(tags, []string{"latest"}) {
+		t.Fatalf("got tags %v, want [latest]", tags)
+	}
+	_, manifestBytes, err := r.getManifest(ctx, "bar", "latest")
+	if err != nil {
+		t.Fatalf("failed to get manifest: %v", err)
+	}
+	if got := digest.FromBytes(manifestBytes); got != dgst {
+		t.Fatalf("got manifest %s, want %s", got, dgst)
+	}
+}
+
+func TestPushToAliasDenied(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t)
+	pushTestImage(t, r, "foo", "latest", "layer")
+	if _, err := r.SetAlias(ctx, "bar", "foo"); err != nil {
+		t.Fatalf("failed to set alias: %v", err)
+	}
+
+	req := httptest.NewRequest(http.MethodPut, "/v2/bar/manifests/other", bytes.NewReader(testManifest(t, r, "other layer")))
+	req.Header.Set("Content-Type", v1.MediaTypeImageManifest)
+	rec := serveTestRequest(newTestRouter(t, r), req)
+	if rec.Code != http.StatusForbidden || !strings.Contains(rec.Body.String(), errCodeDenied) {
+		t.Fatalf("got status %d (%s), want %d %s", rec.Code, rec.Body, http.StatusForbidden, errCodeDenied)
+	}
+	if _, err := r.db.GetManifest("bar", "other"); err == nil {
+		t.Fatal("manifest pushed to the alias was cached")
+	}
+}
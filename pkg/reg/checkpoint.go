@@ -0,0 +1,41 @@
+package reg
+
+import (
+	"context"
+	"log/slog"
+	"time"
+)
+
+// RunWALCheckpoints truncates the write-ahead log every interval until ctx is done. SQLite's
+// automatic checkpoints copy pages back into the database but never shrink the -wal file, and
+// they are skipped altogether while readers keep the log busy, so under a steady stream of
+// writes (bootstrap, caching pulls) the file only grows.
+func (r *Registry) RunWALCheckpoints(ctx context.Context, interval time.Duration) {
+	ticker := time.NewTicker(interval)
+	defer ticker.Stop()
+
+	for {
+		select {
+		case <-ctx.Done():
+			return
+		case <-ticker.C:
+		}
+		r.checkpointWAL(ctx)
+	}
+}
+
+func (r *Registry) checkpointWAL(ctx context.Context) {
+	start := time.Now()
+	result, err := r.db.CheckpointWAL(ctx)
+	if err != nil {
+		slog.ErrorContext(ctx, "error checkpointing the write-ahead log", "error", err)
+		return
+	}
+	if result.Busy {
+		slog.WarnContext(ctx, "write-ahead log checkpoint incomplete, the database was busy",
+			"log_pages", result.LogPages, "checkpointed_pages", result.CheckpointedPages)
+		return
+	}
+	slog.InfoContext(ctx, "Checkpointed write-ahead log", "log_pages", result.LogPages,
+		"checkpointed_pages", result.CheckpointedPages, "duration", time.Since(start))
+}
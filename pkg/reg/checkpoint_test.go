@@ -0,0 +1,45 @@
+package reg
+
+import (
+	"context"
+	"fmt"
+	"os"
+	"testing"
+)
+
+func TestCheckpointWALShrinksLog(t *testing.T) {
+	r := newTestRegistry(t)
+	walPath := r.cfg.DBPath + "-wal"
+
+	for i := range 50 {
+		tags := make([]string, 100)
+		for j := range tags {
+			tags[j] = fmt.Sprintf("v%d.%d", i, j)
+		}
+		if err := r.db.PutTags(fmt.Sprintf("repo%d", i), tags); err != nil {
+			t.Fatalf("failed to put tags: %v", err)
+		}
+	}
+	before, err := os.Stat(walPath)
+	if err != nil {
+		t.Fatalf("failed to stat the write-ahead log: %v", err)
+	}
+	if before.Size() == 0 {
+		t.Fatal("write-ahead log did not grow")
+	}
+
+	result, err := r.db.CheckpointWAL(context.Background())
+	if err != nil {
+		t.Fatalf("failed to checkpoint: %v", err)
+	}
+	if result.Busy {
+		t.Fatal("checkpoint reported a busy database")
+	}
+	after, err := os.Stat(walPath)
+	if err != nil {
+		t.Fatalf("failed to stat the write-ahead log: %v", err)
+	}
+	if after.Size() >= before.Size() {
+		t.Fatalf("write-ahead log did not shrink: %d bytes before, %d after", before.Size(), after.Size())
+	}
+}
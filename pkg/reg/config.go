@@ -0,0 +1,370 @@
+package reg
+
+import (
+	"cmp"
+	"errors"
+	"fmt"
+	"path"
+	"regexp"
+	"slices"
+	"strconv"
+	"strings"
+	"time"
+)
+
+const (
+	StorageS3 = "s3"
+	StorageFS = "fs"
+)
+
+// DefaultStoragePrefix is where the reference registry (distribution) keeps its layout.
+const DefaultStoragePrefix = "docker/registry/v2"
+
+const (
+	LogFormatText = "text"
+	LogFormatJSON = "json"
+)
+
+type Config struct {
+	Listen              string
+	HTTP2               bool
+	Compress            bool
+	TLSCert             string
+	TLSKey              string
+	ClientCA            string
+	LogFormat           string
+	ProgressFormat      string
+	Storage             string
+	Bucket              string
+	BucketRoutes        []string
+	AWSRegion           string
+	AWSProfile          string
+	AWSAccessKeyID      string
+	AWSSecretAccessKey  string
+	StorageRoot         string
+	StoragePrefix       string
+	DBPath              string
+	DBBusyTimeout       time.Duration
+	SQLitePragmas       []string
+	BootstrapWorkers    int
+	AllowMediaTypes     []string
+	MaxManifestBytes    int64
+	MaxUploadBytes      int64
+	S3Timeout           time.Duration
+	MaxS3Concurrency    int
+	RequestTimeout      time.Duration
+	ListingCacheSeconds int
+	ReadOnly            bool
+	DBOnly              bool
+	EnableDelete        bool
+	ProxyFallback       bool
+	InternalBucket      bool
+	VerifyBlobs         bool
+	CORSAllowOrigins    []string
+	AdminToken          string
+	ACLPath             string
+	OTLPEndpoint        string
+
+	ManifestCacheSize     int
+	PresignCacheSize      int
+	PrefetchChildren      bool
+	NoCache               bool
+	CompressCache         bool
+	CacheTTL              time.Duration
+	CacheTTLOverrides     []string
+	RefreshInterval       time.Duration
+	WALCheckpointInterval time.Duration
+}
+
+func (c Config) Validate() error {
+	var errs []error
+	switch c.Storage {
+	case StorageS3:
+		if c.Bucket == "" {
+			errs = append(errs, errors.New("bucket: required for s3 storage"))
+		}
+		if (c.AWSAccessKeyID == "") != (c.AWSSecretAccessKey == "") {
+			errs = append(errs, errors.New("aws-access-key-id, aws-secret-access-key: must be given together"))
+		}
+		if c.AWSProfile != "" && c.AWSAccessKeyID != "" {
+			errs = append(errs, errors.New("aws-profile: conflicts with static credentials from aws-access-key-id and aws-secret-access-key"))
+		}
+		if _, err := ParseBucketRoutes(c.BucketRoutes); err != nil {
+			errs = append(errs, err)
+		}
+	case StorageFS:
+		if c.StorageRoot == "" {
+			errs = append(errs, errors.New("root: required for fs storage"))
+		}
+		if len(c.BucketRoutes) > 0 {
+			errs = append(errs, errors.New("bucket-route: only supported with s3 storage"))
+		}
+	default:
+		errs = append(errs, fmt.Errorf("storage: unknown backend %q, expected s3 or fs", c.Storage))
+	}
+	if err := validateStoragePrefix(c.StoragePrefix); err != nil {
+		errs = append(errs, err)
+	}
+	switch c.LogFormat {
+	case LogFormatText, LogFormatJSON:
+	default:
+		errs = append(errs, fmt.Errorf("log-format: unknown format %q, expected text or json", c.LogFormat))
+	}
+	switch c.ProgressFormat {
+	case ProgressFormatText, ProgressFormatJSON:
+	default:
+		errs = append(errs, fmt.Errorf("progress-format: unknown format %q, expected text or json", c.ProgressFormat))
+	}
+	if c.DBPath == "" {
+		errs = append(errs, errors.New("db: required"))
+	}
+	if c.DBBusyTimeout < 0 {
+		errs = append(errs, errors.New("db-busy-timeout: must not be negative"))
+	}
+	if _, err := ParseSQLitePragmas(c.SQLitePragmas); err != nil {
+		errs = append(errs, err)
+	}
+	if c.MaxManifestBytes < 1 {
+		errs = append(errs, errors.New("max-manifest-bytes: must be positive"))
+	}
+	for _, mediaType := range c.AllowMediaTypes {
+		if !strings.Contains(mediaType, "/") {
+			errs = append(errs, fmt.Errorf("allow-media-type: invalid media type %q", mediaType))
+		}
+	}
+	if c.BootstrapWorkers < 1 {
+		errs = append(errs, errors.New("bootstrap-workers: must be at least 1"))
+	}
+	if c.S3Timeout < 0 {
+		errs = append(errs, errors.New("s3-timeout: must not be negative"))
+	}
+	if c.MaxS3Concurrency < 0 {
+		errs = append(errs, errors.New("max-s3-concurrency: must not be negative"))
+	}
+	if c.RequestTimeout < 0 {
+		errs = append(errs, errors.New("request-timeout: must not be negative"))
+	}
+	if c.ManifestCacheSize < 0 {
+		errs = append(errs, errors.New("manifest-cache-size: must not be negative"))
+	}
+	if c.PresignCacheSize < 0 {
+		errs = append(errs, errors.New("presign-cache-size: must not be negative"))
+	}
+	return errors.Join(errs...)
+}
+
+// validateStoragePrefix accepts an empty prefix, which stands for the default one.
+func validateStoragePrefix(prefix string) error {
+	trimmed := strings.Trim(prefix, "/")
+	if trimmed == "" {
+		return nil
+	}
+	for _, segment := range strings.Split(trimmed, "/") {
+		if segment == "" || segment == "." || segment == ".." {
+			return fmt.Errorf("storage-prefix: invalid prefix %q", prefix)
+		}
+	}
+	return nil
+}
+
+// storagePrefix returns the storage prefix without leading and trailing slashes,
+// the default one if unset.
+func (c Config) storagePrefix() string {
+	if prefix := strings.Trim(c.StoragePrefix, "/"); prefix != "" {
+		return prefix
+	}
+	return DefaultStoragePrefix
+}
+
+// ValidateServe additionally checks the options only used when serving the API.
+func (c Config) ValidateServe() error {
+	err := c.Validate()
+	if c.Listen == "" {
+		err = errors.Join(err, errors.New("listen: required"))
+	}
+	if (c.TLSCert == "") != (c.TLSKey == "") {
+		err = errors.Join(err, errors.New("tls-cert, tls-key: must be given together"))
+	}
+	if c.ClientCA != "" && c.TLSCert == "" {
+		err = errors.Join(err, errors.New("client-ca: requires tls-cert and tls-key"))
+	}
+	if c.RefreshInterval < 0 {
+		err = errors.Join(err, errors.New("refresh-interval: must not be negative"))
+	}
+	if c.WALCheckpointInterval < 0 {
+		err = errors.Join(err, errors.New("wal-checkpoint-interval: must not be negative"))
+	}
+	if c.MaxUploadBytes < 0 {
+		err = errors.Join(err, errors.New("max-upload-bytes: must not be negative"))
+	}
+	if c.CacheTTL < 0 {
+		err = errors.Join(err, errors.New("cache-ttl: must not be negative"))
+	}
+	ttlOverrides, parseErr := ParseCacheTTLOverrides(c.CacheTTLOverrides)
+	if parseErr != nil {
+		err = errors.Join(err, parseErr)
+	}
+	if c.ListingCacheSeconds < 0 {
+		err = errors.Join(err, errors.New("listing-cache-seconds: must not be negative"))
+	}
+	if c.PrefetchChildren && c.NoCache {
+		err = errors.Join(err, errors.New("prefetch-children: conflicts with no-cache, which bypasses the database"))
+	}
+	if c.RefreshInterval > 0 && c.NoCache {
+		err = errors.Join(err, errors.New("refresh-interval: nothing to refresh with no-cache"))
+	}
+	if c.ReadOnly && c.EnableDelete {
+		err = errors.Join(err, errors.New("read-only: conflicts with enable-delete"))
+	}
+	if c.ReadOnly && c.RefreshInterval > 0 {
+		err = errors.Join(err, errors.New("read-only: conflicts with refresh-interval, which writes to the database"))
+	}
+	if c.ReadOnly && c.WALCheckpointInterval > 0 {
+		err = errors.Join(err, errors.New("read-only: conflicts with wal-checkpoint-interval, which writes to the database"))
+	}
+	if c.DBOnly && !c.ReadOnly {
+		err = errors.Join(err, errors.New("db-only: requires read-only, as pushes and deletes write to storage"))
+	}
+	if c.DBOnly && c.NoCache {
+		err = errors.Join(err, errors.New("db-only: conflicts with no-cache, which bypasses the database"))
+	}
+	if c.DBOnly && c.CacheTTL > 0 {
+		err = errors.Join(err, errors.New("db-only: conflicts with cache-ttl, tags can't be resolved from storage again"))
+	}
+	if c.DBOnly && slices.ContainsFunc(ttlOverrides, func(override CacheTTLOverride) bool { return override.TTL == 0 }) {
+		err = errors.Join(err, errors.New("db-only: conflicts with cache-ttl-override of 0, which bypasses the database"))
+	}
+	if c.OTLPEndpoint != "" {
+		if _, otlpErr := otlpTracesURL(c.OTLPEndpoint); otlpErr != nil {
+			err = errors.Join(err, fmt.Errorf("otlp-endpoint: %w", otlpErr))
+		}
+	}
+	return err
+}
+
+type BucketRoute struct {
+	Prefix string
+	Bucket string
+}
+
+// ParseBucketRoutes parses "prefix=bucket" routes, ordered so that the longest prefix wins.
+// Repositories not matching any prefix stay in the default bucket.
+func ParseBucketRoutes(routes []string) ([]BucketRoute, error) {
+	var parsed []BucketRoute
+	var errs []error
+	seen := make(map[string]bool)
+	for _, route := range routes {
+		prefix, bucket, ok := strings.Cut(route, "=")
+		if !ok || prefix == "" || bucket == "" {
+			errs = append(errs, fmt.Errorf("bucket-route: invalid route %q, expected prefix=bucket", route))
+			continue
+		}
+		if seen[prefix] {
+			errs = append(errs, fmt.Errorf("bucket-route: duplicate prefix %q", prefix))
+			continue
+		}
+		seen[prefix] = true
+		parsed = append(parsed, BucketRoute{Prefix: prefix, Bucket: bucket})
+	}
+	slices.SortStableFunc(parsed, func(a, b BucketRoute) int {
+		return cmp.Compare(len(b.Prefix), len(a.Prefix))
+	})
+	return parsed, errors.Join(errs...)
+}
+
+type CacheTTLOverride struct {
+	Pattern string
+	TTL     time.Duration
+}
+
+// ParseCacheTTLOverrides parses "glob=seconds" overrides of --cache-ttl, kept in the given order
+// as the first matching pattern wins. 0 seconds means never serving the repositories from the cache.
+func ParseCacheTTLOverrides(overrides []string) ([]CacheTTLOverride, error) {
+	var parsed []CacheTTLOverride
+	var errs []error
+	for _, override := range overrides {
+		pattern, seconds, ok := strings.Cut(override, "=")
+		if !ok || pattern == "" || seconds == "" {
+			errs = append(errs, fmt.Errorf("cache-ttl-override: invalid override %q, expected glob=seconds", override))
+			continue
+		}
+		if _, err := path.Match(pattern, ""); err != nil {
+			errs = append(errs, fmt.Errorf("cache-ttl-override: invalid pattern %q: %w", pattern, err))
+			continue
+		}
+		ttl, err := strconv.ParseInt(seconds, 10, 64)
+		if err != nil || ttl < 0 {
+			errs = append(errs, fmt.Errorf("cache-ttl-override: invalid number of seconds %q for %s", seconds, pattern))
+			continue
+		}
+		parsed = append(parsed, CacheTTLOverride{Pattern: pattern, TTL: time.Duration(min(ttl, maxCacheTTLSeconds)) * time.Second})
+	}
+	return parsed, errors.Join(errs...)
+}
+
+// maxCacheTTLSeconds keeps "effectively permanent" TTLs from overflowing a time.Duration.
+const maxCacheTTLSeconds = 100 * 365 * 24 * 60 * 60
+
+// sqlitePragmas lists the pragmas which can be tuned with --sqlite-pragma along with their defaults,
+// empty when SQLite's own default is kept. Anything else (journal_mode, foreign_keys, user_version...)
+// could break the database or the migrations, so it is rejected.
+var sqlitePragmas = map[string]string{
+	"synchronous":        "NORMAL",
+	"cache_size":         "",
+	"mmap_size":          "",
+	"temp_store":         "",
+	"wal_autocheckpoint": "",
+	"journal_size_limit": "",
+}
+
+var sqlitePragmaValuePattern = regexp.MustCompile(`^(-?[0-9]+|[A-Za-z]+)$`)
+
+type SQLitePragma struct {
+	Name  string
+	Value string
+}
+
+// ParseSQLitePragmas parses "name=value" pragmas and merges them into the defaults,
+// returning them sorted by name.
+func ParseSQLitePragmas(pragmas []string) ([]SQLitePragma, error) {
+	values := make(map[string]string, len(sqlitePragmas))
+	for name, value := range sqlitePragmas {
+		if value != "" {
+			values[name] = value
+		}
+	}
+	var errs []error
+	for _, pragma := range pragmas {
+		name, value, ok := strings.Cut(pragma, "=")
+		name = strings.ToLower(strings.TrimSpace(name))
+		value = strings.TrimSpace(value)
+		if !ok || name == "" || value == "" {
+			errs = append(errs, fmt.Errorf("sqlite-pragma: invalid pragma %q, expected name=value", pragma))
+			continue
+		}
+		if name == "busy_timeout" {
+			errs = append(errs, errors.New("sqlite-pragma: busy_timeout is set with db-busy-timeout"))
+			continue
+		}
+		if _, ok := sqlitePragmas[name]; !ok {
+			errs = append(errs, fmt.Errorf("sqlite-pragma: unsupported pragma %q", name))
+			continue
+		}
+		// NOTICE: pragmas can't take bound parameters, so values are restricted to plain
+		// numbers and keywords before being spliced into the statement
+		if !sqlitePragmaValuePattern.MatchString(value) {
+			errs = append(errs, fmt.Errorf("sqlite-pragma: invalid value %q for %s, expected a number or keyword", value, name))
+			continue
+		}
+		values[name] = value
+	}
+
+	parsed := make([]SQLitePragma, 0, len(values))
+	for name, value := range values {
+		parsed = append(parsed, SQLitePragma{Name: name, Value: value})
+	}
+	slices.SortFunc(parsed, func(a, b SQLitePragma) int {
+		return cmp.Compare(a.Name, b.Name)
+	})
+	return parsed, errors.Join(errs...)
+}
@@ -0,0 +1,82 @@
+package reg
+
+import (
+	"reflect"
+	"testing"
+)
+
+func TestParseBucketRoutes(t *testing.T) {
+	routes, err := ParseBucketRoutes([]string{"team/=bucket-a", "team/app/=bucket-b", "t=bucket-c"})
+	if err != nil {
+		t.Fatalf("failed to parse routes: %v", err)
+	}
+	want := []BucketRoute{
+		{Prefix: "team/app/", Bucket: "bucket-b"},
+		{Prefix: "team/", Bucket: "bucket-a"},
+		{Prefix: "t", Bucket: "bucket-c"},
+	}
+	if !reflect.DeepEqual(routes, want) {
+		t.Fatalf("got routes %v, want %v", routes, want)
+	}
+
+	for _, routes := range [][]string{
+		{"team/"},
+		{"=bucket"},
+		{"team/="},
+		{"team/=bucket-a", "team/=bucket-b"},
+	} {
+		if _, err := ParseBucketRoutes(routes); err == nil {
+			t.Errorf("parsed invalid routes %q", routes)
+		}
+	}
+}
+
+func TestParseSQLitePragmas(t *testing.T) {
+	pragmas, err := ParseSQLitePragmas([]string{"cache_size=-4000", " Temp_Store = MEMORY "})
+	if err != nil {
+		t.Fatalf("failed to parse pragmas: %v", err)
+	}
+	want := []SQLitePragma{
+		{Name: "cache_size", Value: "-4000"},
+		{Name: "synchronous", Value: "NORMAL"},
+		{Name: "temp_store", Value: "MEMORY"},
+	}
+	if !reflect.DeepEqual(pragmas, want) {
+		t.Fatalf("got pragmas %v, want %v", pragmas, want)
+	}
+
+	for _, pragma := range []string{
+		"cache_size",
+		"cache_size=",
+		"busy_timeout=1000",
+		"journal_mode=DELETE",
+		"user_version=0",
+		"cache_size=1; DROP TABLE tags",
+		"temp_store='MEMORY'",
+	} {
+		if _, err := ParseSQLitePragmas([]string{pragma}); err == nil {
+			t.Errorf("parsed invalid pragma %q", pragma)
+		}
+	}
+}
+
+func TestStoragePrefix(t *testing.T) {
+	for prefix, want := range map[string]string{
+		"":                DefaultStoragePrefix,
+		"/":               DefaultStoragePrefix,
+		"registry":        "registry",
+		"/registry/prod/": "registry/prod",
+	} {
+		if err := validateStoragePrefix(prefix); err != nil {
+			t.Errorf("rejected prefix %q: %v", prefix, err)
+		}
+		if got := (Config{StoragePrefix: prefix}).storagePrefix(); got != want {
+			t.Errorf("got prefix %q for %q, want %q", got, prefix, want)
+		}
+	}
+	for _, prefix := range []string{"a//b", "a/../b", "./a", "/.."} {
+		if err := validateStoragePrefix(prefix); err == nil {
+			t.Errorf("accepted prefix %q", prefix)
+		}
+	}
+}
@@ -1,29 +1,205 @@
 package reg
 
 import (
+	"bytes"
+	"compress/gzip"
+	"context"
 	"database/sql"
+	"database/sql/driver"
+	"encoding/json"
+	"errors"
 	"fmt"
+	"io"
+	"io/fs"
 	"log/slog"
+	"slices"
+	"strings"
+	"time"
 
 	"github.com/jmoiron/sqlx"
-	_ "github.com/mattn/go-sqlite3"
+	"github.com/mattn/go-sqlite3"
+	"github.com/opencontainers/go-digest"
 	v1 "github.com/opencontainers/image-spec/specs-go/v1"
 )
 
+var ErrDatabaseBusy = errors.New("database is busy")
+
 type RegistryDB struct {
 	db *sqlx.DB
+	// compressManifests gzips the manifests stored from then on, rows already stored are left as they are
+	compressManifests bool
+}
+
+// storedManifest is a manifest as stored in the manifest_json column: the manifest itself,
+// or its gzipped bytes when compressed is set.
+type storedManifest struct {
+	ManifestJSON []byte `db:"manifest_json"`
+	Compressed   bool   `db:"compressed"`
 }
 
-func initSQLite(path string) (*RegistryDB, error) {
-	db, err := sqlx.Open("sqlite3", path)
+// decode returns the exact bytes the manifest was stored with, which its digest is the hash of.
+func (m storedManifest) decode() (string, error) {
+	if !m.Compressed {
+		return string(m.ManifestJSON), nil
+	}
+	reader, err := gzip.NewReader(bytes.NewReader(m.ManifestJSON))
 	if err != nil {
-		return nil, fmt.Errorf("failed to open database: %w", err)
+		return "", fmt.Errorf("failed to decompress manifest: %w", err)
 	}
-	_, err = db.Exec("PRAGMA journal_mode=WAL;")
+	decompressed, err := io.ReadAll(reader)
+	if err != nil {
+		return "", fmt.Errorf("failed to decompress manifest: %w", err)
+	}
+	return string(decompressed), nil
+}
+
+// encodeManifest returns the value to store in the manifest_json column and whether it is compressed.
+func (r *RegistryDB) encodeManifest(manifestBytes string) (any, bool, error) {
+	if !r.compressManifests {
+		return manifestBytes, false, nil
+	}
+	var buf bytes.Buffer
+	writer := gzip.NewWriter(&buf)
+	if _, err := writer.Write([]byte(manifestBytes)); err != nil {
+		return nil, false, fmt.Errorf("failed to compress manifest: %w", err)
+	}
+	if err := writer.Close(); err != nil {
+		return nil, false, fmt.Errorf("failed to compress manifest: %w", err)
+	}
+	// NOTICE: stored as a BLOB, which the TEXT affinity of the column leaves alone
+	return buf.Bytes(), true, nil
+}
+
+// decodeManifests decodes the manifests returned by a query, in order.
+func decodeManifests(stored []storedManifest) ([]string, error) {
+	manifestJSONs := make([]string, 0, len(stored))
+	for _, m := range stored {
+		manifestJSON, err := m.decode()
+		if err != nil {
+			return nil, err
+		}
+		manifestJSONs = append(manifestJSONs, manifestJSON)
+	}
+	return manifestJSONs, nil
+}
+
+// dbWriteError marks SQLITE_BUSY/SQLITE_LOCKED failures with ErrDatabaseBusy, so that
+// callers can tell lock contention (tunable with the busy timeout) apart from real failures.
+func dbWriteError(err error) error {
+	var sqliteErr sqlite3.Error
+	if errors.As(err, &sqliteErr) && (sqliteErr.Code == sqlite3.ErrBusy || sqliteErr.Code == sqlite3.ErrLocked) {
+		return fmt.Errorf("%w: %w", ErrDatabaseBusy, err)
+	}
+	return err
+}
+
+// sqliteConnector opens connections with a driver running the configured pragmas on each of them.
+type sqliteConnector struct {
+	driver *sqlite3.SQLiteDriver
+	dsn    string
+}
+
+func (c sqliteConnector) Connect(_ context.Context) (driver.Conn, error) {
+	return c.driver.Open(c.dsn)
+}
+
+func (c sqliteConnector) Driver() driver.Driver {
+	return c.driver
+}
+
+// sqliteMaxConns bounds the pool of database connections, all of which are kept open between
+// requests. Writers are serialized by SQLite anyway, this mostly bounds concurrent readers.
+const sqliteMaxConns = 32
+
+func initSQLite(path string, busyTimeout time.Duration, pragmas []SQLitePragma) (*RegistryDB, error) {
+	// NOTICE: busy_timeout is a per-connection setting, so it has to be passed in the DSN
+	// rather than executed once - otherwise only one connection from the pool would get it.
+	// Immediate transactions take the write lock upfront, which lets busy_timeout kick in
+	// instead of failing on a read->write lock upgrade.
+	dsn := fmt.Sprintf("file:%s?_busy_timeout=%d&_txlock=immediate", path, busyTimeout.Milliseconds())
+	// NOTICE: the tunable pragmas are per-connection as well, hence the connect hook
+	connector := sqliteConnector{
+		driver: &sqlite3.SQLiteDriver{
+			ConnectHook: func(conn *sqlite3.SQLiteConn) error {
+				for _, pragma := range pragmas {
+					if _, err := conn.Exec(fmt.Sprintf("PRAGMA %s=%s;", pragma.Name, pragma.Value), nil); err != nil {
+						return fmt.Errorf("failed to set pragma %s: %w", pragma.Name, err)
+					}
+				}
+				return nil
+			},
+		},
+		dsn: dsn,
+	}
+	db := sqlx.NewDb(sql.OpenDB(connector), "sqlite3")
+	// NOTICE: database/sql keeps only 2 idle connections by default, so under concurrent requests
+	// connections were closed and reopened (running every pragma again) all the time
+	db.SetMaxOpenConns(sqliteMaxConns)
+	db.SetMaxIdleConns(sqliteMaxConns)
+	_, err := db.Exec("PRAGMA journal_mode=WAL;")
 	if err != nil {
 		return nil, fmt.Errorf("failed to set journal mode: %w", err)
 	}
 
+	if err := migrate(db); err != nil {
+		return nil, err
+	}
+
+	return &RegistryDB{db: db}, nil
+}
+
+// migrations bring the schema from version i to i+1, the current version being kept in PRAGMA user_version.
+// NOTICE: append only - databases in the wild have already run the existing steps.
+var migrations = []func(tx *sqlx.Tx) error{
+	migrateBaseSchema,
+	migrateManifestDigests,
+	migrateDigestManifests,
+	migrateRepositoryAliases,
+	migrateReferrers,
+	migrateManifestCompression,
+	migrateManifestCheckedAt,
+	migrateUploadHashState,
+}
+
+func migrate(db *sqlx.DB) error {
+	tx, err := db.Beginx()
+	if err != nil {
+		return fmt.Errorf("failed to start migration: %w", err)
+	}
+	defer func() {
+		if err != nil {
+			_ = tx.Rollback()
+		}
+	}()
+
+	// NOTICE: the version is read inside the (immediate) transaction, so that two processes
+	// opening the same database don't both run the same steps.
+	var version int
+	if err = tx.Get(&version, "PRAGMA user_version"); err != nil {
+		return fmt.Errorf("failed to read schema version: %w", err)
+	}
+	if version > len(migrations) {
+		err = fmt.Errorf("database schema version %d is newer than the supported %d", version, len(migrations))
+		return err
+	}
+	for ; version < len(migrations); version++ {
+		slog.Info("Migrating database schema", "from", version, "to", version+1)
+		if err = migrations[version](tx); err != nil {
+			return fmt.Errorf("failed to migrate schema to version %d: %w", version+1, err)
+		}
+	}
+	if _, err = tx.Exec(fmt.Sprintf("PRAGMA user_version = %d", version)); err != nil {
+		return fmt.Errorf("failed to set schema version: %w", err)
+	}
+	if err = tx.Commit(); err != nil {
+		return fmt.Errorf("failed to commit migration: %w", err)
+	}
+	return nil
+}
+
+// migrateBaseSchema creates the schema as it was before versioning was introduced. Databases
+// created back then start at version 0 too, so every step has to tolerate existing objects.
+func migrateBaseSchema(tx *sqlx.Tx) error {
 	tables := []string{
 		`CREATE TABLE IF NOT EXISTS tags (
 			repository TEXT NOT NULL,
@@ -33,6 +209,8 @@ func initSQLite(path string) (*RegistryDB, error) {
 		`CREATE TABLE IF NOT EXISTS manifests (
 			tag_rowid INTEGER NOT NULL,
 			manifest_json TEXT NOT NULL,
+			created_at DATETIME,
+			media_type TEXT,
 			PRIMARY KEY(tag_rowid)
 		);`,
 		`CREATE TABLE IF NOT EXISTS manifest_layers (
@@ -55,28 +233,166 @@ func initSQLite(path string) (*RegistryDB, error) {
 			created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
 			last_activity DATETIME DEFAULT CURRENT_TIMESTAMP,
 			total_size INTEGER,
-			uploaded_size INTEGER DEFAULT 0
+			uploaded_size INTEGER DEFAULT 0,
+			part_count INTEGER NOT NULL DEFAULT 0
 		);`,
 	}
 
 	for _, table := range tables {
 		slog.Debug("Creating table", "table", table)
-		_, err = db.Exec(table)
-		if err != nil {
-			return nil, fmt.Errorf("failed to create table: %w", err)
+		if _, err := tx.Exec(table); err != nil {
+			return fmt.Errorf("failed to create table: %w", err)
 		}
 	}
 
-	return &RegistryDB{db: db}, nil
+	// NOTICE: CREATE TABLE IF NOT EXISTS leaves tables from older versions alone,
+	// so columns added since then need to be added explicitly.
+	if err := addColumnIfMissing(tx, "manifests", "created_at", "DATETIME"); err != nil {
+		return err
+	}
+	if err := addColumnIfMissing(tx, "manifests", "media_type", "TEXT"); err != nil {
+		return err
+	}
+	if err := addColumnIfMissing(tx, "upload_sessions", "part_count", "INTEGER NOT NULL DEFAULT 0"); err != nil {
+		return err
+	}
+
+	// NOTICE: (repository, name) lookups on tags are already served by the primary key's
+	// implicit unique index, and manifests.tag_rowid is an alias for the rowid.
+	indexes := []string{
+		`CREATE INDEX IF NOT EXISTS manifest_layers_layer_digest ON manifest_layers (layer_digest);`,
+		`CREATE INDEX IF NOT EXISTS upload_sessions_last_activity ON upload_sessions (last_activity);`,
+	}
+
+	for _, index := range indexes {
+		slog.Debug("Creating index", "index", index)
+		if _, err := tx.Exec(index); err != nil {
+			return fmt.Errorf("failed to create index: %w", err)
+		}
+	}
+
+	return nil
+}
+
+// migrateManifestDigests adds the digest of each stored manifest, so that manifests cached
+// under a tag can be served when pulled by digest too.
+func migrateManifestDigests(tx *sqlx.Tx) error {
+	if err := addColumnIfMissing(tx, "manifests", "digest", "TEXT"); err != nil {
+		return err
+	}
+
+	var rows []struct {
+		RowID        int64  `db:"rowid"`
+		ManifestJSON string `db:"manifest_json"`
+	}
+	if err := tx.Select(&rows, `SELECT rowid, manifest_json FROM manifests WHERE digest IS NULL`); err != nil {
+		return fmt.Errorf("failed to list manifests: %w", err)
+	}
+	for _, row := range rows {
+		if _, err := tx.Exec(`UPDATE manifests SET digest = ? WHERE rowid = ?`, digest.FromString(row.ManifestJSON).String(), row.RowID); err != nil {
+			return fmt.Errorf("failed to backfill manifest digest: %w", err)
+		}
+	}
+
+	if _, err := tx.Exec(`CREATE INDEX IF NOT EXISTS manifests_digest ON manifests (digest);`); err != nil {
+		return fmt.Errorf("failed to create index: %w", err)
+	}
+	return nil
+}
+
+// migrateDigestManifests adds a table for manifests known only by digest, such as the
+// per-platform children of an index, which have no tag to be stored under in manifests.
+func migrateDigestManifests(tx *sqlx.Tx) error {
+	_, err := tx.Exec(`CREATE TABLE IF NOT EXISTS digest_manifests (
+		repository TEXT NOT NULL,
+		digest TEXT NOT NULL,
+		manifest_json TEXT NOT NULL,
+		media_type TEXT,
+		created_at DATETIME,
+		PRIMARY KEY(repository, digest)
+	);`)
+	if err != nil {
+		return fmt.Errorf("failed to create table: %w", err)
+	}
+	return nil
+}
+
+// migrateRepositoryAliases adds aliases, alternative names a repository can be pulled with.
+func migrateRepositoryAliases(tx *sqlx.Tx) error {
+	_, err := tx.Exec(`CREATE TABLE IF NOT EXISTS repo_aliases (
+		alias TEXT PRIMARY KEY,
+		repository TEXT NOT NULL
+	);`)
+	if err != nil {
+		return fmt.Errorf("failed to create table: %w", err)
+	}
+	return nil
+}
+
+// migrateReferrers adds the referrers index: manifests pointing at another one with their
+// subject field (signatures, SBOMs...), keyed by the digest of the subject.
+func migrateReferrers(tx *sqlx.Tx) error {
+	_, err := tx.Exec(`CREATE TABLE IF NOT EXISTS referrers (
+		repository TEXT NOT NULL,
+		subject TEXT NOT NULL,
+		digest TEXT NOT NULL,
+		media_type TEXT NOT NULL,
+		artifact_type TEXT,
+		size INTEGER NOT NULL,
+		annotations TEXT,
+		PRIMARY KEY(repository, subject, digest)
+	);`)
+	if err != nil {
+		return fmt.Errorf("failed to create table: %w", err)
+	}
+	return nil
+}
+
+// migrateManifestCompression flags the manifests stored compressed with --compress-cache.
+// Existing rows are all uncompressed and stay so until they are stored again.
+func migrateManifestCompression(tx *sqlx.Tx) error {
+	for _, table := range []string{"manifests", "digest_manifests"} {
+		if err := addColumnIfMissing(tx, table, "compressed", "INTEGER NOT NULL DEFAULT 0"); err != nil {
+			return err
+		}
+	}
+	return nil
+}
+
+// migrateManifestCheckedAt adds when each tag was last resolved from storage, for --cache-ttl.
+// Existing rows fall back to created_at.
+func migrateManifestCheckedAt(tx *sqlx.Tx) error {
+	return addColumnIfMissing(tx, "manifests", "checked_at", "DATETIME")
+}
+
+// migrateUploadHashState keeps the sha256 state of each upload, so that completing it doesn't
+// read the blob back. Sessions in progress have none and are still verified that way.
+func migrateUploadHashState(tx *sqlx.Tx) error {
+	return addColumnIfMissing(tx, "upload_sessions", "hash_state", "BLOB")
+}
+
+func addColumnIfMissing(tx *sqlx.Tx, table string, column string, definition string) error {
+	var columns []string
+	if err := tx.Select(&columns, `SELECT name FROM pragma_table_info(?)`, table); err != nil {
+		return fmt.Errorf("failed to inspect table %s: %w", table, err)
+	}
+	if slices.Contains(columns, column) {
+		return nil
+	}
+	slog.Debug("Adding column", "table", table, "column", column)
+	if _, err := tx.Exec(fmt.Sprintf("ALTER TABLE %s ADD COLUMN %s %s", table, column, definition)); err != nil {
+		return fmt.Errorf("failed to add column %s.%s: %w", table, column, err)
+	}
+	return nil
 }
 
 func (r *RegistryDB) GetManifest(repo string, tag string) (string, error) {
-	query := `SELECT manifest_json FROM manifests 
+	query := `SELECT manifest_json, compressed FROM manifests 
 		JOIN tags ON tags.rowid = manifests.tag_rowid
 		WHERE tags.repository = ? AND tags.name = ?`
 
-	var manifestJSON string
-	err := r.db.Get(&manifestJSON, query, repo, tag)
+	var stored storedManifest
+	err := r.db.Get(&stored, query, repo, tag)
 
 	slog.Debug("Retrieved manifest", "repo", repo, "tag", tag)
 	if err != nil {
@@ -86,13 +402,90 @@ func (r *RegistryDB) GetManifest(repo string, tag string) (string, error) {
 		return "", fmt.Errorf("failed to get manifest: %w", err)
 	}
 
-	return manifestJSON, nil
+	return stored.decode()
+}
+
+// GetManifestCheckedAt returns the manifest cached for a tag along with when it was last resolved
+// from storage, the zero time if that is unknown.
+func (r *RegistryDB) GetManifestCheckedAt(repo string, tag string) (string, time.Time, error) {
+	query := `SELECT manifest_json, compressed, checked_at, created_at FROM manifests
+		JOIN tags ON tags.rowid = manifests.tag_rowid
+		WHERE tags.repository = ? AND tags.name = ?`
+
+	var row struct {
+		storedManifest
+		CheckedAt sql.NullTime `db:"checked_at"`
+		CreatedAt sql.NullTime `db:"created_at"`
+	}
+	if err := r.db.Get(&row, query, repo, tag); err != nil {
+		if err == sql.ErrNoRows {
+			return "", time.Time{}, fmt.Errorf("manifest not found for repository %s and tag %s", repo, tag)
+		}
+		return "", time.Time{}, fmt.Errorf("failed to get manifest: %w", err)
+	}
+	manifestJSON, err := row.decode()
+	if err != nil {
+		return "", time.Time{}, err
+	}
+	checkedAt := row.CheckedAt.Time
+	if !row.CheckedAt.Valid {
+		checkedAt = row.CreatedAt.Time
+	}
+	return manifestJSON, checkedAt, nil
+}
+
+// GetManifestByDigest returns a manifest of a repository cached under any of its tags.
+func (r *RegistryDB) GetManifestByDigest(repo string, dgst digest.Digest) (string, error) {
+	query := `SELECT manifest_json, compressed FROM manifests
+		JOIN tags ON tags.rowid = manifests.tag_rowid
+		WHERE tags.repository = ? AND manifests.digest = ?
+		UNION ALL
+		SELECT manifest_json, compressed FROM digest_manifests
+		WHERE repository = ? AND digest = ?
+		LIMIT 1`
+
+	var stored storedManifest
+	err := r.db.Get(&stored, query, repo, dgst.String(), repo, dgst.String())
+	if err != nil {
+		if err == sql.ErrNoRows {
+			return "", fmt.Errorf("manifest not found for repository %s and digest %s", repo, dgst)
+		}
+		return "", fmt.Errorf("failed to get manifest: %w", err)
+	}
+
+	return stored.decode()
+}
+
+// PutDigestManifest stores a manifest which is not (necessarily) tagged, to be served by digest.
+// Manifests are immutable under their digest, so storing one again is a no-op.
+func (r *RegistryDB) PutDigestManifest(repo string, manifestBytes string, manifest *v1.Manifest) error {
+	encoded, compressed, err := r.encodeManifest(manifestBytes)
+	if err != nil {
+		return err
+	}
+	query := `INSERT INTO digest_manifests (repository, digest, manifest_json, compressed, media_type, created_at)
+		VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+		ON CONFLICT(repository, digest) DO NOTHING`
+	_, err = r.db.Exec(query, repo, digest.FromString(manifestBytes).String(), encoded, compressed, manifestMediaType([]byte(manifestBytes), manifest))
+	if err != nil {
+		return fmt.Errorf("failed to store manifest: %w", dbWriteError(err))
+	}
+	return nil
 }
 
 func (r *RegistryDB) PutManifest(repo string, tag string, manifestBytes string, manifest *v1.Manifest) error {
+	layerDigests, err := manifestLayerDigests([]byte(manifestBytes), manifest)
+	if err != nil {
+		return err
+	}
+	encoded, compressed, err := r.encodeManifest(manifestBytes)
+	if err != nil {
+		return err
+	}
+
 	tx, err := r.db.Beginx()
 	if err != nil {
-		return fmt.Errorf("failed to start transaction: %w", err)
+		return fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
 	}
 	defer func() {
 		if err != nil {
@@ -103,21 +496,31 @@ func (r *RegistryDB) PutManifest(repo string, tag string, manifestBytes string,
 	query := `INSERT INTO tags (repository, name) VALUES (?, ?) ON CONFLICT(repository, name) DO NOTHING`
 	_, err = tx.Exec(query, repo, tag)
 	if err != nil {
-		return fmt.Errorf("failed to register tag: %w", err)
+		return fmt.Errorf("failed to register tag: %w", dbWriteError(err))
 	}
 
 	var tagRowID int64
 	query = `SELECT rowid FROM tags WHERE repository = ? AND name = ?`
 	err = tx.Get(&tagRowID, query, repo, tag)
 	if err != nil {
-		return fmt.Errorf("failed to get tag rowid: %w", err)
+		return fmt.Errorf("failed to get tag rowid: %w", dbWriteError(err))
 	}
 
-	query = `INSERT INTO manifests (tag_rowid, manifest_json) VALUES (?, ?) 
-		ON CONFLICT(tag_rowid) DO UPDATE SET manifest_json = ?`
-	_, err = tx.Exec(query, tagRowID, manifestBytes, manifestBytes)
+	// NOTICE: re-storing the same manifest (e.g. on a cache refill) keeps its original created_at
+	// and compares digests, as the stored bytes differ once --compress-cache is toggled
+	query = `INSERT INTO manifests (tag_rowid, manifest_json, compressed, media_type, digest, created_at, checked_at)
+		VALUES (?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP) 
+		ON CONFLICT(tag_rowid) DO UPDATE SET
+			created_at = CASE WHEN digest = excluded.digest THEN created_at ELSE CURRENT_TIMESTAMP END,
+			checked_at = excluded.checked_at,
+			manifest_json = excluded.manifest_json,
+			compressed = excluded.compressed,
+			media_type = excluded.media_type,
+			digest = excluded.digest`
+	// NOTICE: a manifest doesn't carry its own digest, it is the hash of the exact stored bytes
+	_, err = tx.Exec(query, tagRowID, encoded, compressed, manifestMediaType([]byte(manifestBytes), manifest), digest.FromString(manifestBytes).String())
 	if err != nil {
-		return fmt.Errorf("failed to store manifest: %w", err)
+		return fmt.Errorf("failed to store manifest: %w", dbWriteError(err))
 	}
 
 	query = `INSERT INTO layers (digest, media_type, size) VALUES (?, ?, ?) 
@@ -125,45 +528,269 @@ func (r *RegistryDB) PutManifest(repo string, tag string, manifestBytes string,
 	for _, layer := range manifest.Layers {
 		_, err = tx.Exec(query, layer.Digest.String(), layer.MediaType, layer.Size, layer.MediaType, layer.Size)
 		if err != nil {
-			return fmt.Errorf("failed to store layer: %w", err)
+			return fmt.Errorf("failed to store layer: %w", dbWriteError(err))
 		}
 	}
 
 	purgeLayersQuery := `DELETE FROM manifest_layers WHERE manifest_rowid = (SELECT rowid FROM manifests WHERE tag_rowid = ?)`
 	_, err = tx.Exec(purgeLayersQuery, tagRowID)
 	if err != nil {
-		return fmt.Errorf("failed to delete existing manifest layers: %w", err)
+		return fmt.Errorf("failed to delete existing manifest layers: %w", dbWriteError(err))
 	}
 
 	var manifestRowID int64
 	query = `SELECT rowid FROM manifests WHERE tag_rowid = ?`
 	err = tx.Get(&manifestRowID, query, tagRowID)
 	if err != nil {
-		return fmt.Errorf("failed to get manifest rowid: %w", err)
+		return fmt.Errorf("failed to get manifest rowid: %w", dbWriteError(err))
 	}
 
-	for i, layer := range manifest.Layers {
+	for i, layerDigest := range layerDigests {
 		_, err = tx.Exec(
 			`INSERT INTO manifest_layers (manifest_rowid, layer_digest, layer_index) VALUES (?, ?, ?)`,
 			manifestRowID,
-			layer.Digest.String(),
+			layerDigest,
 			i,
 		)
 		if err != nil {
-			return fmt.Errorf("failed to store manifest layer: %w", err)
+			return fmt.Errorf("failed to store manifest layer: %w", dbWriteError(err))
 		}
 	}
 
 	if err = tx.Commit(); err != nil {
-		return fmt.Errorf("failed to commit transaction: %w", err)
+		return fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
 	}
 
 	return nil
 }
 
+func (r *RegistryDB) DeleteManifest(repo string, dgst digest.Digest) (int64, error) {
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return 0, fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
+	}
+	defer func() {
+		if err != nil {
+			_ = tx.Rollback()
+		}
+	}()
+
+	var tagRowIDs []int64
+	query := `SELECT manifests.tag_rowid FROM manifests
+		JOIN tags ON tags.rowid = manifests.tag_rowid
+		WHERE tags.repository = ? AND manifests.digest = ?`
+	err = tx.Select(&tagRowIDs, query, repo, dgst.String())
+	if err != nil {
+		return 0, fmt.Errorf("failed to list manifests: %w", dbWriteError(err))
+	}
+
+	// NOTICE: manifests are keyed by tag, so every tag pointing at the digest goes away with it
+	var deleted int64
+	for _, tagRowID := range tagRowIDs {
+		_, err = tx.Exec(`DELETE FROM manifest_layers WHERE manifest_rowid = (SELECT rowid FROM manifests WHERE tag_rowid = ?)`, tagRowID)
+		if err != nil {
+			return 0, fmt.Errorf("failed to delete manifest layers: %w", dbWriteError(err))
+		}
+		_, err = tx.Exec(`DELETE FROM manifests WHERE tag_rowid = ?`, tagRowID)
+		if err != nil {
+			return 0, fmt.Errorf("failed to delete manifest: %w", dbWriteError(err))
+		}
+		_, err = tx.Exec(`DELETE FROM tags WHERE rowid = ?`, tagRowID)
+		if err != nil {
+			return 0, fmt.Errorf("failed to delete tag: %w", dbWriteError(err))
+		}
+		deleted++
+	}
+	_, err = tx.Exec(`DELETE FROM digest_manifests WHERE repository = ? AND digest = ?`, repo, dgst.String())
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifest: %w", dbWriteError(err))
+	}
+	_, err = tx.Exec(`DELETE FROM referrers WHERE repository = ? AND digest = ?`, repo, dgst.String())
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete referrer: %w", dbWriteError(err))
+	}
+
+	if err = tx.Commit(); err != nil {
+		return 0, fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
+	}
+
+	return deleted, nil
+}
+
+// DeleteTag removes a single tag and the manifest row stored under it, reporting whether it existed.
+func (r *RegistryDB) DeleteTag(repo string, tag string) (bool, error) {
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return false, fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
+	}
+	defer func() {
+		if err != nil {
+			_ = tx.Rollback()
+		}
+	}()
+
+	var tagRowID int64
+	err = tx.Get(&tagRowID, `SELECT rowid FROM tags WHERE repository = ? AND name = ?`, repo, tag)
+	if errors.Is(err, sql.ErrNoRows) {
+		return false, nil
+	}
+	if err != nil {
+		return false, fmt.Errorf("failed to look up tag: %w", dbWriteError(err))
+	}
+
+	_, err = tx.Exec(`DELETE FROM manifest_layers WHERE manifest_rowid = (SELECT rowid FROM manifests WHERE tag_rowid = ?)`, tagRowID)
+	if err != nil {
+		return false, fmt.Errorf("failed to delete manifest layers: %w", dbWriteError(err))
+	}
+	_, err = tx.Exec(`DELETE FROM manifests WHERE tag_rowid = ?`, tagRowID)
+	if err != nil {
+		return false, fmt.Errorf("failed to delete manifest: %w", dbWriteError(err))
+	}
+	_, err = tx.Exec(`DELETE FROM tags WHERE rowid = ?`, tagRowID)
+	if err != nil {
+		return false, fmt.Errorf("failed to delete tag: %w", dbWriteError(err))
+	}
+
+	if err = tx.Commit(); err != nil {
+		return false, fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
+	}
+	return true, nil
+}
+
+// InvalidateManifest drops the cached manifest of a tag, keeping the tag itself listed,
+// so that the next fetch resolves it from storage again.
+func (r *RegistryDB) InvalidateManifest(repo string, tag string) (int64, error) {
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return 0, fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
+	}
+	defer func() {
+		_ = tx.Rollback()
+	}()
+
+	tagRowQuery := `SELECT rowid FROM tags WHERE repository = ? AND name = ?`
+	_, err = tx.Exec(`DELETE FROM manifest_layers WHERE manifest_rowid IN (
+		SELECT m.rowid FROM manifests m WHERE m.tag_rowid = (`+tagRowQuery+`))`, repo, tag)
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifest layers: %w", dbWriteError(err))
+	}
+	result, err := tx.Exec(`DELETE FROM manifests WHERE tag_rowid = (`+tagRowQuery+`)`, repo, tag)
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifest: %w", dbWriteError(err))
+	}
+	invalidated, err := result.RowsAffected()
+	if err != nil {
+		return 0, fmt.Errorf("failed to count deleted manifests: %w", err)
+	}
+
+	if err := tx.Commit(); err != nil {
+		return 0, fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
+	}
+	return invalidated, nil
+}
+
+// InvalidateRepository drops the cached tags and manifests of a repository, so that both
+// its tag list and manifests get resolved from storage again. It returns the number of
+// manifests dropped.
+func (r *RegistryDB) InvalidateRepository(repo string) (int64, error) {
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return 0, fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
+	}
+	defer func() {
+		_ = tx.Rollback()
+	}()
+
+	tagRowsQuery := `SELECT rowid FROM tags WHERE repository = ?`
+	_, err = tx.Exec(`DELETE FROM manifest_layers WHERE manifest_rowid IN (
+		SELECT m.rowid FROM manifests m WHERE m.tag_rowid IN (`+tagRowsQuery+`))`, repo)
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifest layers: %w", dbWriteError(err))
+	}
+	result, err := tx.Exec(`DELETE FROM manifests WHERE tag_rowid IN (`+tagRowsQuery+`)`, repo)
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifests: %w", dbWriteError(err))
+	}
+	invalidated, err := result.RowsAffected()
+	if err != nil {
+		return 0, fmt.Errorf("failed to count deleted manifests: %w", err)
+	}
+	if _, err := tx.Exec(`DELETE FROM tags WHERE repository = ?`, repo); err != nil {
+		return 0, fmt.Errorf("failed to delete tags: %w", dbWriteError(err))
+	}
+	result, err = tx.Exec(`DELETE FROM digest_manifests WHERE repository = ?`, repo)
+	if err != nil {
+		return 0, fmt.Errorf("failed to delete manifests: %w", dbWriteError(err))
+	}
+	invalidatedByDigest, err := result.RowsAffected()
+	if err != nil {
+		return 0, fmt.Errorf("failed to count deleted manifests: %w", err)
+	}
+	invalidated += invalidatedByDigest
+
+	if err := tx.Commit(); err != nil {
+		return 0, fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
+	}
+	return invalidated, nil
+}
+
+// ReferencedDigests returns the digests of all blobs referenced by stored manifests:
+// the manifests themselves, their configs, layers and, for indexes, child manifests.
+func (r *RegistryDB) ReferencedDigests() (map[string]struct{}, error) {
+	// NOTICE: both reads happen in one transaction, so they see the same snapshot
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return nil, fmt.Errorf("failed to start transaction: %w", err)
+	}
+	defer func() {
+		_ = tx.Rollback()
+	}()
+
+	var layerDigests []string
+	if err := tx.Select(&layerDigests, `SELECT DISTINCT layer_digest FROM manifest_layers`); err != nil {
+		return nil, fmt.Errorf("failed to list manifest layers: %w", err)
+	}
+	// NOTICE: manifests stored by digest have no manifest_layers rows, their layers come from the JSON
+	var stored []storedManifest
+	query := `SELECT manifest_json, compressed FROM manifests UNION ALL SELECT manifest_json, compressed FROM digest_manifests`
+	if err := tx.Select(&stored, query); err != nil {
+		return nil, fmt.Errorf("failed to list manifests: %w", err)
+	}
+	manifestJSONs, err := decodeManifests(stored)
+	if err != nil {
+		return nil, err
+	}
+
+	referenced := make(map[string]struct{}, len(layerDigests)+2*len(manifestJSONs))
+	for _, layerDigest := range layerDigests {
+		referenced[layerDigest] = struct{}{}
+	}
+	for _, manifestJSON := range manifestJSONs {
+		referenced[digest.FromString(manifestJSON).String()] = struct{}{}
+		var manifest struct {
+			Config    v1.Descriptor   `json:"config"`
+			Layers    []v1.Descriptor `json:"layers"`
+			Manifests []v1.Descriptor `json:"manifests"`
+		}
+		if err := json.Unmarshal([]byte(manifestJSON), &manifest); err != nil {
+			return nil, fmt.Errorf("failed to parse manifest: %w", err)
+		}
+		if manifest.Config.Digest != "" {
+			referenced[manifest.Config.Digest.String()] = struct{}{}
+		}
+		for _, layer := range manifest.Layers {
+			referenced[layer.Digest.String()] = struct{}{}
+		}
+		for _, child := range manifest.Manifests {
+			referenced[child.Digest.String()] = struct{}{}
+		}
+	}
+	return referenced, nil
+}
+
 func (r *RegistryDB) ListTags(repo string) ([]string, error) {
 	var tags []string
-	query := `SELECT name FROM tags WHERE repository = ?`
+	query := `SELECT name FROM tags WHERE repository = ? ORDER BY name`
 
 	err := r.db.Select(&tags, query, repo)
 	if err != nil {
@@ -173,10 +800,20 @@ func (r *RegistryDB) ListTags(repo string) ([]string, error) {
 	return tags, nil
 }
 
+// RepositoryExists reports whether the database has any tag or manifest of a repository.
+func (r *RegistryDB) RepositoryExists(repo string) (bool, error) {
+	var exists bool
+	query := `SELECT EXISTS (SELECT 1 FROM tags WHERE repository = ?) OR EXISTS (SELECT 1 FROM digest_manifests WHERE repository = ?)`
+	if err := r.db.Get(&exists, query, repo, repo); err != nil {
+		return false, fmt.Errorf("failed to check repository: %w", err)
+	}
+	return exists, nil
+}
+
 func (r *RegistryDB) PutTags(repo string, tags []string) error {
 	tx, err := r.db.Beginx()
 	if err != nil {
-		return fmt.Errorf("failed to start transaction: %w", err)
+		return fmt.Errorf("failed to start transaction: %w", dbWriteError(err))
 	}
 	defer func() {
 		if err != nil {
@@ -186,14 +823,14 @@ func (r *RegistryDB) PutTags(repo string, tags []string) error {
 
 	query := `INSERT INTO tags (repository, name) VALUES (?, ?) ON CONFLICT(repository, name) DO NOTHING`
 	for _, tag := range tags {
-		_, err := tx.Exec(query, repo, tag)
+		_, err = tx.Exec(query, repo, tag)
 		if err != nil {
-			return fmt.Errorf("failed to register tag: %w", err)
+			return fmt.Errorf("failed to register tag: %w", dbWriteError(err))
 		}
 	}
 
 	if err = tx.Commit(); err != nil {
-		return fmt.Errorf("failed to commit transaction: %w", err)
+		return fmt.Errorf("failed to commit transaction: %w", dbWriteError(err))
 	}
 
 	return nil
@@ -204,7 +841,7 @@ func (r *RegistryDB) ListRepositories(continuationToken *string, n int) ([]strin
 		token := ""
 		continuationToken = &token
 	}
-	query := `SELECT repository FROM tags WHERE repository > ? LIMIT ?`
+	query := `SELECT DISTINCT repository FROM tags WHERE repository > ? ORDER BY repository LIMIT ?`
 	var repos []string
 	err := r.db.Select(&repos, query, continuationToken, n)
 	if err != nil {
@@ -218,39 +855,251 @@ func (r *RegistryDB) ListRepositories(continuationToken *string, n int) ([]strin
 	return repos, &repos[len(repos)-1], nil
 }
 
+func (r *RegistryDB) Ping(ctx context.Context) error {
+	var dummy int
+	return r.db.GetContext(ctx, &dummy, `SELECT 1`)
+}
+
+// PutReferrer records that the manifest described by referrer refers to subject.
+func (r *RegistryDB) PutReferrer(repo string, subject digest.Digest, referrer v1.Descriptor) error {
+	var annotations sql.NullString
+	if len(referrer.Annotations) > 0 {
+		marshaled, err := json.Marshal(referrer.Annotations)
+		if err != nil {
+			return fmt.Errorf("failed to marshal annotations: %w", err)
+		}
+		annotations = sql.NullString{String: string(marshaled), Valid: true}
+	}
+	query := `INSERT INTO referrers (repository, subject, digest, media_type, artifact_type, size, annotations)
+		VALUES (?, ?, ?, ?, ?, ?, ?)
+		ON CONFLICT(repository, subject, digest) DO NOTHING`
+	_, err := r.db.Exec(query, repo, subject.String(), referrer.Digest.String(), referrer.MediaType, referrer.ArtifactType, referrer.Size, annotations)
+	if err != nil {
+		return fmt.Errorf("failed to store referrer: %w", dbWriteError(err))
+	}
+	return nil
+}
+
+// ListReferrers returns the descriptors of the manifests referring to subject, of the given
+// artifact type only if it is non-empty.
+func (r *RegistryDB) ListReferrers(repo string, subject digest.Digest, artifactType string) ([]v1.Descriptor, error) {
+	var rows []struct {
+		Digest       string         `db:"digest"`
+		MediaType    string         `db:"media_type"`
+		ArtifactType sql.NullString `db:"artifact_type"`
+		Size         int64          `db:"size"`
+		Annotations  sql.NullString `db:"annotations"`
+	}
+	query := `SELECT digest, media_type, artifact_type, size, annotations FROM referrers
+		WHERE repository = ? AND subject = ? AND (? = '' OR artifact_type = ?)
+		ORDER BY digest`
+	if err := r.db.Select(&rows, query, repo, subject.String(), artifactType, artifactType); err != nil {
+		return nil, fmt.Errorf("failed to list referrers: %w", err)
+	}
+
+	referrers := make([]v1.Descriptor, 0, len(rows))
+	for _, row := range rows {
+		referrer := v1.Descriptor{
+			MediaType:    row.MediaType,
+			ArtifactType: row.ArtifactType.String,
+			Digest:       digest.Digest(row.Digest),
+			Size:         row.Size,
+		}
+		if row.Annotations.Valid {
+			if err := json.Unmarshal([]byte(row.Annotations.String), &referrer.Annotations); err != nil {
+				return nil, fmt.Errorf("failed to parse annotations of referrer %s: %w", row.Digest, err)
+			}
+		}
+		referrers = append(referrers, referrer)
+	}
+	return referrers, nil
+}
+
+// ExportedManifest is a cached manifest as dumped by /admin/export. Manifests stored only
+// by digest (e.g. children of an index) have no tag.
+type ExportedManifest struct {
+	Repository string          `json:"repo"`
+	Tag        string          `json:"tag,omitempty"`
+	Digest     string          `json:"digest"`
+	MediaType  string          `json:"media_type"`
+	Manifest   json.RawMessage `json:"manifest"`
+}
+
+// ExportManifests calls fn for every cached manifest, of a single repository if repo is
+// non-empty, ordered by repository. Rows are streamed from the database one at a time.
+func (r *RegistryDB) ExportManifests(ctx context.Context, repo string, fn func(ExportedManifest) error) error {
+	query := `SELECT repository, tag, digest, media_type, manifest_json, compressed FROM (
+			SELECT t.repository AS repository, t.name AS tag, m.digest AS digest, m.media_type AS media_type,
+				m.manifest_json AS manifest_json, m.compressed AS compressed
+			FROM manifests m JOIN tags t ON t.rowid = m.tag_rowid
+			UNION ALL
+			SELECT repository, '' AS tag, digest, media_type, manifest_json, compressed FROM digest_manifests
+		)
+		WHERE ? = '' OR repository = ?
+		ORDER BY repository, tag, digest`
+	rows, err := r.db.QueryContext(ctx, query, repo, repo)
+	if err != nil {
+		return fmt.Errorf("failed to export manifests: %w", err)
+	}
+	defer rows.Close()
+
+	for rows.Next() {
+		var exported ExportedManifest
+		var mediaType sql.NullString
+		var stored storedManifest
+		if err := rows.Scan(&exported.Repository, &exported.Tag, &exported.Digest, &mediaType, &stored.ManifestJSON, &stored.Compressed); err != nil {
+			return fmt.Errorf("failed to scan manifest: %w", err)
+		}
+		manifestJSON, err := stored.decode()
+		if err != nil {
+			return err
+		}
+		exported.Manifest = json.RawMessage(manifestJSON)
+		exported.MediaType = mediaType.String
+		// NOTICE: rows stored before the media_type column existed have it sniffed on the fly
+		if !mediaType.Valid {
+			var manifest v1.Manifest
+			if err := json.Unmarshal(exported.Manifest, &manifest); err == nil {
+				exported.MediaType = manifestMediaType(exported.Manifest, &manifest)
+			}
+		}
+		if err := fn(exported); err != nil {
+			return err
+		}
+	}
+	if err := rows.Err(); err != nil {
+		return fmt.Errorf("failed to export manifests: %w", err)
+	}
+	return nil
+}
+
+// ListAliases returns every alias along with the repository it stands for.
+func (r *RegistryDB) ListAliases() (map[string]string, error) {
+	var rows []struct {
+		Alias      string `db:"alias"`
+		Repository string `db:"repository"`
+	}
+	if err := r.db.Select(&rows, `SELECT alias, repository FROM repo_aliases`); err != nil {
+		return nil, fmt.Errorf("failed to list aliases: %w", err)
+	}
+	aliases := make(map[string]string, len(rows))
+	for _, row := range rows {
+		aliases[row.Alias] = row.Repository
+	}
+	return aliases, nil
+}
+
+func (r *RegistryDB) PutAlias(alias string, repository string) error {
+	query := `INSERT INTO repo_aliases (alias, repository) VALUES (?, ?)
+		ON CONFLICT(alias) DO UPDATE SET repository = excluded.repository`
+	if _, err := r.db.Exec(query, alias, repository); err != nil {
+		return fmt.Errorf("failed to store alias: %w", dbWriteError(err))
+	}
+	return nil
+}
+
+func (r *RegistryDB) DeleteAlias(alias string) error {
+	result, err := r.db.Exec(`DELETE FROM repo_aliases WHERE alias = ?`, alias)
+	if err != nil {
+		return fmt.Errorf("failed to delete alias: %w", dbWriteError(err))
+	}
+	deleted, err := result.RowsAffected()
+	if err != nil {
+		return fmt.Errorf("failed to count deleted aliases: %w", err)
+	}
+	if deleted == 0 {
+		return fmt.Errorf("alias %s not found: %w", alias, fs.ErrNotExist)
+	}
+	return nil
+}
+
+// PoolStats reports the connection pool. closed_idle growing along with the request count
+// would mean connections are closed and reopened instead of being reused.
+func (r *RegistryDB) PoolStats() map[string]any {
+	stats := r.db.Stats()
+	return map[string]any{
+		"open":             stats.OpenConnections,
+		"in_use":           stats.InUse,
+		"idle":             stats.Idle,
+		"max_open":         stats.MaxOpenConnections,
+		"wait_count":       stats.WaitCount,
+		"wait_seconds":     stats.WaitDuration.Seconds(),
+		"closed_idle":      stats.MaxIdleClosed,
+		"closed_idle_time": stats.MaxIdleTimeClosed,
+		"closed_lifetime":  stats.MaxLifetimeClosed,
+	}
+}
+
+// CheckWritable takes the write lock and writes to the database in a transaction which is rolled
+// back, catching read-only files and directories without leaving anything behind.
+func (r *RegistryDB) CheckWritable(ctx context.Context) error {
+	tx, err := r.db.BeginTxx(ctx, nil)
+	if err != nil {
+		return dbWriteError(err)
+	}
+	defer tx.Rollback()
+	if _, err := tx.ExecContext(ctx, `CREATE TABLE doctor_check (id INTEGER)`); err != nil {
+		return dbWriteError(err)
+	}
+	return nil
+}
+
+func (r *RegistryDB) HasRepositories() bool {
+	var dummy int
+	return r.db.Get(&dummy, `SELECT 1 FROM tags LIMIT 1`) == nil
+}
+
 func (r *RegistryDB) Exists(repo string, tag string) bool {
 	query := `SELECT 1 FROM tags WHERE repository = ? AND name = ?`
 	var dummy int
 	return r.db.Get(&dummy, query, repo, tag) == nil
 }
 
-func (r *RegistryDB) CreateUploadSession(uploadID, repository, s3Key string) error {
-	query := `INSERT INTO upload_sessions (upload_id, repository, s3_key) VALUES (?, ?, ?)`
-	_, err := r.db.Exec(query, uploadID, repository, s3Key)
+type UploadSession struct {
+	Repository   string `db:"repository"`
+	S3UploadID   string `db:"s3_upload_id"`
+	S3Key        string `db:"s3_key"`
+	UploadedSize int64  `db:"uploaded_size"`
+	PartCount    int32  `db:"part_count"`
+	HashState    []byte `db:"hash_state"`
+}
+
+// hashable reports whether the session has the hash of everything uploaded so far.
+func (s *UploadSession) hashable() bool {
+	return s.UploadedSize == 0 || len(s.HashState) > 0
+}
+
+func (r *RegistryDB) CreateUploadSession(uploadID, repository, s3Key, s3UploadID string) error {
+	query := `INSERT INTO upload_sessions (upload_id, repository, s3_key, s3_upload_id) VALUES (?, ?, ?, ?)`
+	_, err := r.db.Exec(query, uploadID, repository, s3Key, s3UploadID)
 	if err != nil {
 		return fmt.Errorf("failed to create upload session: %w", err)
 	}
 	return nil
 }
 
-func (r *RegistryDB) UpdateUploadSession(uploadID, s3UploadID string, uploadedSize int64) error {
-	query := `UPDATE upload_sessions SET s3_upload_id = ?, uploaded_size = ?, last_activity = CURRENT_TIMESTAMP WHERE upload_id = ?`
-	_, err := r.db.Exec(query, s3UploadID, uploadedSize, uploadID)
+func (r *RegistryDB) UpdateUploadSession(uploadID, s3UploadID string, uploadedSize int64, partCount int32, hashState []byte) error {
+	query := `UPDATE upload_sessions SET s3_upload_id = ?, uploaded_size = ?, part_count = ?, hash_state = ?, last_activity = CURRENT_TIMESTAMP WHERE upload_id = ?`
+	_, err := r.db.Exec(query, s3UploadID, uploadedSize, partCount, hashState, uploadID)
 	if err != nil {
 		return fmt.Errorf("failed to update upload session: %w", err)
 	}
 	return nil
 }
 
-func (r *RegistryDB) GetUploadSession(uploadID string) (string, string, int64, error) {
-	query := `SELECT COALESCE(s3_upload_id, ''), COALESCE(s3_key, ''), uploaded_size FROM upload_sessions WHERE upload_id = ?`
-	var s3UploadID, s3Key string
-	var uploadedSize int64
-	err := r.db.QueryRow(query, uploadID).Scan(&s3UploadID, &s3Key, &uploadedSize)
+func (r *RegistryDB) GetUploadSession(uploadID string) (*UploadSession, error) {
+	query := `SELECT repository, COALESCE(s3_upload_id, '') AS s3_upload_id, COALESCE(s3_key, '') AS s3_key,
+		COALESCE(uploaded_size, 0) AS uploaded_size, part_count, hash_state
+		FROM upload_sessions WHERE upload_id = ?`
+	var session UploadSession
+	err := r.db.Get(&session, query, uploadID)
+	if errors.Is(err, sql.ErrNoRows) {
+		return nil, fmt.Errorf("upload session %s not found: %w", uploadID, errors.Join(err, fs.ErrNotExist))
+	}
 	if err != nil {
-		return "", "", 0, fmt.Errorf("failed to get upload session: %w", err)
+		return nil, fmt.Errorf("failed to get upload session: %w", err)
 	}
-	return s3UploadID, s3Key, uploadedSize, nil
+	return &session, nil
 }
 
 func (r *RegistryDB) DeleteUploadSession(uploadID string) error {
@@ -278,9 +1127,12 @@ func (r *RegistryDB) ListAllTags(continuationToken *string, n int) ([]map[string
 		continuationToken = &token
 	}
 
-	query := `SELECT repository, name FROM tags WHERE repository || ':' || name > ? ORDER BY repository, name LIMIT ?`
+	// NOTICE: the token is "repository:tag", but it has to be compared as a tuple to follow the
+	// ORDER BY - "/" and "-" sort below ":", so nested repositories would compare below the token
+	lastRepo, lastTag, _ := strings.Cut(*continuationToken, ":")
+	query := `SELECT repository, name FROM tags WHERE (repository, name) > (?, ?) ORDER BY repository, name LIMIT ?`
 	var result []map[string]string
-	rows, err := r.db.Query(query, *continuationToken, n)
+	rows, err := r.db.Query(query, lastRepo, lastTag, n)
 	if err != nil {
 		return nil, nil, fmt.Errorf("failed to list tags: %w", err)
 	}
@@ -303,6 +1155,18 @@ func (r *RegistryDB) ListAllTags(continuationToken *string, n int) ([]map[string
 	return result, &nextToken, nil
 }
 
+// GetLayerSize returns the size of a layer referenced by any cached manifest.
+func (r *RegistryDB) GetLayerSize(dgst string) (int64, error) {
+	var size int64
+	if err := r.db.Get(&size, `SELECT size FROM layers WHERE digest = ?`, dgst); err != nil {
+		if err == sql.ErrNoRows {
+			return 0, fmt.Errorf("layer %s not found: %w", dgst, fs.ErrNotExist)
+		}
+		return 0, fmt.Errorf("failed to get layer size: %w", err)
+	}
+	return size, nil
+}
+
 func (r *RegistryDB) ListLayers(continuationToken *string, n int) ([]map[string]any, *string, error) {
 	if continuationToken == nil {
 		token := ""
@@ -344,7 +1208,7 @@ func (r *RegistryDB) ListManifests(continuationToken *string, n int) ([]map[stri
 		continuationToken = &token
 	}
 
-	query := `SELECT t.repository, t.name FROM manifests m 
+	query := `SELECT t.repository, t.name, m.digest FROM manifests m 
 		JOIN tags t ON t.rowid = m.tag_rowid 
 		WHERE t.repository || ':' || t.name > ?
 		ORDER BY t.repository, t.name LIMIT ?`
@@ -356,11 +1220,11 @@ func (r *RegistryDB) ListManifests(continuationToken *string, n int) ([]map[stri
 	defer rows.Close()
 
 	for rows.Next() {
-		var repo, tag string
-		if err := rows.Scan(&repo, &tag); err != nil {
+		var repo, tag, manifestDigest string
+		if err := rows.Scan(&repo, &tag, &manifestDigest); err != nil {
 			return nil, nil, fmt.Errorf("failed to scan manifest row: %w", err)
 		}
-		result = append(result, map[string]string{"repository": repo, "tag": tag})
+		result = append(result, map[string]string{"repository": repo, "tag": tag, "digest": manifestDigest})
 	}
 
 	if len(result) == 0 {
@@ -372,6 +1236,218 @@ func (r *RegistryDB) ListManifests(continuationToken *string, n int) ([]map[stri
 	return result, &nextToken, nil
 }
 
+func (r *RegistryDB) ListRepositoryManifests(repo string) ([]map[string]any, error) {
+	query := `SELECT t.name, m.manifest_json, m.compressed, m.media_type, m.digest, m.created_at FROM manifests m
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?
+		ORDER BY t.name`
+	rows, err := r.db.Query(query, repo)
+	if err != nil {
+		return nil, fmt.Errorf("failed to list repository manifests: %w", err)
+	}
+	defer rows.Close()
+
+	result := []map[string]any{}
+	for rows.Next() {
+		var tag, manifestDigest string
+		var stored storedManifest
+		var mediaType, createdAt sql.NullString
+		if err := rows.Scan(&tag, &stored.ManifestJSON, &stored.Compressed, &mediaType, &manifestDigest, &createdAt); err != nil {
+			return nil, fmt.Errorf("failed to scan manifest row: %w", err)
+		}
+		manifestJSON, err := stored.decode()
+		if err != nil {
+			return nil, err
+		}
+		// NOTICE: rows stored before the media_type column existed have it sniffed on the fly
+		if !mediaType.Valid {
+			var manifest v1.Manifest
+			if err := json.Unmarshal([]byte(manifestJSON), &manifest); err != nil {
+				return nil, fmt.Errorf("failed to parse manifest %s:%s: %w", repo, tag, err)
+			}
+			mediaType.String = manifestMediaType([]byte(manifestJSON), &manifest)
+		}
+		entry := map[string]any{
+			"digest":     manifestDigest,
+			"media_type": mediaType.String,
+			"size":       len(manifestJSON),
+			"tag":        tag,
+		}
+		if createdAt.Valid {
+			entry["created_at"] = createdAt.String
+		}
+		result = append(result, entry)
+	}
+	if err := rows.Err(); err != nil {
+		return nil, fmt.Errorf("failed to list repository manifests: %w", err)
+	}
+	return result, nil
+}
+
+// GetRepositorySize sums up the storage used by a repository, counting every blob once
+// no matter how many of its manifests share it.
+func (r *RegistryDB) GetRepositorySize(repo string) (map[string]any, error) {
+	var tagCount int
+	if err := r.db.Get(&tagCount, `SELECT COUNT(*) FROM tags WHERE repository = ?`, repo); err != nil {
+		return nil, fmt.Errorf("failed to count tags: %w", err)
+	}
+	if tagCount == 0 {
+		return nil, fmt.Errorf("repository %s not found: %w", repo, fs.ErrNotExist)
+	}
+
+	var layerBytes int64
+	query := `SELECT COALESCE(SUM(size), 0) FROM layers WHERE digest IN (
+		SELECT ml.layer_digest FROM manifest_layers ml
+		JOIN manifests m ON m.rowid = ml.manifest_rowid
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?)`
+	if err := r.db.Get(&layerBytes, query, repo); err != nil {
+		return nil, fmt.Errorf("failed to sum layer sizes: %w", err)
+	}
+
+	var stored []storedManifest
+	query = `SELECT m.manifest_json, m.compressed FROM manifests m
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?`
+	if err := r.db.Select(&stored, query, repo); err != nil {
+		return nil, fmt.Errorf("failed to list manifests: %w", err)
+	}
+	manifestJSONs, err := decodeManifests(stored)
+	if err != nil {
+		return nil, err
+	}
+
+	var manifestBytes, configBytes int64
+	seen := make(map[string]struct{})
+	for _, manifestJSON := range manifestJSONs {
+		manifestDigest := digest.FromString(manifestJSON).String()
+		if _, ok := seen[manifestDigest]; ok {
+			continue
+		}
+		seen[manifestDigest] = struct{}{}
+		manifestBytes += int64(len(manifestJSON))
+
+		var manifest v1.Manifest
+		if err := json.Unmarshal([]byte(manifestJSON), &manifest); err != nil {
+			return nil, fmt.Errorf("failed to parse manifest: %w", err)
+		}
+		if manifest.Config.Digest == "" {
+			continue
+		}
+		if _, ok := seen[manifest.Config.Digest.String()]; ok {
+			continue
+		}
+		seen[manifest.Config.Digest.String()] = struct{}{}
+		configBytes += manifest.Config.Size
+	}
+
+	return map[string]any{
+		"name":                 repo,
+		"size_bytes":           layerBytes + configBytes + manifestBytes,
+		"distinct_layer_bytes": layerBytes,
+		"config_bytes":         configBytes,
+		"manifest_bytes":       manifestBytes,
+	}, nil
+}
+
+type RepositoryBlob struct {
+	Digest    string `json:"digest" db:"digest"`
+	MediaType string `json:"media_type" db:"media_type"`
+	Size      int64  `json:"size" db:"size"`
+}
+
+// ListRepositoryBlobs returns the distinct layers and configs referenced by the manifests
+// of a repository, sorted by digest. Unknown repositories simply have no blobs.
+func (r *RegistryDB) ListRepositoryBlobs(repo string) ([]RepositoryBlob, error) {
+	tx, err := r.db.Beginx()
+	if err != nil {
+		return nil, fmt.Errorf("failed to start transaction: %w", err)
+	}
+	defer func() {
+		_ = tx.Rollback()
+	}()
+
+	var layers []RepositoryBlob
+	query := `SELECT DISTINCT l.digest, l.media_type, l.size FROM layers l
+		JOIN manifest_layers ml ON ml.layer_digest = l.digest
+		JOIN manifests m ON m.rowid = ml.manifest_rowid
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?`
+	if err := tx.Select(&layers, query, repo); err != nil {
+		return nil, fmt.Errorf("failed to list repository layers: %w", err)
+	}
+
+	var stored []storedManifest
+	query = `SELECT m.manifest_json, m.compressed FROM manifests m
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?`
+	if err := tx.Select(&stored, query, repo); err != nil {
+		return nil, fmt.Errorf("failed to list manifests: %w", err)
+	}
+	manifestJSONs, err := decodeManifests(stored)
+	if err != nil {
+		return nil, err
+	}
+
+	blobs := make(map[string]RepositoryBlob, len(layers))
+	for _, layer := range layers {
+		blobs[layer.Digest] = layer
+	}
+	for _, manifestJSON := range manifestJSONs {
+		var manifest v1.Manifest
+		if err := json.Unmarshal([]byte(manifestJSON), &manifest); err != nil {
+			return nil, fmt.Errorf("failed to parse manifest: %w", err)
+		}
+		if manifest.Config.Digest == "" {
+			continue
+		}
+		blobs[manifest.Config.Digest.String()] = RepositoryBlob{
+			Digest:    manifest.Config.Digest.String(),
+			MediaType: manifest.Config.MediaType,
+			Size:      manifest.Config.Size,
+		}
+	}
+
+	result := make([]RepositoryBlob, 0, len(blobs))
+	for _, blob := range blobs {
+		result = append(result, blob)
+	}
+	slices.SortFunc(result, func(a, b RepositoryBlob) int {
+		return strings.Compare(a.Digest, b.Digest)
+	})
+	return result, nil
+}
+
+// ListLargestLayers returns up to n distinct layers referenced by a repository, largest first.
+func (r *RegistryDB) ListLargestLayers(repo string, n int) ([]RepositoryBlob, error) {
+	layers := []RepositoryBlob{}
+	query := `SELECT DISTINCT l.digest, l.media_type, l.size FROM layers l
+		JOIN manifest_layers ml ON ml.layer_digest = l.digest
+		JOIN manifests m ON m.rowid = ml.manifest_rowid
+		JOIN tags t ON t.rowid = m.tag_rowid
+		WHERE t.repository = ?
+		ORDER BY l.size DESC, l.digest
+		LIMIT ?`
+	if err := r.db.Select(&layers, query, repo, n); err != nil {
+		return nil, fmt.Errorf("failed to list largest layers: %w", err)
+	}
+	return layers, nil
+}
+
+// likeEscaper escapes the LIKE wildcards (and the escape character itself) for ESCAPE '\'.
+var likeEscaper = strings.NewReplacer(`\`, `\\`, `%`, `\%`, `_`, `\_`)
+
+// SearchRepositories returns up to n cached repository names starting with prefix, sorted.
+// NOTICE: LIKE ignores ASCII case, which is harmless as repository names are lowercase anyway.
+func (r *RegistryDB) SearchRepositories(prefix string, n int) ([]string, error) {
+	repositories := []string{}
+	query := `SELECT DISTINCT repository FROM tags WHERE repository LIKE ? ESCAPE '\' ORDER BY repository LIMIT ?`
+	if err := r.db.Select(&repositories, query, likeEscaper.Replace(prefix)+"%", n); err != nil {
+		return nil, fmt.Errorf("failed to search repositories: %w", err)
+	}
+	return repositories, nil
+}
+
 func (r *RegistryDB) ListUploadSessions() ([]map[string]any, error) {
 	var result []map[string]any
 	query := `SELECT upload_id, repository, digest, s3_upload_id, s3_key, 
@@ -417,6 +1493,39 @@ func (r *RegistryDB) ListUploadSessions() ([]map[string]any, error) {
 	return result, nil
 }
 
+// SQLitePragmas reads back the tunable pragmas as seen by a pooled connection, which shows
+// whether --sqlite-pragma took effect (SQLite silently caps some values, e.g. mmap_size).
+func (r *RegistryDB) SQLitePragmas() (map[string]string, error) {
+	values := make(map[string]string, len(sqlitePragmas))
+	for name := range sqlitePragmas {
+		var value string
+		if err := r.db.Get(&value, fmt.Sprintf("PRAGMA %s;", name)); err != nil {
+			return nil, fmt.Errorf("failed to read pragma %s: %w", name, err)
+		}
+		values[name] = value
+	}
+	return values, nil
+}
+
+// CountManifestMediaTypes counts the cached manifests per media type. Rows stored before the
+// media_type column existed, which no refresh or push has rewritten since, are counted as "unknown".
+func (r *RegistryDB) CountManifestMediaTypes() (map[string]int, error) {
+	var rows []struct {
+		MediaType string `db:"media_type"`
+		Count     int    `db:"count"`
+	}
+	query := `SELECT COALESCE(NULLIF(media_type, ''), 'unknown') AS media_type, COUNT(*) AS count
+		FROM manifests GROUP BY 1`
+	if err := r.db.Select(&rows, query); err != nil {
+		return nil, fmt.Errorf("failed to count manifest media types: %w", err)
+	}
+	counts := make(map[string]int, len(rows))
+	for _, row := range rows {
+		counts[row.MediaType] = row.Count
+	}
+	return counts, nil
+}
+
 func (r *RegistryDB) GetRegistryStats() (map[string]any, error) {
 	stats := make(map[string]any)
 
@@ -438,6 +1547,12 @@ func (r *RegistryDB) GetRegistryStats() (map[string]any, error) {
 	}
 	stats["manifests"] = manifestCount
 
+	mediaTypes, err := r.CountManifestMediaTypes()
+	if err != nil {
+		return nil, err
+	}
+	stats["manifest_media_types"] = mediaTypes
+
 	var layerCount int
 	if err := r.db.Get(&layerCount, "SELECT COUNT(*) FROM layers"); err != nil {
 		return nil, fmt.Errorf("failed to count layers: %w", err)
@@ -463,6 +1578,24 @@ func (r *RegistryDB) GetRegistryStats() (map[string]any, error) {
 	return stats, nil
 }
 
+// WALCheckpoint is the outcome of PRAGMA wal_checkpoint: pages in the log before the checkpoint
+// and how many of them were copied into the database. Busy means it couldn't complete.
+type WALCheckpoint struct {
+	Busy              bool
+	LogPages          int
+	CheckpointedPages int
+}
+
+// CheckpointWAL copies the write-ahead log into the database and truncates it to zero bytes.
+func (r *RegistryDB) CheckpointWAL(ctx context.Context) (WALCheckpoint, error) {
+	var busy, logPages, checkpointedPages int
+	err := r.db.QueryRowContext(ctx, `PRAGMA wal_checkpoint(TRUNCATE)`).Scan(&busy, &logPages, &checkpointedPages)
+	if err != nil {
+		return WALCheckpoint{}, dbWriteError(err)
+	}
+	return WALCheckpoint{Busy: busy != 0, LogPages: logPages, CheckpointedPages: checkpointedPages}, nil
+}
+
 func (r *RegistryDB) Close() error {
 	if err := r.db.Close(); err != nil {
 		return fmt.Errorf("failed to close database: %w", err)
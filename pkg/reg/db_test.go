@@ -0,0 +1,335 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"path/filepath"
+	"reflect"
+	"slices"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/jmoiron/sqlx"
+	"github.com/opencontainers/go-digest"
+)
+
+func TestWriteReturnsBusyAfterTimeout(t *testing.T) {
+	path := filepath.Join(t.TempDir(), "registry.db")
+	const busyTimeout = 200 * time.Millisecond
+	holder, err := initSQLite(path, busyTimeout, nil)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	defer holder.Close()
+	waiter, err := initSQLite(path, busyTimeout, nil)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	defer waiter.Close()
+
+	// NOTICE: transactions are immediate, so beginning one takes the write lock
+	tx, err := holder.db.Beginx()
+	if err != nil {
+		t.Fatalf("failed to start transaction: %v", err)
+	}
+	defer tx.Rollback()
+
+	start := time.Now()
+	err = waiter.PutTags("foo", []string{"latest"})
+	elapsed := time.Since(start)
+	if !errors.Is(err, ErrDatabaseBusy) {
+		t.Fatalf("got error %v, want %v", err, ErrDatabaseBusy)
+	}
+	if elapsed < busyTimeout*3/4 {
+		t.Fatalf("gave up after %s, before the busy timeout of %s", elapsed, busyTimeout)
+	}
+
+	if err := tx.Rollback(); err != nil {
+		t.Fatalf("failed to release the write lock: %v", err)
+	}
+	if err := waiter.PutTags("foo", []string{"latest"}); err != nil {
+		t.Fatalf("failed to write once the lock was released: %v", err)
+	}
+}
+
+func TestLookupsUseIndexes(t *testing.T) {
+	r := newTestRegistry(t)
+
+	for name, query := range map[string]string{
+		"tag manifest":    `SELECT manifest_json, compressed FROM manifests JOIN tags ON tags.rowid = manifests.tag_rowid WHERE tags.repository = ? AND tags.name = ?`,
+		"repository tags": `SELECT name FROM tags WHERE repository = ? ORDER BY name`,
+		"repository":      `SELECT EXISTS (SELECT 1 FROM tags WHERE repository = ?) OR EXISTS (SELECT 1 FROM digest_manifests WHERE repository = ?)`,
+		"digest manifest": `SELECT rowid FROM manifests WHERE digest = ?`,
+		"layer manifests": `SELECT manifest_rowid FROM manifest_layers WHERE layer_digest = ?`,
+		"stale uploads":   `SELECT upload_id FROM upload_sessions WHERE last_activity < datetime('now', ?)`,
+	} {
+		t.Run(name, func(t *testing.T) {
+			args := make([]any, strings.Count(query, "?"))
+			for i := range args {
+				args[i] = "x"
+			}
+			rows, err := r.db.db.Query("EXPLAIN QUERY PLAN "+query, args...)
+			if err != nil {
+				t.Fatalf("failed to explain query: %v", err)
+			}
+			defer rows.Close()
+			for rows.Next() {
+				var id, parent, unused int
+				var detail string
+				if err := rows.Scan(&id, &parent, &unused, &detail); err != nil {
+					t.Fatalf("failed to scan plan: %v", err)
+				}
+				// NOTICE: a lookup has to SEARCH, a SCAN walks the whole table (or a whole index)
+				if strings.HasPrefix(detail, "SCAN") {
+					t.Errorf("query scans instead of searching: %s", detail)
+				}
+			}
+			if err := rows.Err(); err != nil {
+				t.Fatalf("failed to read plan: %v", err)
+			}
+		})
+	}
+}
+
+func TestCompressedManifestRoundTrip(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.CompressCache = true })
+	manifestBytes := testManifest(t, r, "layer")
+	dgst, err := r.putManifest(context.Background(), "foo", "latest", manifestBytes)
+	if err != nil {
+		t.Fatalf("failed to push manifest: %v", err)
+	}
+
+	var compressed bool
+	if err := r.db.db.Get(&compressed, `SELECT compressed FROM manifests WHERE digest = ?`, dgst.String()); err != nil {
+		t.Fatalf("failed to read the stored row: %v", err)
+	}
+	if !compressed {
+		t.Fatal("manifest was stored uncompressed")
+	}
+
+	cached, err := r.db.GetManifest("foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to get cached manifest: %v", err)
+	}
+	if cached != string(manifestBytes) {
+		t.Fatalf("got %s, want the pushed bytes %s", cached, manifestBytes)
+	}
+	if got := digest.FromString(cached); got != dgst {
+		t.Fatalf("got digest %s, want %s", got, dgst)
+	}
+}
+
+func TestStoredManifestDigest(t *testing.T) {
+	// NOTICE: compressed rows hash to anything but the manifest digest, which must come from the row
+	r := newTestRegistry(t, func(cfg *Config) { cfg.CompressCache = true })
+	latest := pushTestImage(t, r, "foo", "latest", "layer")
+	pushTestImage(t, r, "foo", "stable", "layer")
+	other := pushTestImage(t, r, "foo", "old", "old layer")
+
+	manifests, _, err := r.db.ListManifests(nil, 10)
+	if err != nil {
+		t.Fatalf("failed to list manifests: %v", err)
+	}
+	want := map[string]string{"latest": latest.String(), "old": other.String(), "stable": latest.String()}
+	if len(manifests) != len(want) {
+		t.Fatalf("listed %d manifests, want %d", len(manifests), len(want))
+	}
+	for _, manifest := range manifests {
+		if manifest["digest"] != want[manifest["tag"]] {
+			t.Errorf("got digest %s for foo:%s, want %s", manifest["digest"], manifest["tag"], want[manifest["tag"]])
+		}
+	}
+	repositoryManifests, err := r.db.ListRepositoryManifests("foo")
+	if err != nil {
+		t.Fatalf("failed to list repository manifests: %v", err)
+	}
+	if len(repositoryManifests) != len(want) {
+		t.Fatalf("listed %d manifests of the repository, want %d", len(repositoryManifests), len(want))
+	}
+	for _, manifest := range repositoryManifests {
+		if manifest["digest"] != want[manifest["tag"].(string)] {
+			t.Errorf("got digest %s for foo:%s in the repository, want %s", manifest["digest"], manifest["tag"], want[manifest["tag"].(string)])
+		}
+	}
+
+	deleted, err := r.db.DeleteManifest("foo", latest)
+	if err != nil {
+		t.Fatalf("failed to delete manifest: %v", err)
+	}
+	if deleted != 2 {
+		t.Errorf("deleted %d tags of %s, want 2", deleted, latest)
+	}
+	if tags, err := r.db.ListTags("foo"); err != nil || !slices.Equal(tags, []string{"old"}) {
+		t.Errorf("got tags %v (%v) after the delete, want [old]", tags, err)
+	}
+}
+
+func TestMigrateUnversionedDatabase(t *testing.T) {
+	path := filepath.Join(t.TempDir(), "registry.db")
+	manifest := `{"schemaVersion":2}`
+
+	// NOTICE: the schema as it was before versioning, at user_version 0
+	old, err := sqlx.Open("sqlite3", path)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	for _, stmt := range []string{
+		`CREATE TABLE tags (repository TEXT NOT NULL, name TEXT NOT NULL, PRIMARY KEY(repository, name))`,
+		`CREATE TABLE manifests (tag_rowid INTEGER NOT NULL, manifest_json TEXT NOT NULL, PRIMARY KEY(tag_rowid))`,
+		`CREATE TABLE upload_sessions (upload_id TEXT PRIMARY KEY, repository TEXT NOT NULL, s3_upload_id TEXT, s3_key TEXT NOT NULL,
+			last_activity DATETIME DEFAULT CURRENT_TIMESTAMP, uploaded_size INTEGER DEFAULT 0)`,
+		`INSERT INTO tags (repository, name) VALUES ('foo', 'latest')`,
+		`INSERT INTO manifests (tag_rowid, manifest_json) VALUES (1, '` + manifest + `')`,
+	} {
+		if _, err := old.Exec(stmt); err != nil {
+			t.Fatalf("failed to create the old schema: %v", err)
+		}
+	}
+	if err := old.Close(); err != nil {
+		t.Fatalf("failed to close database: %v", err)
+	}
+
+	// NOTICE: opened twice, the second time there is nothing left to migrate
+	for range 2 {
+		db, err := initSQLite(path, time.Second, nil)
+		if err != nil {
+			t.Fatalf("failed to migrate database: %v", err)
+		}
+		defer db.Close()
+
+		var version int
+		if err := db.db.Get(&version, "PRAGMA user_version"); err != nil {
+			t.Fatalf("failed to read schema version: %v", err)
+		}
+		if version != len(migrations) {
+			t.Fatalf("got schema version %d, want %d", version, len(migrations))
+		}
+		got, err := db.GetManifest("foo", "latest")
+		if err != nil || got != manifest {
+			t.Fatalf("got manifest %q (%v), want %q", got, err, manifest)
+		}
+		var stored string
+		if err := db.db.Get(&stored, "SELECT digest FROM manifests"); err != nil {
+			t.Fatalf("failed to read manifest digest: %v", err)
+		}
+		if want := digest.FromString(manifest).String(); stored != want {
+			t.Fatalf("got backfilled digest %s, want %s", stored, want)
+		}
+		if err := db.CreateUploadSession("upload", "foo", "uploads/upload", ""); err != nil {
+			t.Fatalf("failed to use the migrated upload sessions: %v", err)
+		}
+		if err := db.UpdateUploadSession("upload", "", 1, 1, []byte("state")); err != nil {
+			t.Fatalf("failed to use the migrated upload sessions: %v", err)
+		}
+		if err := db.DeleteUploadSession("upload"); err != nil {
+			t.Fatalf("failed to use the migrated upload sessions: %v", err)
+		}
+	}
+}
+
+func TestSQLitePragmasOnEveryConnection(t *testing.T) {
+	ctx := context.Background()
+	pragmas, err := ParseSQLitePragmas([]string{"cache_size=-4000", "temp_store=MEMORY"})
+	if err != nil {
+		t.Fatalf("failed to parse pragmas: %v", err)
+	}
+	db, err := initSQLite(filepath.Join(t.TempDir(), "registry.db"), time.Second, pragmas)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	defer db.Close()
+
+	// NOTICE: both connections are held at once, so the second can't be the first one reused
+	want := map[string]string{"cache_size": "-4000", "temp_store": "2", "synchronous": "1"}
+	for range 2 {
+		conn, err := db.db.Connx(ctx)
+		if err != nil {
+			t.Fatalf("failed to get connection: %v", err)
+		}
+		defer conn.Close()
+		for name, value := range want {
+			var got string
+			if err := conn.GetContext(ctx, &got, "PRAGMA "+name+";"); err != nil {
+				t.Fatalf("failed to read pragma %s: %v", name, err)
+			}
+			if got != value {
+				t.Errorf("got %s=%s on a pooled connection, want %s", name, got, value)
+			}
+		}
+	}
+
+	values, err := db.SQLitePragmas()
+	if err != nil {
+		t.Fatalf("failed to read pragmas: %v", err)
+	}
+	for name, value := range want {
+		if values[name] != value {
+			t.Errorf("got %s=%s, want %s", name, values[name], value)
+		}
+	}
+}
+
+func TestConnectionsKeptBetweenRequests(t *testing.T) {
+	ctx := context.Background()
+	db, err := initSQLite(filepath.Join(t.TempDir(), "registry.db"), time.Second, nil)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	defer db.Close()
+
+	// NOTICE: as many connections in use at once as concurrent requests would take
+	const concurrent = 10
+	for round := range 3 {
+		var conns []*sqlx.Conn
+		for range concurrent {
+			conn, err := db.db.Connx(ctx)
+			if err != nil {
+				t.Fatalf("failed to get connection: %v", err)
+			}
+			conns = append(conns, conn)
+		}
+		for _, conn := range conns {
+			if err := conn.Close(); err != nil {
+				t.Fatalf("failed to release connection: %v", err)
+			}
+		}
+		stats := db.db.Stats()
+		if stats.MaxIdleClosed != 0 || stats.OpenConnections != concurrent {
+			t.Fatalf("got %d connections open and %d closed after round %d, want %d open and none closed", stats.OpenConnections, stats.MaxIdleClosed, round, concurrent)
+		}
+	}
+}
+
+func TestSearchRepositoriesEscapesWildcards(t *testing.T) {
+	db, err := initSQLite(filepath.Join(t.TempDir(), "registry.db"), time.Second, nil)
+	if err != nil {
+		t.Fatalf("failed to open database: %v", err)
+	}
+	defer db.Close()
+	for _, repo := range []string{"foo_bar", "fooxbar", "foo%bar", `foo\bar`, "Foo_baz", "bar"} {
+		if err := db.PutTags(repo, []string{"latest"}); err != nil {
+			t.Fatalf("failed to put tags: %v", err)
+		}
+	}
+
+	for prefix, want := range map[string][]string{
+		"foo_":  {"Foo_baz", "foo_bar"},
+		"foo%":  {"foo%bar"},
+		`foo\`:  {`foo\bar`},
+		"foo":   {"Foo_baz", "foo%bar", `foo\bar`, "foo_bar", "fooxbar"},
+		"":      {"Foo_baz", "bar", "foo%bar", `foo\bar`, "foo_bar", "fooxbar"},
+		"baz":   {},
+	} {
+		got, err := db.SearchRepositories(prefix, 10)
+		if err != nil {
+			t.Fatalf("failed to search repositories: %v", err)
+		}
+		if !reflect.DeepEqual(got, want) {
+			t.Errorf("got %q searching %q, want %q", got, prefix, want)
+		}
+	}
+	if got, err := db.SearchRepositories("foo", 2); err != nil || len(got) != 2 {
+		t.Errorf("got %q (%v) searching 2 repositories", got, err)
+	}
+}
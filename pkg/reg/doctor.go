@@ -0,0 +1,113 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"fmt"
+
+	"github.com/aws/aws-sdk-go-v2/service/s3"
+)
+
+// DoctorCheck is the outcome of one of the checks run by Doctor.
+type DoctorCheck struct {
+	Name   string `json:"name"`
+	OK     bool   `json:"ok"`
+	Detail string `json:"detail,omitempty"`
+}
+
+// Doctor verifies the configuration against the actual storage and database: that AWS credentials
+// resolve, every bucket is reachable, the layout exists under the storage prefix and the database
+// is writable. Checks depending on a failed one are skipped, so the first failure is the one to fix.
+func Doctor(ctx context.Context, cfg Config) []DoctorCheck {
+	var checks []DoctorCheck
+	check := func(name string, err error) bool {
+		c := DoctorCheck{Name: name, OK: err == nil}
+		if err != nil {
+			c.Detail = err.Error()
+		}
+		checks = append(checks, c)
+		return err == nil
+	}
+
+	// NOTICE: the storage is never used with --db-only, so it doesn't have to be reachable
+	storage, routes, err := newStorages(ctx, cfg)
+	if !cfg.DBOnly && check("storage configuration", err) {
+		r := &Registry{storage: storage, routes: routes, cfg: cfg, prefix: cfg.storagePrefix()}
+		for _, storage := range r.storages() {
+			if bucket, ok := storage.(*s3Storage); ok {
+				if !check("credentials for bucket "+bucket.bucket, bucket.checkCredentials(ctx)) {
+					continue
+				}
+				if !check("bucket "+bucket.bucket+" reachable", bucket.headBucket(ctx)) {
+					continue
+				}
+			}
+			check(fmt.Sprintf("layout under %s/ %s", r.prefix, storageName(storage)), r.checkLayout(ctx, storage))
+		}
+	}
+
+	// NOTICE: a read-only registry only needs to read the database, which may well be read-only
+	dbCheck := "database " + cfg.DBPath + " writable"
+	if cfg.ReadOnly {
+		dbCheck = "database " + cfg.DBPath + " readable"
+	}
+	pragmas, err := ParseSQLitePragmas(cfg.SQLitePragmas)
+	if err != nil {
+		check(dbCheck, err)
+		return checks
+	}
+	db, err := initSQLite(cfg.DBPath, cfg.DBBusyTimeout, pragmas)
+	if err == nil {
+		if cfg.ReadOnly {
+			err = db.Ping(ctx)
+		} else {
+			err = db.CheckWritable(ctx)
+		}
+		db.Close()
+	}
+	check(dbCheck, err)
+	return checks
+}
+
+func storageName(storage Storage) string {
+	switch s := storage.(type) {
+	case *s3Storage:
+		return "in bucket " + s.bucket
+	case *fsStorage:
+		return "in " + s.root
+	}
+	return ""
+}
+
+// checkLayout fails if nothing at all is stored under the prefix, the usual sign of a wrong
+// bucket or --storage-prefix. A brand new registry fails it too, until the first push.
+func (r *Registry) checkLayout(ctx context.Context, storage Storage) error {
+	found := false
+	err := storage.List(ctx, r.prefix+"/", func(ObjectInfo) error {
+		found = true
+		return errStopListing
+	})
+	if err != nil && !errors.Is(err, errStopListing) {
+		return err
+	}
+	if !found {
+		return fmt.Errorf("no objects found under %s/, check --storage-prefix (or ignore if nothing was pushed yet)", r.prefix)
+	}
+	return nil
+}
+
+func (s *s3Storage) checkCredentials(ctx context.Context) error {
+	provider := s.client.Options().Credentials
+	if provider == nil {
+		return errors.New("no credentials provider configured")
+	}
+	if _, err := provider.Retrieve(ctx); err != nil {
+		return fmt.Errorf("failed to resolve credentials: %w", err)
+	}
+	return nil
+}
+
+func (s *s3Storage) headBucket(ctx context.Context) error {
+	_, err := s.client.HeadBucket(ctx, &s3.HeadBucketInput{Bucket: &s.bucket})
+	return s3Error(err)
+}
@@ -0,0 +1,98 @@
+package reg
+
+import (
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io/fs"
+	"log/slog"
+	"net/http"
+)
+
+// OCI distribution error codes, see https://github.com/opencontainers/distribution-spec/blob/main/spec.md#error-codes
+const (
+	errCodeBlobUnknown             = "BLOB_UNKNOWN"
+	errCodeBlobUploadInvalid       = "BLOB_UPLOAD_INVALID"
+	errCodeBlobUploadUnknown       = "BLOB_UPLOAD_UNKNOWN"
+	errCodeDenied                  = "DENIED"
+	errCodeDigestInvalid           = "DIGEST_INVALID"
+	errCodeManifestBlobUnknown     = "MANIFEST_BLOB_UNKNOWN"
+	errCodeManifestInvalid         = "MANIFEST_INVALID"
+	errCodeManifestUnknown         = "MANIFEST_UNKNOWN"
+	errCodeNameInvalid             = "NAME_INVALID"
+	errCodeNameUnknown             = "NAME_UNKNOWN"
+	errCodePaginationNumberInvalid = "PAGINATION_NUMBER_INVALID"
+	errCodeSizeInvalid             = "SIZE_INVALID"
+	errCodeTagInvalid              = "TAG_INVALID"
+	errCodeUnsupported             = "UNSUPPORTED"
+)
+
+type ociError struct {
+	Code    string `json:"code"`
+	Message string `json:"message"`
+	Detail  any    `json:"detail,omitempty"`
+}
+
+func writeOCIError(w http.ResponseWriter, r *http.Request, status int, code string, message string, detail any) {
+	body, err := json.Marshal(struct {
+		Errors []ociError `json:"errors"`
+	}{
+		Errors: []ociError{{Code: code, Message: message, Detail: detail}},
+	})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling error response", "error", err)
+		http.Error(w, message, status)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(status)
+	if _, err := w.Write(body); err != nil {
+		slog.ErrorContext(r.Context(), "error writing error response", "error", err)
+	}
+}
+
+// writeRegistryError maps an error returned by the registry to a response: invalid input and missing
+// objects are client errors with an OCI error code, notFoundCode depending on what was asked for,
+// while backend failures become 502 (storage), 503 (throttled storage, database contention) or 500.
+func writeRegistryError(w http.ResponseWriter, r *http.Request, msg string, notFoundCode string, err error) {
+	switch {
+	case errors.Is(err, ErrDanglingTag):
+		slog.WarnContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusNotFound, notFoundCode, err.Error(), map[string]string{"reason": "dangling tag"})
+	case errors.Is(err, fs.ErrNotExist):
+		slog.DebugContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusNotFound, notFoundCode, err.Error(), nil)
+	case errors.Is(err, ErrInvalidDigest), errors.Is(err, ErrDigestMismatch):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusBadRequest, errCodeDigestInvalid, err.Error(), nil)
+	case errors.Is(err, ErrManifestBlobUnknown):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusBadRequest, errCodeManifestBlobUnknown, err.Error(), nil)
+	case errors.Is(err, ErrManifestMediaTypeNotAllowed), errors.Is(err, ErrPushToAlias):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusForbidden, errCodeDenied, err.Error(), nil)
+	case errors.Is(err, ErrManifestInvalid):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusBadRequest, errCodeManifestInvalid, err.Error(), nil)
+	case errors.Is(err, ErrUploadTooLarge):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusRequestEntityTooLarge, errCodeSizeInvalid, err.Error(), nil)
+	case errors.Is(err, ErrInvalidUploadRange):
+		slog.InfoContext(r.Context(), msg, "error", err)
+		writeOCIError(w, r, http.StatusRequestedRangeNotSatisfiable, errCodeBlobUploadInvalid, err.Error(), nil)
+	case errors.Is(err, ErrDatabaseBusy):
+		slog.WarnContext(r.Context(), msg, "error", err)
+		w.Header().Set("Retry-After", "1")
+		http.Error(w, fmt.Sprintf("%s: %v", msg, err), http.StatusServiceUnavailable)
+	case errors.Is(err, ErrStorageThrottled):
+		slog.WarnContext(r.Context(), msg, "error", err)
+		w.Header().Set("Retry-After", "1")
+		http.Error(w, fmt.Sprintf("%s: %v", msg, err), http.StatusServiceUnavailable)
+	case errors.Is(err, ErrStorage):
+		slog.ErrorContext(r.Context(), msg, "error", err)
+		http.Error(w, fmt.Sprintf("%s: %v", msg, err), http.StatusBadGateway)
+	default:
+		slog.ErrorContext(r.Context(), msg, "error", err)
+		http.Error(w, fmt.Sprintf("%s: %v", msg, err), http.StatusInternalServerError)
+	}
+}
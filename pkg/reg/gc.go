@@ -0,0 +1,77 @@
+package reg
+
+import (
+	"context"
+	"fmt"
+	"log/slog"
+	"strings"
+	"time"
+)
+
+type GCResult struct {
+	Scanned      int
+	Referenced   int
+	Recent       int
+	Deleted      []string
+	DeletedBytes int64
+}
+
+// GarbageCollect deletes blobs not referenced by any manifest known to the database.
+func (r *Registry) GarbageCollect(ctx context.Context, dryRun bool, gracePeriod time.Duration) (*GCResult, error) {
+	// NOTICE: the referenced set is snapshotted before listing the blobs, so blobs uploaded
+	// afterwards look unreferenced - the grace period is what keeps them (and in-progress pushes) alive.
+	referenced, err := r.db.ReferencedDigests()
+	if err != nil {
+		return nil, fmt.Errorf("failed to compute referenced blobs: %w", err)
+	}
+	cutoff := time.Now().Add(-gracePeriod)
+
+	result := &GCResult{}
+	for _, storage := range r.storages() {
+		if err := collectStorage(ctx, storage, r.prefix, referenced, cutoff, dryRun, result); err != nil {
+			return result, err
+		}
+	}
+	return result, nil
+}
+
+// collectStorage sweeps the blobs of a single storage, adding to result.
+func collectStorage(ctx context.Context, storage Storage, storagePrefix string, referenced map[string]struct{}, cutoff time.Time, dryRun bool, result *GCResult) error {
+	prefix := storagePrefix + "/blobs/"
+	var unreferenced []ObjectInfo
+	err := storage.List(ctx, prefix, func(obj ObjectInfo) error {
+		// <prefix>/blobs/<algorithm>/<first two hex chars>/<hex>/data
+		parts := strings.Split(strings.TrimPrefix(obj.Key, prefix), "/")
+		if len(parts) != 4 || parts[3] != "data" {
+			return nil
+		}
+		result.Scanned++
+		if _, ok := referenced[parts[0]+":"+parts[2]]; ok {
+			result.Referenced++
+			return nil
+		}
+		if obj.LastModified.After(cutoff) {
+			result.Recent++
+			return nil
+		}
+		unreferenced = append(unreferenced, obj)
+		return nil
+	})
+	if err != nil {
+		return fmt.Errorf("failed to list blobs: %w", err)
+	}
+
+	for _, obj := range unreferenced {
+		parts := strings.Split(strings.TrimPrefix(obj.Key, prefix), "/")
+		dgst := parts[0] + ":" + parts[2]
+		if !dryRun {
+			if err := storage.DeleteObject(ctx, obj.Key); err != nil {
+				return fmt.Errorf("failed to delete blob %s: %w", dgst, err)
+			}
+		}
+		slog.InfoContext(ctx, "collected blob", "digest", dgst, "size", obj.Size, "dryRun", dryRun)
+		result.Deleted = append(result.Deleted, dgst)
+		result.DeletedBytes += obj.Size
+	}
+	return nil
+}
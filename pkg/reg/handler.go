@@ -3,6 +3,7 @@ package reg
 import (
 	"bytes"
 	"context"
+	"crypto/subtle"
 	"encoding/json"
 	"errors"
 	"fmt"
@@ -11,11 +12,17 @@ import (
 	"log/slog"
 	"net/http"
 	"net/url"
+	"slices"
 	"strconv"
+	"strings"
+	"time"
 
 	"github.com/google/uuid"
 	"github.com/gorilla/mux"
 	lru "github.com/hashicorp/golang-lru/v2"
+	"github.com/opencontainers/go-digest"
+	"github.com/opencontainers/image-spec/specs-go"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
 )
 
 type Handler struct {
@@ -35,17 +42,79 @@ func NewRouter(ctx context.Context, registry *Registry) (*mux.Router, error) {
 	}
 
 	r := mux.NewRouter()
+	r.Use(requestID, accessLog)
+	if tracingEnabled() {
+		r.Use(traceRequests)
+	}
+	if registry.cfg.RequestTimeout > 0 {
+		r.Use(requestTimeout(registry.cfg.RequestTimeout))
+	}
+	r.NotFoundHandler = requestID(accessLog(http.NotFoundHandler()))
+	r.MethodNotAllowedHandler = requestID(accessLog(methodNotAllowed(r)))
 	apiRouter := r.PathPrefix("/v2").Subrouter()
+	if len(registry.cfg.CORSAllowOrigins) > 0 {
+		apiRouter.Use(cors(registry.cfg.CORSAllowOrigins))
+		// NOTICE: preflight requests need a matching route for the middleware to run at all
+		apiRouter.PathPrefix("/").Methods("OPTIONS").Handler(http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+			w.WriteHeader(http.StatusNoContent)
+		}))
+	}
+	if registry.cfg.ReadOnly {
+		apiRouter.Use(readOnly)
+	}
+	if registry.cfg.MaxUploadBytes > 0 {
+		apiRouter.Use(limitRequestBody(registry.cfg.MaxUploadBytes))
+	}
+	apiRouter.Use(validateRepositoryNames, validateReferences)
+	if registry.acl != nil {
+		apiRouter.Use(h.checkACL)
+	}
+
+	// NOTICE: only JSON listings are compressed, blobs (layers are compressed already) and
+	// manifests (served byte for byte) are not
+	jsonHandler := func(handler http.HandlerFunc) http.Handler {
+		if registry.cfg.Compress {
+			return compress(handler)
+		}
+		return handler
+	}
+	// NOTICE: only listings get cache directives, manifests are revalidated with their ETag and
+	// blob redirects expire with their presigned URLs
+	listingHandler := func(handler http.HandlerFunc) http.Handler {
+		if registry.cfg.ListingCacheSeconds > 0 {
+			return cacheControl(registry.cfg.ListingCacheSeconds, jsonHandler(handler))
+		}
+		return jsonHandler(handler)
+	}
 
+	// NOTICE: repository names may contain path segments named like the API suffixes (e.g.
+	// "a/blobs/b" or "x/manifests"), so routes must anchor on the trailing suffix: the greedy
+	// {name:.*} backtracks to the last delimiter followed by a single-segment reference, which
+	// {reference} and {digest} match by default ([^/]+), so extra trailing segments never match
+	// instead of being dropped. Routes with a literal last segment (blobs/uploads/, blobs/list,
+	// tags/list) must be registered before the ones taking that segment as a reference.
 	// end-1: Check API support
 	apiRouter.Handle("/", http.HandlerFunc(h.checkAPISupport)).Methods("GET")
 
+	// custom endpoint 9: list the blobs referenced by a repository's manifests
+	// NOTICE: registered before end-2, which would otherwise take "list" for a digest
+	apiRouter.Handle("/{name:.*}/blobs/list", jsonHandler(h.listRepositoryBlobs)).Methods("GET")
+
 	// end-2: Get blob
 	apiRouter.Handle("/{name:.*}/blobs/{digest}", http.HandlerFunc(h.getBlob)).Methods("GET", "HEAD")
 
 	// end-3: Get manifest
 	apiRouter.Handle("/{name:.*}/manifests/{reference}", http.HandlerFunc(h.getManifest)).Methods("GET", "HEAD")
 
+	// custom endpoint 11: layer history of an image, joined with its config
+	apiRouter.Handle("/{name:.*}/manifests/{reference}/history", jsonHandler(h.getImageHistory)).Methods("GET")
+
+	// end-11: Mount blob from another repository
+	// NOTICE: registered before end-4a, which would otherwise take mounts for plain uploads
+	apiRouter.Handle("/{name:.*}/blobs/uploads/", http.HandlerFunc(h.mountBlob)).
+		Methods("POST").
+		Queries("mount", "{digest}", "from", "{other_name}")
+
 	// end-4b: Start upload with digest
 	apiRouter.Handle("/{name:.*}/blobs/uploads/", http.HandlerFunc(h.startUploadWithDigest)).
 		Methods("POST").
@@ -65,13 +134,8 @@ func NewRouter(ctx context.Context, registry *Registry) (*mux.Router, error) {
 	// end-7: Put manifest
 	apiRouter.Handle("/{name:.*}/manifests/{reference}", http.HandlerFunc(h.putManifest)).Methods("PUT")
 
-	// end-8a: List tags
-	apiRouter.Handle("/{name:.*}/tags/list", http.HandlerFunc(h.listTags)).Methods("GET")
-
-	// end-8b: List tags with pagination
-	apiRouter.Handle("/{name:.*}/tags/list", http.HandlerFunc(h.listTagsPaginated)).
-		Methods("GET").
-		Queries("n", "{n:[0-9]+}", "last", "{last}")
+	// end-8a, end-8b: List tags, optionally paginated with n and last
+	apiRouter.Handle("/{name:.*}/tags/list", listingHandler(h.listTags)).Methods("GET")
 
 	// end-9: Delete manifest
 	apiRouter.Handle("/{name:.*}/manifests/{reference}", http.HandlerFunc(h.deleteManifest)).Methods("DELETE")
@@ -79,47 +143,155 @@ func NewRouter(ctx context.Context, registry *Registry) (*mux.Router, error) {
 	// end-10: Delete blob
 	apiRouter.Handle("/{name:.*}/blobs/{digest}", http.HandlerFunc(h.deleteBlob)).Methods("DELETE")
 
-	// end-11: Mount blob from another repository
-	apiRouter.Handle("/{name:.*}/blobs/uploads/", http.HandlerFunc(h.mountBlob)).
-		Methods("POST").
-		Queries("mount", "{digest}", "from", "{other_name}")
-
-	// end-12a: Get referrers
-	apiRouter.Handle("/{name:.*}/referrers/{digest}", http.HandlerFunc(h.getReferrers)).Methods("GET")
-
 	// end-12b: Get referrers filtered by artifact type
+	// NOTICE: registered first, the unfiltered route would match the filtered requests too
 	apiRouter.Handle("/{name:.*}/referrers/{digest}", http.HandlerFunc(h.getReferrersFiltered)).
 		Methods("GET").
 		Queries("artifactType", "{artifactType}")
 
+	// end-12a: Get referrers
+	apiRouter.Handle("/{name:.*}/referrers/{digest}", http.HandlerFunc(h.getReferrers)).Methods("GET")
+
 	// end-13: Get upload status
 	apiRouter.Handle("/{name:.*}/blobs/uploads/{reference}", http.HandlerFunc(h.getUploadStatus)).Methods("GET")
 
 	// end-14: Cancel upload
 	apiRouter.Handle("/{name:.*}/blobs/uploads/{reference}", http.HandlerFunc(h.cancelUpload)).Methods("DELETE")
 
+	// catalog: list repositories, paginated with n and last
+	apiRouter.Handle("/_catalog", listingHandler(h.getCatalog)).Methods("GET")
+
 	// custom endpoint 1: list all repositories
-	apiRouter.Handle("/repositories", http.HandlerFunc(http.HandlerFunc(h.listRepositories))).
+	apiRouter.Handle("/repositories", jsonHandler(h.listRepositories)).
 		Methods("GET")
 
 	// custom endpoint 2: list all tags
-	apiRouter.Handle("/tags", http.HandlerFunc(h.listAllTags)).Methods("GET")
+	apiRouter.Handle("/tags", jsonHandler(h.listAllTags)).Methods("GET")
 
 	// custom endpoint 3: list all layers
-	apiRouter.Handle("/layers", http.HandlerFunc(h.listLayers)).Methods("GET")
+	apiRouter.Handle("/layers", jsonHandler(h.listLayers)).Methods("GET")
 
 	// custom endpoint 4: list all manifests
-	apiRouter.Handle("/manifests", http.HandlerFunc(h.listManifests)).Methods("GET")
+	apiRouter.Handle("/manifests", jsonHandler(h.listManifests)).Methods("GET")
 
 	// custom endpoint 5: list upload sessions
-	apiRouter.Handle("/upload-sessions", http.HandlerFunc(h.listUploadSessions)).Methods("GET")
+	apiRouter.Handle("/upload-sessions", jsonHandler(h.listUploadSessions)).Methods("GET")
 
 	// custom endpoint 6: get registry stats
-	apiRouter.Handle("/stats", http.HandlerFunc(h.getRegistryStats)).Methods("GET")
+	apiRouter.Handle("/stats", listingHandler(h.getRegistryStats)).Methods("GET")
+
+	// custom endpoint 7: list all manifests of a repository
+	apiRouter.Handle("/{name:.*}/manifests", jsonHandler(h.listRepositoryManifests)).Methods("GET")
+
+	// custom endpoint 8: get repository size
+	apiRouter.Handle("/{name:.*}/size", jsonHandler(h.getRepositorySize)).Methods("GET")
+
+	// custom endpoint 10: list the largest layers of a repository
+	apiRouter.Handle("/{name:.*}/layers/top", jsonHandler(h.listLargestLayers)).Methods("GET")
+
+	// root: identify the service to humans and health checkers probing / instead of /v2/
+	r.Handle("/", http.HandlerFunc(h.getRoot)).Methods("GET", "HEAD")
+
+	// version: build information of the running binary
+	r.Handle("/version", http.HandlerFunc(h.getVersion)).Methods("GET")
+
+	// liveness: the process is up and serving requests, regardless of its backends
+	r.Handle("/livez", http.HandlerFunc(h.livez)).Methods("GET", "HEAD")
+
+	// readiness: the database and storage are reachable, /healthz is kept as an alias
+	r.Handle("/readyz", http.HandlerFunc(h.readyz)).Methods("GET", "HEAD")
+	r.Handle("/healthz", http.HandlerFunc(h.readyz)).Methods("GET", "HEAD")
+
+	adminRouter := r.PathPrefix("/admin").Subrouter()
+	adminRouter.Use(h.requireAdminToken)
+
+	// admin endpoint 1: preload a repository into the database
+	adminRouter.Handle("/warm/{name:.*}", http.HandlerFunc(h.warmRepository)).Methods("POST")
+
+	// admin endpoint 2: resolve many manifest references at once
+	adminRouter.Handle("/manifests/resolve", jsonHandler(h.resolveManifests)).Methods("POST")
+
+	// admin endpoint 3: drop a cached manifest, so that it is resolved from storage again
+	adminRouter.Handle("/cache/{name:.*}/manifests/{reference}", http.HandlerFunc(h.invalidateCache)).Methods("DELETE")
+
+	// admin endpoint 4: drop everything cached about a repository
+	adminRouter.Handle("/cache/{name:.*}", http.HandlerFunc(h.invalidateCache)).Methods("DELETE")
+
+	// admin endpoint 5: search cached repositories by name prefix
+	adminRouter.Handle("/search", jsonHandler(h.searchRepositories)).Methods("GET")
+
+	// admin endpoint 6: check that the blobs referenced by a repository exist in storage
+	adminRouter.Handle("/reconcile/{name:.*}", jsonHandler(h.reconcileRepository)).Methods("GET")
+
+	// admin endpoint 7: make a repository name an alias of another, or drop the alias
+	adminRouter.Handle("/aliases/{name:.*}", http.HandlerFunc(h.setAlias)).Methods("PUT")
+	adminRouter.Handle("/aliases/{name:.*}", http.HandlerFunc(h.deleteAlias)).Methods("DELETE")
+
+	// admin endpoint 8: dump every cached manifest as newline-delimited JSON, optionally of one repository
+	adminRouter.Handle("/export", http.HandlerFunc(h.exportManifests)).Methods("GET")
 
 	return r, nil
 }
 
+func (h *Handler) requireAdminToken(next http.Handler) http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		token := h.registry.cfg.AdminToken
+		if token == "" {
+			http.Error(w, "admin endpoints are disabled, see --admin-token", http.StatusForbidden)
+			return
+		}
+		provided, ok := strings.CutPrefix(r.Header.Get("Authorization"), "Bearer ")
+		if !ok || subtle.ConstantTimeCompare([]byte(provided), []byte(token)) != 1 {
+			w.Header().Set("WWW-Authenticate", `Bearer realm="reg-admin"`)
+			http.Error(w, "invalid admin token", http.StatusUnauthorized)
+			return
+		}
+		next.ServeHTTP(w, r)
+	})
+}
+
+func (h *Handler) getRoot(w http.ResponseWriter, r *http.Request) {
+	h.writeJSON(w, r, "root", map[string]string{
+		"service": "reg",
+		"version": ReadBuildInfo().Version,
+		"api":     "/v2/",
+	})
+}
+
+func (h *Handler) getVersion(w http.ResponseWriter, r *http.Request) {
+	h.writeJSON(w, r, "version", ReadBuildInfo())
+}
+
+func (h *Handler) writeJSON(w http.ResponseWriter, r *http.Request, what string, value any) {
+	marshaled, err := json.Marshal(value)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling "+what, "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling %s: %v", what, err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	if _, err := w.Write(marshaled); err != nil {
+		slog.ErrorContext(r.Context(), "error writing "+what+" response", "error", err)
+	}
+}
+
+func (h *Handler) livez(w http.ResponseWriter, r *http.Request) {
+	w.WriteHeader(http.StatusOK)
+}
+
+const readinessTimeout = 5 * time.Second
+
+func (h *Handler) readyz(w http.ResponseWriter, r *http.Request) {
+	ctx, cancel := context.WithTimeout(r.Context(), readinessTimeout)
+	defer cancel()
+	if err := h.registry.CheckReady(ctx); err != nil {
+		slog.WarnContext(r.Context(), "readiness check failed", "error", err)
+		http.Error(w, fmt.Sprintf("not ready: %v", err), http.StatusServiceUnavailable)
+		return
+	}
+	w.WriteHeader(http.StatusOK)
+}
+
 func (h *Handler) checkAPISupport(w http.ResponseWriter, r *http.Request) {
 	w.WriteHeader(http.StatusOK)
 }
@@ -131,7 +303,7 @@ func (h *Handler) getBlob(w http.ResponseWriter, r *http.Request) {
 
 	if h.blobCache != nil {
 		if blobData, ok := h.blobCache.Get(digest); ok {
-			slog.Debug("blob cache hit", "digest", digest)
+			slog.DebugContext(r.Context(), "blob cache hit", "digest", digest)
 			w.Header().Set("Content-Type", "application/octet-stream")
 			w.Header().Set("Content-Length", fmt.Sprintf("%d", len(blobData)))
 			w.Header().Set("Docker-Content-Digest", digest)
@@ -143,7 +315,7 @@ func (h *Handler) getBlob(w http.ResponseWriter, r *http.Request) {
 
 			_, err := w.Write(blobData)
 			if err != nil {
-				slog.Error("error writing blob from cache", "error", err)
+				slog.ErrorContext(r.Context(), "error writing blob from cache", "error", err)
 				http.Error(w, fmt.Sprintf("error writing blob: %v", err), http.StatusInternalServerError)
 				return
 			}
@@ -151,37 +323,120 @@ func (h *Handler) getBlob(w http.ResponseWriter, r *http.Request) {
 		}
 	}
 
-	presignedURL, err := h.registry.getBlobRedirect(r.Context(), name, digest, r.Method)
-	if err != nil {
-		if errors.Is(err, fs.ErrNotExist) {
-			http.Error(w, fmt.Sprintf("blob not found: %v", err), http.StatusNotFound)
+	if r.Method == "HEAD" {
+		size, err := h.registry.statBlob(r.Context(), name, digest)
+		if err != nil {
+			writeRegistryError(w, r, "error checking blob", errCodeBlobUnknown, err)
 			return
 		}
-		slog.Error("error getting blob redirect", "error", err)
-		http.Error(w, fmt.Sprintf("error getting blob redirect: %v", err), http.StatusInternalServerError)
+
+		w.Header().Set("Content-Type", "application/octet-stream")
+		w.Header().Set("Content-Length", fmt.Sprintf("%d", size))
+		w.Header().Set("Docker-Content-Digest", digest)
+		w.WriteHeader(http.StatusOK)
 		return
 	}
 
-	if r.Method == "HEAD" {
-		exists, err := h.registry.hasBlob(r.Context(), digest)
-		if err != nil {
-			slog.Error("error checking blob existence", "error", err)
-			http.Error(w, fmt.Sprintf("error checking blob: %v", err), http.StatusInternalServerError)
+	if h.registry.cfg.InternalBucket {
+		slog.DebugContext(r.Context(), "proxying blob from internal-only bucket", "digest", digest)
+		h.proxyBlob(w, r, name, digest)
+		return
+	}
+
+	presignedURL, expiresAt, err := h.registry.getBlobRedirect(r.Context(), name, digest, r.Method)
+	if errors.Is(err, ErrPresignUnsupported) {
+		h.proxyBlob(w, r, name, digest)
+		return
+	}
+	if err != nil {
+		if h.registry.cfg.ProxyFallback && !errors.Is(err, ErrInvalidDigest) && !errors.Is(err, fs.ErrNotExist) {
+			slog.WarnContext(r.Context(), "presigning failed, proxying blob instead", "digest", digest, "error", err)
+			h.proxyBlob(w, r, name, digest)
 			return
 		}
+		writeRegistryError(w, r, "error getting blob redirect", errCodeBlobUnknown, err)
+		return
+	}
+
+	// NOTICE: caches in front of the registry must not hand out the redirect after the URL expires
+	maxAge := max(int(time.Until(expiresAt).Seconds()), 0)
+	w.Header().Set("X-Presign-Expires-At", expiresAt.UTC().Format(time.RFC3339))
+	w.Header().Set("Cache-Control", fmt.Sprintf("private, max-age=%d", maxAge))
+	// NOTICE: not Content-Length, which would describe the (empty) body of the redirect itself
+	if size, ok := h.registry.cachedBlobSize(r.Context(), digest); ok {
+		w.Header().Set("X-Blob-Size", strconv.FormatInt(size, 10))
+	}
+	http.Redirect(w, r, presignedURL, http.StatusFound)
+}
+
+func (h *Handler) proxyBlob(w http.ResponseWriter, r *http.Request, name string, digest string) {
+	size, err := h.registry.statBlob(r.Context(), name, digest)
+	if err != nil {
+		writeRegistryError(w, r, "error checking blob", errCodeBlobUnknown, err)
+		return
+	}
 
-		if !exists {
-			http.Error(w, "blob not found", http.StatusNotFound)
+	start, end := int64(0), size-1
+	status := http.StatusOK
+	if rangeHeader := r.Header.Get("Range"); rangeHeader != "" {
+		start, end, err = parseByteRange(rangeHeader, size)
+		if err != nil {
+			w.Header().Set("Content-Range", fmt.Sprintf("bytes */%d", size))
+			http.Error(w, fmt.Sprintf("invalid range: %v", err), http.StatusRequestedRangeNotSatisfiable)
 			return
 		}
+		status = http.StatusPartialContent
+		w.Header().Set("Content-Range", fmt.Sprintf("bytes %d-%d/%d", start, end, size))
+	}
 
-		w.Header().Set("Content-Type", "application/octet-stream")
-		w.Header().Set("Docker-Content-Digest", digest)
-		w.WriteHeader(http.StatusOK)
+	body, err := h.registry.openBlob(r.Context(), name, digest, start, end-start+1)
+	if err != nil {
+		writeRegistryError(w, r, "error opening blob", errCodeBlobUnknown, err)
+		return
+	}
+	defer body.Close()
+
+	w.Header().Set("Content-Type", "application/octet-stream")
+	w.Header().Set("Content-Length", fmt.Sprintf("%d", end-start+1))
+	w.Header().Set("Accept-Ranges", "bytes")
+	w.Header().Set("Docker-Content-Digest", digest)
+	w.WriteHeader(status)
+	if h.registry.cfg.VerifyBlobs && status == http.StatusOK && size > 0 {
+		streamVerifiedBlob(w, r, body, name, digest, size)
 		return
 	}
+	if _, err := io.Copy(w, body); err != nil {
+		slog.ErrorContext(r.Context(), "error streaming blob", "digest", digest, "error", err)
+	}
+}
 
-	http.Redirect(w, r, presignedURL, http.StatusFound)
+// streamVerifiedBlob streams a whole blob while hashing it, holding back the last byte until the
+// digest checks out. On a mismatch the connection is aborted, so that the client ends up with
+// a truncated transfer rather than a complete-looking corrupted blob.
+func streamVerifiedBlob(w http.ResponseWriter, r *http.Request, body io.Reader, name string, dgst string, size int64) {
+	parsed, err := digest.Parse(dgst)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error verifying blob", "digest", dgst, "error", err)
+		panic(http.ErrAbortHandler)
+	}
+	verifier := parsed.Verifier()
+	content := io.TeeReader(body, verifier)
+	if _, err := io.CopyN(w, content, size-1); err != nil {
+		slog.ErrorContext(r.Context(), "error streaming blob", "digest", dgst, "error", err)
+		return
+	}
+	last := make([]byte, 1)
+	if _, err := io.ReadFull(content, last); err != nil {
+		slog.ErrorContext(r.Context(), "error streaming blob", "digest", dgst, "error", err)
+		return
+	}
+	if !verifier.Verified() {
+		slog.ErrorContext(r.Context(), "BLOB CORRUPTED: stored content does not match its digest, aborting the transfer", "repo", name, "digest", dgst, "size", size)
+		panic(http.ErrAbortHandler)
+	}
+	if _, err := w.Write(last); err != nil {
+		slog.ErrorContext(r.Context(), "error streaming blob", "digest", dgst, "error", err)
+	}
 }
 
 func (h *Handler) getManifest(w http.ResponseWriter, r *http.Request) {
@@ -191,25 +446,123 @@ func (h *Handler) getManifest(w http.ResponseWriter, r *http.Request) {
 
 	manifest, manifestBytes, err := h.registry.getManifest(r.Context(), name, reference)
 	if err != nil {
-		slog.Error("error getting manifest", "error", err)
-		if errors.Is(err, fs.ErrNotExist) {
-			http.Error(w, fmt.Sprintf("manifest not found: %v", err), http.StatusNotFound)
+		writeRegistryError(w, r, "error getting manifest", h.manifestNotFoundCode(r.Context(), name, err), err)
+		return
+	}
+
+	// NOTICE: manifests cached before the allowlist was narrowed are refused too
+	mediaType := manifestMediaType(manifestBytes, manifest)
+	if !h.registry.mediaTypeAllowed(mediaType) {
+		writeRegistryError(w, r, "error getting manifest", errCodeManifestUnknown, fmt.Errorf("%w: %s", ErrManifestMediaTypeNotAllowed, mediaType))
+		return
+	}
+
+	dgst := digest.FromBytes(manifestBytes)
+	if fields := r.URL.Query().Get("fields"); fields != "" {
+		h.writeManifestFields(w, r, fields, dgst, mediaType, int64(len(manifestBytes)))
+		return
+	}
+	etag := fmt.Sprintf("%q", dgst)
+	w.Header().Set("ETag", etag)
+	w.Header().Set("Docker-Content-Digest", dgst.String())
+	if etagMatches(r.Header.Get("If-None-Match"), etag) {
+		w.WriteHeader(http.StatusNotModified)
+		return
+	}
+
+	// NOTICE: the stored bytes are served verbatim, re-encoding them would change the digest
+	w.Header().Set("Content-Type", mediaType)
+	w.Header().Set("Accept-Ranges", "bytes")
+	status := http.StatusOK
+	body := manifestBytes
+	if rangeHeader := r.Header.Get("Range"); rangeHeader != "" {
+		size := int64(len(manifestBytes))
+		start, end, err := parseByteRange(rangeHeader, size)
+		if err != nil {
+			w.Header().Set("Content-Range", fmt.Sprintf("bytes */%d", size))
+			http.Error(w, fmt.Sprintf("invalid range: %v", err), http.StatusRequestedRangeNotSatisfiable)
 			return
 		}
-		http.Error(w, fmt.Sprintf("error getting manifest: %v", err), http.StatusInternalServerError)
+		status = http.StatusPartialContent
+		body = manifestBytes[start : end+1]
+		w.Header().Set("Content-Range", fmt.Sprintf("bytes %d-%d/%d", start, end, size))
+	}
+	w.Header().Set("Content-Length", fmt.Sprintf("%d", len(body)))
+	w.WriteHeader(status)
+	if r.Method == "HEAD" {
 		return
 	}
-
-	w.Header().Set("Content-Type", manifest.MediaType)
-	w.Header().Set("Content-Length", fmt.Sprintf("%d", len(manifestBytes)))
-	_, err = w.Write(manifestBytes)
+	_, err = w.Write(body)
 	if err != nil {
-		slog.Error("error writing manifest response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing manifest response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing manifest response: %v", err), http.StatusInternalServerError)
 		return
 	}
 }
 
+// manifestFields are the fields which can be requested with ?fields= instead of the whole manifest.
+var manifestFields = []string{"digest", "mediaType", "size"}
+
+// writeManifestFields answers ?fields=digest,mediaType,size with a JSON object holding just those,
+// for clients which only need to know what a reference points at. The manifest itself comes from
+// the database or the in-memory cache like for full responses, so cached ones never hit storage.
+func (h *Handler) writeManifestFields(w http.ResponseWriter, r *http.Request, fields string, dgst digest.Digest, mediaType string, size int64) {
+	values := map[string]any{"digest": dgst.String(), "mediaType": mediaType, "size": size}
+	trimmed := make(map[string]any)
+	for _, field := range strings.Split(fields, ",") {
+		field = strings.TrimSpace(field)
+		if !slices.Contains(manifestFields, field) {
+			writeOCIError(w, r, http.StatusBadRequest, errCodeUnsupported, "unknown manifest field, expected one of "+strings.Join(manifestFields, ", "), map[string]string{"field": field})
+			return
+		}
+		trimmed[field] = values[field]
+	}
+
+	marshaledFields, err := json.Marshal(trimmed)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling manifest fields", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling manifest fields: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Docker-Content-Digest", dgst.String())
+	w.Header().Set("Content-Type", "application/json")
+	w.Header().Set("Content-Length", fmt.Sprintf("%d", len(marshaledFields)))
+	if r.Method == "HEAD" {
+		return
+	}
+	_, err = w.Write(marshaledFields)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing manifest fields response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing manifest fields response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+// manifestNotFoundCode tells a missing manifest of a known repository (MANIFEST_UNKNOWN) apart
+// from a repository which doesn't exist at all (NAME_UNKNOWN), as the spec asks for.
+func (h *Handler) manifestNotFoundCode(ctx context.Context, name string, err error) string {
+	if !errors.Is(err, fs.ErrNotExist) {
+		return errCodeManifestUnknown
+	}
+	exists, existsErr := h.registry.repositoryKnown(ctx, name)
+	if existsErr == nil && !exists {
+		return errCodeNameUnknown
+	}
+	return errCodeManifestUnknown
+}
+
+// etagMatches reports whether an If-None-Match header lists the given (quoted) ETag.
+// Weak validators compare equal to strong ones, as RFC 9110 prescribes for If-None-Match.
+func etagMatches(header string, etag string) bool {
+	for _, candidate := range strings.Split(header, ",") {
+		candidate = strings.TrimPrefix(strings.TrimSpace(candidate), "W/")
+		if candidate == "*" || candidate == etag {
+			return true
+		}
+	}
+	return false
+}
+
 func (h *Handler) startUpload(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
 	name := vars["name"]
@@ -217,20 +570,48 @@ func (h *Handler) startUpload(w http.ResponseWriter, r *http.Request) {
 
 	err := h.registry.startUpload(r.Context(), name, uploadId)
 	if err != nil {
-		slog.Error("error starting upload", "error", err)
+		slog.ErrorContext(r.Context(), "error starting upload", "error", err)
 		http.Error(w, fmt.Sprintf("error starting upload: %v", err), http.StatusInternalServerError)
 		return
 	}
 
-	w.Header().Set("Location", fmt.Sprintf("v2/%s/blobs/uploads/%s", name, uploadId))
+	writeUploadAccepted(w, name, uploadId, 0)
+}
+
+func writeUploadAccepted(w http.ResponseWriter, name string, uploadId string, uploadedSize int64) {
+	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/uploads/%s", name, uploadId))
+	w.Header().Set("Docker-Upload-UUID", uploadId)
+	setUploadRange(w, uploadedSize)
 	w.WriteHeader(http.StatusAccepted)
 }
 
+// setUploadRange reports the bytes received so far. The range is inclusive, so an empty
+// upload has none to report.
+func setUploadRange(w http.ResponseWriter, uploadedSize int64) {
+	if uploadedSize > 0 {
+		w.Header().Set("Range", fmt.Sprintf("0-%d", uploadedSize-1))
+	}
+}
+
+func writeUploadCreated(w http.ResponseWriter, name string, digest string) {
+	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/%s", name, digest))
+	w.Header().Set("Docker-Content-Digest", digest)
+	w.WriteHeader(http.StatusCreated)
+}
+
+func writeUploadError(w http.ResponseWriter, r *http.Request, msg string, err error) {
+	writeRegistryError(w, r, msg, errCodeBlobUploadUnknown, err)
+}
+
 func parseContentRange(fRange string) (int64, int64, error) {
+	if fRange == "" {
+		return 0, int64(1<<63 - 1), nil
+	}
+	// NOTICE: OCI clients send a bare "<start>-<end>", older ones an HTTP-style "bytes=<start>-<end>"
 	var startOffset, endOffset int64
-	_, err := fmt.Sscanf(fRange, "bytes=%d-%d", &startOffset, &endOffset)
+	_, err := fmt.Sscanf(strings.TrimPrefix(fRange, "bytes="), "%d-%d", &startOffset, &endOffset)
 	if err != nil {
-		return 0, int64(1<<63 - 1), nil
+		return 0, 0, fmt.Errorf("%w %q: %v", ErrInvalidUploadRange, fRange, err)
 	}
 	// NOTICE: The endOffset is inclusive in the Content-Range header, but we need to
 	// convert it to exclusive for our internal representation.
@@ -238,6 +619,47 @@ func parseContentRange(fRange string) (int64, int64, error) {
 	return startOffset, endOffset, nil
 }
 
+// parseByteRange parses a Range request header against an object of the given size and returns
+// the inclusive start and end offsets. Multi-range requests are served with their first range only.
+func parseByteRange(header string, size int64) (int64, int64, error) {
+	spec, found := strings.CutPrefix(header, "bytes=")
+	if !found {
+		return 0, 0, fmt.Errorf("unsupported range unit in %q", header)
+	}
+	spec, _, _ = strings.Cut(spec, ",")
+	startStr, endStr, found := strings.Cut(strings.TrimSpace(spec), "-")
+	if !found {
+		return 0, 0, fmt.Errorf("malformed range %q", header)
+	}
+
+	var start, end int64
+	if startStr == "" {
+		suffix, err := strconv.ParseInt(endStr, 10, 64)
+		if err != nil || suffix <= 0 {
+			return 0, 0, fmt.Errorf("malformed range %q", header)
+		}
+		start, end = max(size-suffix, 0), size-1
+	} else {
+		var err error
+		start, err = strconv.ParseInt(startStr, 10, 64)
+		if err != nil || start < 0 {
+			return 0, 0, fmt.Errorf("malformed range %q", header)
+		}
+		end = size - 1
+		if endStr != "" {
+			end, err = strconv.ParseInt(endStr, 10, 64)
+			if err != nil || end < start {
+				return 0, 0, fmt.Errorf("malformed range %q", header)
+			}
+			end = min(end, size-1)
+		}
+	}
+	if start >= size {
+		return 0, 0, fmt.Errorf("range %q not satisfiable for size %d", header, size)
+	}
+	return start, end, nil
+}
+
 func (h *Handler) startUploadWithDigest(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
 	name := vars["name"]
@@ -246,7 +668,7 @@ func (h *Handler) startUploadWithDigest(w http.ResponseWriter, r *http.Request)
 
 	err := h.registry.startUpload(r.Context(), name, uploadId)
 	if err != nil {
-		slog.Error("error starting upload", "error", err)
+		slog.ErrorContext(r.Context(), "error starting upload", "error", err)
 		http.Error(w, fmt.Sprintf("error starting upload: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -254,47 +676,43 @@ func (h *Handler) startUploadWithDigest(w http.ResponseWriter, r *http.Request)
 	if len(r.Header.Get("Content-Length")) > 0 && r.Header.Get("Content-Length") != "0" {
 		contentLength, err := strconv.ParseInt(r.Header.Get("Content-Length"), 10, 64)
 		if err != nil {
-			slog.Error("error parsing content length", "error", err)
+			slog.ErrorContext(r.Context(), "error parsing content length", "error", err)
 			http.Error(w, fmt.Sprintf("error parsing content length: %v", err), http.StatusBadRequest)
 			return
 		}
 
 		var blobReader io.ReadCloser = r.Body
+		var blobData []byte
 		if contentLength <= 8192 {
-			var blobData []byte
 			blobData, err = io.ReadAll(r.Body)
 			if err != nil {
-				slog.Error("error reading blob data", "error", err)
+				slog.ErrorContext(r.Context(), "error reading blob data", "error", err)
 				http.Error(w, fmt.Sprintf("error reading blob data: %v", err), http.StatusInternalServerError)
 				return
 			}
-			if h.blobCache != nil {
-				h.blobCache.Add(digest, blobData)
-			}
 			blobReader = io.NopCloser(bytes.NewReader(blobData))
 		}
 
-		_, err = h.registry.uploadChunk(r.Context(), uploadId, 0, blobReader)
+		_, err = h.registry.uploadChunk(r.Context(), name, uploadId, 0, blobReader)
 		if err != nil {
-			slog.Error("error uploading chunk", "error", err)
-			http.Error(w, fmt.Sprintf("error uploading chunk: %v", err), http.StatusInternalServerError)
+			writeUploadError(w, r, "error uploading chunk", err)
 			return
 		}
 
-		err = h.registry.completeUpload(r.Context(), uploadId, digest)
+		err = h.registry.completeUpload(r.Context(), name, uploadId, digest)
 		if err != nil {
-			slog.Error("error completing upload", "error", err)
-			http.Error(w, fmt.Sprintf("error completing upload: %v", err), http.StatusInternalServerError)
+			writeUploadError(w, r, "error completing upload", err)
 			return
 		}
+		if h.blobCache != nil && blobData != nil {
+			h.blobCache.Add(digest, blobData)
+		}
 
-		w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/%s", name, digest))
-		w.WriteHeader(http.StatusCreated)
+		writeUploadCreated(w, name, digest)
 		return
 	}
 
-	w.Header().Set("Location", fmt.Sprintf("v2/%s/blobs/uploads/%s", name, uploadId))
-	w.WriteHeader(http.StatusAccepted)
+	writeUploadAccepted(w, name, uploadId, 0)
 }
 
 func (h *Handler) uploadChunk(w http.ResponseWriter, r *http.Request) {
@@ -305,22 +723,29 @@ func (h *Handler) uploadChunk(w http.ResponseWriter, r *http.Request) {
 	fRange := r.Header.Get("Content-Range")
 	startOffset, endOffset, err := parseContentRange(fRange)
 	if err != nil {
-		slog.Error("error parsing content range", "error", err)
+		slog.ErrorContext(r.Context(), "error parsing content range", "error", err)
 		http.Error(w, fmt.Sprintf("error parsing content range: %v", err), http.StatusBadRequest)
 		return
 	}
-	slog.Debug("uploadChunk", "ref", reference, "range", fRange, "start", startOffset, "end", endOffset)
+	slog.DebugContext(r.Context(), "uploadChunk", "ref", reference, "range", fRange, "start", startOffset, "end", endOffset)
+
+	if fRange == "" {
+		// NOTICE: chunks sent without Content-Range (e.g. a streamed upload) are appended
+		session, err := h.registry.getUploadSession(name, reference)
+		if err != nil {
+			writeUploadError(w, r, "error getting upload session", err)
+			return
+		}
+		startOffset = session.UploadedSize
+	}
 
-	n, err := h.registry.uploadChunk(r.Context(), reference, startOffset, r.Body)
+	n, err := h.registry.uploadChunk(r.Context(), name, reference, startOffset, r.Body)
 	if err != nil {
-		slog.Error("error uploading chunk", "error", err)
-		http.Error(w, fmt.Sprintf("error uploading chunk: %v", err), http.StatusInternalServerError)
+		writeUploadError(w, r, "error uploading chunk", err)
 		return
 	}
 
-	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/uploads/%s", name, reference))
-	w.Header().Set("Range", fmt.Sprintf("bytes=%d-%d", startOffset, startOffset+n-1))
-	w.WriteHeader(http.StatusAccepted)
+	writeUploadAccepted(w, name, reference, startOffset+n)
 }
 
 func (h *Handler) completeUpload(w http.ResponseWriter, r *http.Request) {
@@ -329,38 +754,71 @@ func (h *Handler) completeUpload(w http.ResponseWriter, r *http.Request) {
 	reference := vars["reference"]
 	digest := vars["digest"]
 
-	err := h.registry.completeUpload(r.Context(), reference, digest)
+	// NOTICE: the closing request may carry the last chunk, or the whole blob for monolithic uploads;
+	// a chunked body has an unknown length (-1)
+	if r.ContentLength != 0 {
+		session, err := h.registry.getUploadSession(name, reference)
+		if err != nil {
+			writeUploadError(w, r, "error getting upload session", err)
+			return
+		}
+		if _, err := h.registry.uploadChunk(r.Context(), name, reference, session.UploadedSize, r.Body); err != nil {
+			writeUploadError(w, r, "error uploading chunk", err)
+			return
+		}
+	}
+
+	err := h.registry.completeUpload(r.Context(), name, reference, digest)
 	if err != nil {
-		slog.Error("error completing upload", "error", err)
-		http.Error(w, fmt.Sprintf("error completing upload: %v", err), http.StatusInternalServerError)
+		writeUploadError(w, r, "error completing upload", err)
 		return
 	}
 
-	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/%s", name, digest))
-	w.WriteHeader(http.StatusCreated)
+	writeUploadCreated(w, name, digest)
 }
 
 func (h *Handler) putManifest(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
 	name := vars["name"]
 	reference := vars["reference"]
-	slog.Warn("putManifest", "name", name, "reference", reference)
+	slog.DebugContext(r.Context(), "putManifest", "name", name, "reference", reference)
 
-	manifestBytes, err := io.ReadAll(r.Body)
+	manifestBytes, err := h.registry.readManifest(r.Body)
+	if errors.Is(err, ErrManifestTooLarge) {
+		writeOCIError(w, r, http.StatusRequestEntityTooLarge, errCodeManifestInvalid, err.Error(), nil)
+		return
+	}
 	if err != nil {
-		slog.Error("error reading manifest body", "error", err)
+		slog.ErrorContext(r.Context(), "error reading manifest body", "error", err)
 		http.Error(w, fmt.Sprintf("error reading manifest body: %v", err), http.StatusInternalServerError)
 		return
 	}
-	err = h.registry.putManifest(r.Context(), name, reference, manifestBytes)
+
+	mediaType, _, _ := strings.Cut(r.Header.Get("Content-Type"), ";")
+	mediaType = strings.TrimSpace(mediaType)
+	if mediaType == "" {
+		var manifest v1.Manifest
+		if err := json.Unmarshal(manifestBytes, &manifest); err == nil {
+			mediaType = manifestMediaType(manifestBytes, &manifest)
+		}
+	}
+	if !h.registry.mediaTypeAllowed(mediaType) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeManifestInvalid, "unsupported manifest media type", map[string]string{"mediaType": mediaType})
+		return
+	}
+
+	dgst, err := h.registry.putManifest(r.Context(), name, reference, manifestBytes)
 	if err != nil {
-		slog.Error("error putting manifest", "error", err)
-		http.Error(w, fmt.Sprintf("error putting manifest: %v", err), http.StatusInternalServerError)
+		writeRegistryError(w, r, "error putting manifest", errCodeManifestUnknown, err)
 		return
 	}
-	w.Header().Set("Location", fmt.Sprintf("/v2/%s/manifests/%s", name, reference))
+	w.Header().Set("Location", fmt.Sprintf("/v2/%s/manifests/%s", name, dgst))
+	w.Header().Set("Docker-Content-Digest", dgst.String())
+	var manifest v1.Manifest
+	if err := json.Unmarshal(manifestBytes, &manifest); err == nil && manifest.Subject != nil {
+		w.Header().Set("OCI-Subject", manifest.Subject.Digest.String())
+	}
 	w.WriteHeader(http.StatusCreated)
-	fmt.Printf("Put manifest for %s with reference %s\n", name, reference)
 }
 
 type tags struct {
@@ -374,46 +832,77 @@ func (h *Handler) listTags(w http.ResponseWriter, r *http.Request) {
 
 	repoTags, err := h.registry.listTags(r.Context(), name)
 	if err != nil {
-		slog.Error("error listing tags", "error", err)
-		http.Error(w, fmt.Sprintf("error listing tags: %v", err), http.StatusInternalServerError)
+		writeRegistryError(w, r, "error listing tags", errCodeNameUnknown, err)
 		return
 	}
 
+	// NOTICE: tags are sorted lexically, so "last" works as a cursor even if it was deleted since
+	if last := r.URL.Query().Get("last"); last != "" {
+		i, _ := slices.BinarySearch(repoTags, last)
+		for i < len(repoTags) && repoTags[i] <= last {
+			i++
+		}
+		repoTags = repoTags[i:]
+	}
+	if nParam := r.URL.Query().Get("n"); nParam != "" {
+		n, err := strconv.Atoi(nParam)
+		if err != nil || n < 0 {
+			writeOCIError(w, r, http.StatusBadRequest, errCodePaginationNumberInvalid, "invalid number of results requested", map[string]string{"n": nParam})
+			return
+		}
+		// NOTICE: n=0 asks for an empty page, which has no next page to link to
+		if n == 0 {
+			repoTags = []string{}
+		} else if n < len(repoTags) {
+			repoTags = repoTags[:n]
+			w.Header().Set(
+				"Link",
+				fmt.Sprintf("</v2/%s/tags/list?n=%d&last=%s>; rel=\"next\"", name, n, url.QueryEscape(repoTags[n-1])),
+			)
+		}
+	}
+
 	marshaledTags, err := json.Marshal(tags{
 		Name: name,
 		Tags: repoTags,
 	})
 	if err != nil {
-		slog.Error("error marshalling tags", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling tags", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling tags: %v", err), http.StatusInternalServerError)
 		return
 	}
 	w.Header().Set("Content-Type", "application/json")
 	_, err = w.Write(marshaledTags)
 	if err != nil {
-		slog.Error("error writing tags response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing tags response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing tags response: %v", err), http.StatusInternalServerError)
 		return
 	}
 }
 
-func (h *Handler) listTagsPaginated(w http.ResponseWriter, r *http.Request) {
-	vars := mux.Vars(r)
-	name := vars["name"]
-	n := vars["n"]
-	last := vars["last"]
-
-	w.WriteHeader(http.StatusOK)
-	fmt.Printf(`{"name":"%s", "tags":["tag4", "tag5"], "n":%s, "last":"%s"}`, name, n, last)
-}
-
 func (h *Handler) deleteManifest(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
 	name := vars["name"]
 	reference := vars["reference"]
 
+	if !h.registry.cfg.EnableDelete {
+		http.Error(w, "manifest deletion is disabled", http.StatusMethodNotAllowed)
+		return
+	}
+
+	// NOTICE: deleting by tag only untags, the manifest stays reachable by its digest
+	var err error
+	if dgst, parseErr := digest.Parse(reference); parseErr == nil {
+		err = h.registry.deleteManifest(r.Context(), name, dgst)
+	} else {
+		err = h.registry.deleteTag(r.Context(), name, reference)
+	}
+	if err != nil {
+		writeRegistryError(w, r, "error deleting manifest", errCodeManifestUnknown, err)
+		return
+	}
+
 	w.WriteHeader(http.StatusAccepted)
-	fmt.Printf("Deleting manifest for %s with reference %s", name, reference)
 }
 
 func (h *Handler) deleteBlob(w http.ResponseWriter, r *http.Request) {
@@ -425,69 +914,143 @@ func (h *Handler) deleteBlob(w http.ResponseWriter, r *http.Request) {
 	fmt.Printf("Deleting blob for %s with digest %s", name, digest)
 }
 
+// mountBlob answers 201 when the blob can be mounted, otherwise it starts a regular upload
+// (202), which the client follows as if it hadn't asked for a mount.
 func (h *Handler) mountBlob(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
 	name := vars["name"]
 	digest := vars["digest"]
 	otherName := vars["other_name"]
 
-	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/%s", name, digest))
-	w.WriteHeader(http.StatusCreated)
-	fmt.Printf("Mounted blob from %s to %s with digest %s", otherName, name, digest)
+	err := h.registry.mountBlob(r.Context(), name, otherName, digest)
+	if errors.Is(err, fs.ErrNotExist) || errors.Is(err, ErrMountUnavailable) {
+		slog.DebugContext(r.Context(), "blob not mountable, starting an upload", "from", otherName, "digest", digest, "error", err)
+		h.startUpload(w, r)
+		return
+	}
+	if err != nil {
+		writeRegistryError(w, r, "error mounting blob", errCodeBlobUnknown, err)
+		return
+	}
+	writeUploadCreated(w, name, digest)
 }
 
 func (h *Handler) getReferrers(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
-	name := vars["name"]
-	digest := vars["digest"]
-
-	w.WriteHeader(http.StatusOK)
-	fmt.Printf(`{"name":"%s", "digest":"%s", "referrers":[{"mediaType":"application/example", "digest":"sha256:abc"}]}`, name, digest)
+	h.writeReferrers(w, r, vars["name"], vars["digest"], "")
 }
 
 func (h *Handler) getReferrersFiltered(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
-	name := vars["name"]
-	digest := vars["digest"]
-	artifactType := vars["artifactType"]
-
-	w.WriteHeader(http.StatusOK)
-	fmt.Printf(`{"name":"%s", "digest":"%s", "artifactType":"%s", "referrers":[{"mediaType":"application/example", "digest":"sha256:def"}]}`, name, digest, artifactType)
+	w.Header().Set("OCI-Filters-Applied", "artifactType")
+	h.writeReferrers(w, r, vars["name"], vars["digest"], vars["artifactType"])
 }
 
-func (h *Handler) getUploadStatus(w http.ResponseWriter, r *http.Request) {
-	vars := mux.Vars(r)
-	name := vars["name"]
-	reference := vars["reference"]
-
-	_, _, uploadedSize, err := h.registry.getUploadSession(reference)
+// writeReferrers serves the referrers of a manifest as an image index, as the OCI distribution spec requires.
+func (h *Handler) writeReferrers(w http.ResponseWriter, r *http.Request, name string, subject string, artifactType string) {
+	referrers, err := h.registry.listReferrers(r.Context(), name, subject, artifactType)
 	if err != nil {
-		slog.Error("error getting upload status", "error", err)
-		http.Error(w, fmt.Sprintf("error getting upload status: %v", err), http.StatusNotFound)
+		writeRegistryError(w, r, "error listing referrers", errCodeManifestUnknown, err)
 		return
 	}
 
-	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/uploads/%s", name, reference))
-	if uploadedSize > 0 {
-		w.Header().Set("Range", fmt.Sprintf("bytes=0-%d", uploadedSize-1))
+	index := v1.Index{
+		Versioned: specs.Versioned{SchemaVersion: 2},
+		MediaType: v1.MediaTypeImageIndex,
+		Manifests: referrers,
+	}
+	marshaledIndex, err := json.Marshal(index)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling referrers", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling referrers: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", v1.MediaTypeImageIndex)
+	if _, err := w.Write(marshaledIndex); err != nil {
+		slog.ErrorContext(r.Context(), "error writing referrers response", "error", err)
+	}
+}
+
+func (h *Handler) getUploadStatus(w http.ResponseWriter, r *http.Request) {
+	vars := mux.Vars(r)
+	name := vars["name"]
+	reference := vars["reference"]
+
+	session, err := h.registry.getUploadSession(name, reference)
+	if err != nil {
+		writeUploadError(w, r, "error getting upload status", err)
+		return
 	}
+
+	w.Header().Set("Location", fmt.Sprintf("/v2/%s/blobs/uploads/%s", name, reference))
+	w.Header().Set("Docker-Upload-UUID", reference)
+	setUploadRange(w, session.UploadedSize)
 	w.WriteHeader(http.StatusNoContent)
 }
 
 func (h *Handler) cancelUpload(w http.ResponseWriter, r *http.Request) {
 	vars := mux.Vars(r)
+	name := vars["name"]
 	reference := vars["reference"]
 
+	if _, err := h.registry.getUploadSession(name, reference); err != nil {
+		writeUploadError(w, r, "error canceling upload", err)
+		return
+	}
 	err := h.registry.abortUpload(r.Context(), reference)
 	if err != nil {
-		slog.Error("error canceling upload", "error", err)
-		http.Error(w, fmt.Sprintf("error canceling upload: %v", err), http.StatusInternalServerError)
+		writeUploadError(w, r, "error canceling upload", err)
 		return
 	}
 
 	w.WriteHeader(http.StatusNoContent)
 }
 
+const maxCatalogPageSize = 1000
+
+func (h *Handler) getCatalog(w http.ResponseWriter, r *http.Request) {
+	n := maxCatalogPageSize
+	if nStr := r.URL.Query().Get("n"); nStr != "" {
+		var err error
+		n, err = strconv.Atoi(nStr)
+		if err != nil || n < 0 {
+			writeOCIError(w, r, http.StatusBadRequest, errCodePaginationNumberInvalid, "invalid number of results requested", map[string]string{"n": nStr})
+			return
+		}
+		n = min(n, maxCatalogPageSize)
+	}
+	last := r.URL.Query().Get("last")
+
+	repositories, more, err := h.registry.listCatalog(r.Context(), last, n)
+	if err != nil {
+		writeRegistryError(w, r, "error listing catalog", errCodeNameUnknown, err)
+		return
+	}
+	if repositories == nil {
+		repositories = []string{}
+	}
+
+	marshaledCatalog, err := json.Marshal(map[string][]string{"repositories": repositories})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling catalog", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling catalog: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	if more && len(repositories) > 0 {
+		w.Header().Set(
+			"Link",
+			fmt.Sprintf("</v2/_catalog?n=%d&last=%s>; rel=\"next\"", n, url.QueryEscape(repositories[len(repositories)-1])),
+		)
+	}
+	_, err = w.Write(marshaledCatalog)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing catalog response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing catalog response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
 func (h *Handler) listRepositories(w http.ResponseWriter, r *http.Request) {
 	var continuationToken *string
 	if token := r.URL.Query().Get("continuationToken"); token != "" {
@@ -500,14 +1063,14 @@ func (h *Handler) listRepositories(w http.ResponseWriter, r *http.Request) {
 	}
 	repositories, continuationToken, err := h.registry.listRepositories(r.Context(), continuationToken, n)
 	if err != nil {
-		slog.Error("error listing repositories", "error", err)
+		slog.ErrorContext(r.Context(), "error listing repositories", "error", err)
 		http.Error(w, fmt.Sprintf("error listing repositories: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledRepos, err := json.Marshal(repositories)
 	if err != nil {
-		slog.Error("error marshalling repositories", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling repositories", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling repositories: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -525,7 +1088,7 @@ func (h *Handler) listRepositories(w http.ResponseWriter, r *http.Request) {
 	}
 	_, err = w.Write(marshaledRepos)
 	if err != nil {
-		slog.Error("error writing repositories response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing repositories response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing repositories response: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -543,14 +1106,14 @@ func (h *Handler) listAllTags(w http.ResponseWriter, r *http.Request) {
 	}
 	tags, continuationToken, err := h.registry.listAllTags(r.Context(), continuationToken, n)
 	if err != nil {
-		slog.Error("error listing tags", "error", err)
+		slog.ErrorContext(r.Context(), "error listing tags", "error", err)
 		http.Error(w, fmt.Sprintf("error listing tags: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledTags, err := json.Marshal(tags)
 	if err != nil {
-		slog.Error("error marshalling tags", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling tags", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling tags: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -568,7 +1131,7 @@ func (h *Handler) listAllTags(w http.ResponseWriter, r *http.Request) {
 	}
 	_, err = w.Write(marshaledTags)
 	if err != nil {
-		slog.Error("error writing tags response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing tags response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing tags response: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -586,14 +1149,14 @@ func (h *Handler) listLayers(w http.ResponseWriter, r *http.Request) {
 	}
 	layers, continuationToken, err := h.registry.listLayers(r.Context(), continuationToken, n)
 	if err != nil {
-		slog.Error("error listing layers", "error", err)
+		slog.ErrorContext(r.Context(), "error listing layers", "error", err)
 		http.Error(w, fmt.Sprintf("error listing layers: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledLayers, err := json.Marshal(layers)
 	if err != nil {
-		slog.Error("error marshalling layers", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling layers", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling layers: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -611,7 +1174,7 @@ func (h *Handler) listLayers(w http.ResponseWriter, r *http.Request) {
 	}
 	_, err = w.Write(marshaledLayers)
 	if err != nil {
-		slog.Error("error writing layers response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing layers response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing layers response: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -629,14 +1192,14 @@ func (h *Handler) listManifests(w http.ResponseWriter, r *http.Request) {
 	}
 	manifests, continuationToken, err := h.registry.listManifests(r.Context(), continuationToken, n)
 	if err != nil {
-		slog.Error("error listing manifests", "error", err)
+		slog.ErrorContext(r.Context(), "error listing manifests", "error", err)
 		http.Error(w, fmt.Sprintf("error listing manifests: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledManifests, err := json.Marshal(manifests)
 	if err != nil {
-		slog.Error("error marshalling manifests", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling manifests", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling manifests: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -654,30 +1217,483 @@ func (h *Handler) listManifests(w http.ResponseWriter, r *http.Request) {
 	}
 	_, err = w.Write(marshaledManifests)
 	if err != nil {
-		slog.Error("error writing manifests response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing manifests response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing manifests response: %v", err), http.StatusInternalServerError)
 		return
 	}
 }
 
+func (h *Handler) listRepositoryManifests(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	manifests, err := h.registry.listRepositoryManifests(r.Context(), name)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error listing repository manifests", "name", name, "error", err)
+		http.Error(w, fmt.Sprintf("error listing repository manifests: %v", err), http.StatusInternalServerError)
+		return
+	}
+
+	marshaledManifests, err := json.Marshal(manifests)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling repository manifests", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling repository manifests: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledManifests)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing repository manifests response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing repository manifests response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) getRepositorySize(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	size, err := h.registry.getRepositorySize(r.Context(), name)
+	if err != nil {
+		writeRegistryError(w, r, "error getting repository size", errCodeNameUnknown, err)
+		return
+	}
+
+	marshaledSize, err := json.Marshal(size)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling repository size", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling repository size: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledSize)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing repository size response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing repository size response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+type repositoryBlobs struct {
+	Name       string           `json:"name"`
+	Blobs      []RepositoryBlob `json:"blobs"`
+	TotalBytes int64            `json:"total_bytes"`
+}
+
+func (h *Handler) listRepositoryBlobs(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	blobs, err := h.registry.listRepositoryBlobs(r.Context(), name)
+	if err != nil {
+		writeRegistryError(w, r, "error listing repository blobs", errCodeNameUnknown, err)
+		return
+	}
+
+	result := repositoryBlobs{
+		Name:  name,
+		Blobs: blobs,
+	}
+	for _, blob := range blobs {
+		result.TotalBytes += blob.Size
+	}
+	marshaledBlobs, err := json.Marshal(result)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling repository blobs", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling repository blobs: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledBlobs)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing repository blobs response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing repository blobs response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+const (
+	defaultTopLayers = 10
+	maxTopLayers     = 1000
+)
+
+type largestLayers struct {
+	Name   string           `json:"name"`
+	Layers []RepositoryBlob `json:"layers"`
+}
+
+func (h *Handler) listLargestLayers(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	n := defaultTopLayers
+	if nStr := r.URL.Query().Get("n"); nStr != "" {
+		var err error
+		n, err = strconv.Atoi(nStr)
+		if err != nil || n < 0 {
+			writeOCIError(w, r, http.StatusBadRequest, errCodePaginationNumberInvalid, "invalid number of results requested", map[string]string{"n": nStr})
+			return
+		}
+		n = min(n, maxTopLayers)
+	}
+
+	layers, err := h.registry.listLargestLayers(r.Context(), name, n)
+	if err != nil {
+		writeRegistryError(w, r, "error listing largest layers", errCodeNameUnknown, err)
+		return
+	}
+
+	marshaledLayers, err := json.Marshal(largestLayers{
+		Name:   name,
+		Layers: layers,
+	})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling largest layers", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling largest layers: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledLayers)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing largest layers response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing largest layers response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) getImageHistory(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	reference := mux.Vars(r)["reference"]
+
+	history, err := h.registry.imageHistory(r.Context(), name, reference)
+	if err != nil {
+		writeRegistryError(w, r, "error getting image history", h.manifestNotFoundCode(r.Context(), name, err), err)
+		return
+	}
+
+	marshaledHistory, err := json.Marshal(history)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling image history", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling image history: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledHistory)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing image history response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing image history response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) warmRepository(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	if !validRepositoryName(name) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"name": name})
+		return
+	}
+
+	if h.registry.cfg.NoCache {
+		http.Error(w, "the database cache is disabled with --no-cache", http.StatusConflict)
+		return
+	}
+	if h.registry.cfg.ReadOnly {
+		http.Error(w, "the database is not written to with --read-only", http.StatusConflict)
+		return
+	}
+
+	summary, err := h.registry.WarmRepository(r.Context(), name)
+	if err != nil {
+		writeRegistryError(w, r, "error warming repository", errCodeNameUnknown, err)
+		return
+	}
+
+	marshaledSummary, err := json.Marshal(summary)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling warm summary", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling warm summary: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledSummary)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing warm summary response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing warm summary response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) invalidateCache(w http.ResponseWriter, r *http.Request) {
+	vars := mux.Vars(r)
+	name := vars["name"]
+	reference, hasReference := vars["reference"]
+	if !validRepositoryName(name) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"name": name})
+		return
+	}
+	if hasReference && !validReference(reference) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeTagInvalid, "invalid tag or digest", map[string]string{"reference": reference})
+		return
+	}
+	if h.registry.cfg.ReadOnly {
+		http.Error(w, "the database is not written to with --read-only", http.StatusConflict)
+		return
+	}
+
+	var invalidated int64
+	var err error
+	if hasReference {
+		invalidated, err = h.registry.InvalidateManifest(r.Context(), name, reference)
+	} else {
+		invalidated, err = h.registry.InvalidateRepository(r.Context(), name)
+	}
+	if err != nil {
+		writeRegistryError(w, r, "error invalidating cache", errCodeNameUnknown, err)
+		return
+	}
+	slog.InfoContext(r.Context(), "invalidated cache", "repo", name, "reference", reference, "invalidated", invalidated)
+
+	marshaledResult, err := json.Marshal(map[string]any{
+		"name":        name,
+		"reference":   reference,
+		"invalidated": invalidated,
+	})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling invalidation result", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling invalidation result: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledResult)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing invalidation response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing invalidation response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+const (
+	defaultSearchResults = 20
+	maxSearchResults     = 1000
+)
+
+func (h *Handler) searchRepositories(w http.ResponseWriter, r *http.Request) {
+	prefix := r.URL.Query().Get("prefix")
+	n := defaultSearchResults
+	if nStr := r.URL.Query().Get("n"); nStr != "" {
+		var err error
+		n, err = strconv.Atoi(nStr)
+		if err != nil || n < 0 {
+			writeOCIError(w, r, http.StatusBadRequest, errCodePaginationNumberInvalid, "invalid number of results requested", map[string]string{"n": nStr})
+			return
+		}
+		n = min(n, maxSearchResults)
+	}
+	if h.registry.cfg.NoCache {
+		http.Error(w, "the database cache is disabled with --no-cache", http.StatusConflict)
+		return
+	}
+
+	repositories, err := h.registry.searchRepositories(r.Context(), prefix, n)
+	if err != nil {
+		writeRegistryError(w, r, "error searching repositories", errCodeNameUnknown, err)
+		return
+	}
+
+	marshaledRepositories, err := json.Marshal(map[string][]string{"repositories": repositories})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling repositories", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling repositories: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledRepositories)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing repositories response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing repositories response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) setAlias(w http.ResponseWriter, r *http.Request) {
+	alias := mux.Vars(r)["name"]
+	var body struct {
+		Repository string `json:"repository"`
+	}
+	decoder := json.NewDecoder(http.MaxBytesReader(w, r.Body, 1<<20))
+	decoder.DisallowUnknownFields()
+	if err := decoder.Decode(&body); err != nil {
+		http.Error(w, fmt.Sprintf("invalid request body, expected {\"repository\": ...}: %v", err), http.StatusBadRequest)
+		return
+	}
+	for _, name := range []string{alias, body.Repository} {
+		if !validRepositoryName(name) {
+			writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"name": name})
+			return
+		}
+	}
+	if h.registry.cfg.ReadOnly {
+		http.Error(w, "the database is not written to with --read-only", http.StatusConflict)
+		return
+	}
+
+	repository, err := h.registry.SetAlias(r.Context(), alias, body.Repository)
+	if errors.Is(err, ErrInvalidAlias) {
+		http.Error(w, err.Error(), http.StatusConflict)
+		return
+	}
+	if err != nil {
+		writeRegistryError(w, r, "error setting alias", errCodeNameUnknown, err)
+		return
+	}
+	slog.InfoContext(r.Context(), "set repository alias", "alias", alias, "repository", repository)
+
+	marshaledAlias, err := json.Marshal(map[string]string{"alias": alias, "repository": repository})
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling alias", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling alias: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledAlias)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing alias response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing alias response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+func (h *Handler) deleteAlias(w http.ResponseWriter, r *http.Request) {
+	alias := mux.Vars(r)["name"]
+	if h.registry.cfg.ReadOnly {
+		http.Error(w, "the database is not written to with --read-only", http.StatusConflict)
+		return
+	}
+	if err := h.registry.DeleteAlias(r.Context(), alias); err != nil {
+		writeRegistryError(w, r, "error deleting alias", errCodeNameUnknown, err)
+		return
+	}
+	slog.InfoContext(r.Context(), "deleted repository alias", "alias", alias)
+	w.WriteHeader(http.StatusNoContent)
+}
+
+// exportFlushInterval is how many manifests are written between flushes of the export stream.
+const exportFlushInterval = 100
+
+func (h *Handler) exportManifests(w http.ResponseWriter, r *http.Request) {
+	repo := r.URL.Query().Get("repo")
+	if repo != "" && !validRepositoryName(repo) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"repo": repo})
+		return
+	}
+
+	// NOTICE: the status is only sent with the first manifest, so that a failing query can still
+	// be reported as an error; once streaming, a failure can only cut the stream short
+	controller := http.NewResponseController(w)
+	encoder := json.NewEncoder(w)
+	exported := 0
+	err := h.registry.exportManifests(r.Context(), repo, func(manifest ExportedManifest) error {
+		if exported == 0 {
+			w.Header().Set("Content-Type", "application/x-ndjson")
+			w.WriteHeader(http.StatusOK)
+		}
+		if err := encoder.Encode(manifest); err != nil {
+			return fmt.Errorf("failed to write manifest: %w", err)
+		}
+		exported++
+		if exported%exportFlushInterval == 0 {
+			_ = controller.Flush()
+		}
+		return nil
+	})
+	if err != nil {
+		if exported == 0 {
+			writeRegistryError(w, r, "error exporting manifests", errCodeNameUnknown, err)
+			return
+		}
+		slog.ErrorContext(r.Context(), "error exporting manifests, the export is incomplete", "exported", exported, "error", err)
+		return
+	}
+	if exported == 0 {
+		w.Header().Set("Content-Type", "application/x-ndjson")
+		w.WriteHeader(http.StatusOK)
+	}
+	slog.InfoContext(r.Context(), "exported manifests", "repo", repo, "manifests", exported)
+}
+
+func (h *Handler) reconcileRepository(w http.ResponseWriter, r *http.Request) {
+	name := mux.Vars(r)["name"]
+	if !validRepositoryName(name) {
+		writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"name": name})
+		return
+	}
+
+	result, err := h.registry.ReconcileRepository(r.Context(), name)
+	if err != nil {
+		writeRegistryError(w, r, "error reconciling repository", errCodeNameUnknown, err)
+		return
+	}
+	if len(result.Missing) > 0 {
+		slog.WarnContext(r.Context(), "referenced blobs missing from storage", "repo", name, "missing", len(result.Missing), "checked", result.Checked)
+	}
+
+	marshaledResult, err := json.Marshal(result)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling reconcile result", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling reconcile result: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledResult)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing reconcile response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing reconcile response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
+const maxResolveBatchSize = 1000
+
+func (h *Handler) resolveManifests(w http.ResponseWriter, r *http.Request) {
+	var refs []ManifestReference
+	decoder := json.NewDecoder(http.MaxBytesReader(w, r.Body, 1<<20))
+	decoder.DisallowUnknownFields()
+	if err := decoder.Decode(&refs); err != nil {
+		http.Error(w, fmt.Sprintf("invalid request body, expected [{\"repo\": ..., \"reference\": ...}]: %v", err), http.StatusBadRequest)
+		return
+	}
+	if len(refs) > maxResolveBatchSize {
+		http.Error(w, fmt.Sprintf("too many references, at most %d are allowed per request", maxResolveBatchSize), http.StatusBadRequest)
+		return
+	}
+
+	results := h.registry.ResolveManifests(r.Context(), refs)
+
+	marshaledResults, err := json.Marshal(results)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error marshalling resolved manifests", "error", err)
+		http.Error(w, fmt.Sprintf("error marshalling resolved manifests: %v", err), http.StatusInternalServerError)
+		return
+	}
+	w.Header().Set("Content-Type", "application/json")
+	_, err = w.Write(marshaledResults)
+	if err != nil {
+		slog.ErrorContext(r.Context(), "error writing resolved manifests response", "error", err)
+		http.Error(w, fmt.Sprintf("error writing resolved manifests response: %v", err), http.StatusInternalServerError)
+		return
+	}
+}
+
 func (h *Handler) listUploadSessions(w http.ResponseWriter, r *http.Request) {
 	sessions, err := h.registry.listUploadSessions(r.Context())
 	if err != nil {
-		slog.Error("error listing upload sessions", "error", err)
+		slog.ErrorContext(r.Context(), "error listing upload sessions", "error", err)
 		http.Error(w, fmt.Sprintf("error listing upload sessions: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledSessions, err := json.Marshal(sessions)
 	if err != nil {
-		slog.Error("error marshalling upload sessions", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling upload sessions", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling upload sessions: %v", err), http.StatusInternalServerError)
 		return
 	}
 	w.Header().Set("Content-Type", "application/json")
 	_, err = w.Write(marshaledSessions)
 	if err != nil {
-		slog.Error("error writing upload sessions response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing upload sessions response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing upload sessions response: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -686,21 +1702,21 @@ func (h *Handler) listUploadSessions(w http.ResponseWriter, r *http.Request) {
 func (h *Handler) getRegistryStats(w http.ResponseWriter, r *http.Request) {
 	stats, err := h.registry.getRegistryStats(r.Context())
 	if err != nil {
-		slog.Error("error getting registry stats", "error", err)
+		slog.ErrorContext(r.Context(), "error getting registry stats", "error", err)
 		http.Error(w, fmt.Sprintf("error getting registry stats: %v", err), http.StatusInternalServerError)
 		return
 	}
 
 	marshaledStats, err := json.Marshal(stats)
 	if err != nil {
-		slog.Error("error marshalling registry stats", "error", err)
+		slog.ErrorContext(r.Context(), "error marshalling registry stats", "error", err)
 		http.Error(w, fmt.Sprintf("error marshalling registry stats: %v", err), http.StatusInternalServerError)
 		return
 	}
 	w.Header().Set("Content-Type", "application/json")
 	_, err = w.Write(marshaledStats)
 	if err != nil {
-		slog.Error("error writing registry stats response", "error", err)
+		slog.ErrorContext(r.Context(), "error writing registry stats response", "error", err)
 		http.Error(w, fmt.Sprintf("error writing registry stats response: %v", err), http.StatusInternalServerError)
 		return
 	}
@@ -0,0 +1,779 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"io/fs"
+	"net/http"
+	"net/http/httptest"
+	"net/url"
+	"reflect"
+	"runtime"
+	"strconv"
+	"strings"
+	"testing"
+
+	"github.com/opencontainers/go-digest"
+	specs "github.com/opencontainers/image-spec/specs-go"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+func TestCatalogPagesFromStorage(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = true })
+	for _, repo := range []string{"e", "b/c/f", "a", "b-d", "b/c"} {
+		pushTestImage(t, r, repo, "latest", "layer of "+repo)
+	}
+	router := newTestRouter(t, r)
+
+	var pages [][]string
+	next := "/v2/_catalog?n=2"
+	for next != "" {
+		if len(pages) > 5 {
+			t.Fatal("catalog does not end")
+		}
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, next, nil))
+		if rec.Code != http.StatusOK {
+			t.Fatalf("got status %d for %s, want %d", rec.Code, next, http.StatusOK)
+		}
+		var catalog struct {
+			Repositories []string `json:"repositories"`
+		}
+		if err := json.Unmarshal(rec.Body.Bytes(), &catalog); err != nil {
+			t.Fatalf("failed to decode catalog: %v", err)
+		}
+		pages = append(pages, catalog.Repositories)
+
+		next = ""
+		if link := rec.Header().Get("Link"); link != "" {
+			start, end := strings.Index(link, "<"), strings.Index(link, ">")
+			if start < 0 || end < start {
+				t.Fatalf("malformed Link header %q", link)
+			}
+			next = link[start+1 : end]
+		}
+	}
+
+	want := [][]string{{"a", "b-d"}, {"b/c", "b/c/f"}, {"e"}}
+	if !reflect.DeepEqual(pages, want) {
+		t.Fatalf("got pages %v, want %v", pages, want)
+	}
+}
+
+func TestBlobRedirectSize(t *testing.T) {
+	r, _ := newCountingTestRegistry(t)
+	pushTestImage(t, r, "foo", "latest", "cached layer")
+	uncached := putTestBlob(t, r, []byte("uncached blob"))
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		name   string
+		digest string
+		want   string
+	}{
+		{name: "cached layer", digest: digest.FromString("cached layer").String(), want: "12"},
+		{name: "uncached blob", digest: uncached.Digest.String(), want: ""},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/blobs/"+tc.digest, nil))
+			if rec.Code != http.StatusFound {
+				t.Fatalf("got status %d, want %d", rec.Code, http.StatusFound)
+			}
+			if got := rec.Header().Get("X-Blob-Size"); got != tc.want {
+				t.Fatalf("got X-Blob-Size %q, want %q", got, tc.want)
+			}
+		})
+	}
+}
+
+func TestManifestNotFoundCode(t *testing.T) {
+	r, storage := newCountingTestRegistry(t, func(cfg *Config) { cfg.ListingCacheSeconds = 60 })
+	pushTestImage(t, r, "cached", "latest", "layer")
+	linkTestManifest(t, r, "uncached", testManifest(t, r, "layer"))
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		name   string
+		code   string
+		listed bool
+	}{
+		{name: "cached", code: errCodeManifestUnknown, listed: false},
+		{name: "uncached", code: errCodeManifestUnknown, listed: true},
+		{name: "unknown", code: errCodeNameUnknown, listed: false},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			// NOTICE: the second miss is answered from the repository cache
+			for i := range 2 {
+				before := storage.listedKeys.Load()
+				rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/"+tc.name+"/manifests/missing", nil))
+				if rec.Code != http.StatusNotFound || !strings.Contains(rec.Body.String(), tc.code) {
+					t.Fatalf("got status %d, want %d %s: %s", rec.Code, http.StatusNotFound, tc.code, rec.Body)
+				}
+				if listed := storage.listedKeys.Load() > before; listed != (tc.listed && i == 0) {
+					t.Fatalf("request %d listed storage: %v", i+1, listed)
+				}
+			}
+		})
+	}
+}
+
+func TestBlobRange(t *testing.T) {
+	r := newTestRegistry(t)
+	blob := putTestBlob(t, r, []byte("0123456789"))
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		name         string
+		rangeHeader  string
+		status       int
+		body         string
+		contentRange string
+	}{
+		{name: "whole blob", status: http.StatusOK, body: "0123456789"},
+		{name: "range", rangeHeader: "bytes=2-5", status: http.StatusPartialContent, body: "2345", contentRange: "bytes 2-5/10"},
+		{name: "open-ended range", rangeHeader: "bytes=7-", status: http.StatusPartialContent, body: "789", contentRange: "bytes 7-9/10"},
+		{name: "suffix range", rangeHeader: "bytes=-3", status: http.StatusPartialContent, body: "789", contentRange: "bytes 7-9/10"},
+		{name: "first of several ranges", rangeHeader: "bytes=0-1, 4-5", status: http.StatusPartialContent, body: "01", contentRange: "bytes 0-1/10"},
+		{name: "out of bounds range", rangeHeader: "bytes=10-20", status: http.StatusRequestedRangeNotSatisfiable, contentRange: "bytes */10"},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			req := httptest.NewRequest(http.MethodGet, "/v2/foo/blobs/"+blob.Digest.String(), nil)
+			if tc.rangeHeader != "" {
+				req.Header.Set("Range", tc.rangeHeader)
+			}
+			rec := serveTestRequest(router, req)
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d, want %d: %s", rec.Code, tc.status, rec.Body)
+			}
+			if got := rec.Header().Get("Content-Range"); got != tc.contentRange {
+				t.Errorf("got Content-Range %q, want %q", got, tc.contentRange)
+			}
+			if tc.status != http.StatusRequestedRangeNotSatisfiable {
+				if rec.Body.String() != tc.body {
+					t.Errorf("got body %q, want %q", rec.Body, tc.body)
+				}
+				if got, want := rec.Header().Get("Content-Length"), strconv.Itoa(len(tc.body)); got != want {
+					t.Errorf("got Content-Length %s, want %s", got, want)
+				}
+			}
+		})
+	}
+}
+
+func TestManifestRange(t *testing.T) {
+	r := newTestRegistry(t)
+	manifestBytes := testManifest(t, r, "layer")
+	pushTestManifest(t, r, "foo", "latest", manifestBytes)
+	router := newTestRouter(t, r)
+	size := len(manifestBytes)
+
+	for _, tc := range []struct {
+		name         string
+		method       string
+		rangeHeader  string
+		status       int
+		body         string
+		contentRange string
+	}{
+		{name: "whole manifest", method: http.MethodGet, status: http.StatusOK, body: string(manifestBytes)},
+		{name: "range", method: http.MethodGet, rangeHeader: "bytes=0-9", status: http.StatusPartialContent, body: string(manifestBytes[:10]), contentRange: fmt.Sprintf("bytes 0-9/%d", size)},
+		{name: "suffix range", method: http.MethodGet, rangeHeader: "bytes=-5", status: http.StatusPartialContent, body: string(manifestBytes[size-5:]), contentRange: fmt.Sprintf("bytes %d-%d/%d", size-5, size-1, size)},
+		{name: "range past the end", method: http.MethodGet, rangeHeader: fmt.Sprintf("bytes=%d-%d", size-2, size+100), status: http.StatusPartialContent, body: string(manifestBytes[size-2:]), contentRange: fmt.Sprintf("bytes %d-%d/%d", size-2, size-1, size)},
+		{name: "head of a range", method: http.MethodHead, rangeHeader: "bytes=0-9", status: http.StatusPartialContent, contentRange: fmt.Sprintf("bytes 0-9/%d", size)},
+		{name: "out of bounds range", method: http.MethodGet, rangeHeader: fmt.Sprintf("bytes=%d-", size), status: http.StatusRequestedRangeNotSatisfiable, contentRange: fmt.Sprintf("bytes */%d", size)},
+		{name: "malformed range", method: http.MethodGet, rangeHeader: "bytes=5-2", status: http.StatusRequestedRangeNotSatisfiable, contentRange: fmt.Sprintf("bytes */%d", size)},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			req := httptest.NewRequest(tc.method, "/v2/foo/manifests/latest", nil)
+			if tc.rangeHeader != "" {
+				req.Header.Set("Range", tc.rangeHeader)
+			}
+			rec := serveTestRequest(router, req)
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d, want %d: %s", rec.Code, tc.status, rec.Body)
+			}
+			if got := rec.Header().Get("Content-Range"); got != tc.contentRange {
+				t.Errorf("got Content-Range %q, want %q", got, tc.contentRange)
+			}
+			if tc.status == http.StatusRequestedRangeNotSatisfiable {
+				return
+			}
+			if rec.Body.String() != tc.body {
+				t.Errorf("got body %q, want %q", rec.Body, tc.body)
+			}
+			wantLength := len(tc.body)
+			if tc.method == http.MethodHead {
+				wantLength = 10
+			}
+			if got, want := rec.Header().Get("Content-Length"), strconv.Itoa(wantLength); got != want {
+				t.Errorf("got Content-Length %s, want %s", got, want)
+			}
+		})
+	}
+}
+
+func TestManifestETag(t *testing.T) {
+	r := newTestRegistry(t)
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+	router := newTestRouter(t, r)
+	etag := `"` + dgst.String() + `"`
+
+	for _, tc := range []struct {
+		name        string
+		ifNoneMatch string
+		status      int
+	}{
+		{name: "no condition", status: http.StatusOK},
+		{name: "match", ifNoneMatch: etag, status: http.StatusNotModified},
+		{name: "weak match", ifNoneMatch: "W/" + etag, status: http.StatusNotModified},
+		{name: "match in a list", ifNoneMatch: `"other", ` + etag, status: http.StatusNotModified},
+		{name: "mismatch", ifNoneMatch: `"` + digest.FromString("other").String() + `"`, status: http.StatusOK},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			req := httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest", nil)
+			if tc.ifNoneMatch != "" {
+				req.Header.Set("If-None-Match", tc.ifNoneMatch)
+			}
+			rec := serveTestRequest(router, req)
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d, want %d", rec.Code, tc.status)
+			}
+			if got := rec.Header().Get("ETag"); got != etag {
+				t.Errorf("got ETag %s, want %s", got, etag)
+			}
+			if empty := rec.Body.Len() == 0; empty != (tc.status == http.StatusNotModified) {
+				t.Errorf("got a %d byte body with status %d", rec.Body.Len(), rec.Code)
+			}
+		})
+	}
+}
+
+func TestTagsSorted(t *testing.T) {
+	unsorted := []string{"v10", "latest", "V2", "1.0", "v2", "_x"}
+	want := []string{"1.0", "V2", "_x", "latest", "v10", "v2"}
+
+	for _, tc := range []struct {
+		name    string
+		noCache bool
+	}{
+		{name: "database", noCache: false},
+		{name: "storage", noCache: true},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = tc.noCache })
+			manifestBytes := testManifest(t, r, "layer")
+			for _, tag := range unsorted {
+				retargetTestTag(t, r, "foo", tag, manifestBytes)
+			}
+			if !tc.noCache {
+				if err := r.db.PutTags("foo", unsorted); err != nil {
+					t.Fatalf("failed to put tags: %v", err)
+				}
+			}
+			router := newTestRouter(t, r)
+
+			for range 3 {
+				if got := getTestTags(t, router, "foo"); !reflect.DeepEqual(got, want) {
+					t.Fatalf("got tags %v, want %v", got, want)
+				}
+			}
+		})
+	}
+}
+
+func TestListTagsPagination(t *testing.T) {
+	r := newTestRegistry(t)
+	for _, tag := range []string{"a", "b", "c"} {
+		pushTestImage(t, r, "foo", tag, "layer")
+	}
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		query string
+		body  string
+		link  string
+	}{
+		{query: "", body: `{"name":"foo","tags":["a","b","c"]}`},
+		{query: "?n=0", body: `{"name":"foo","tags":[]}`},
+		{query: "?n=2", body: `{"name":"foo","tags":["a","b"]}`, link: `</v2/foo/tags/list?n=2&last=b>; rel="next"`},
+		{query: "?n=2&last=b", body: `{"name":"foo","tags":["c"]}`},
+		{query: "?n=5", body: `{"name":"foo","tags":["a","b","c"]}`},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/tags/list"+tc.query, nil))
+		if rec.Code != http.StatusOK || strings.TrimSpace(rec.Body.String()) != tc.body {
+			t.Errorf("got status %d and %s for %q, want %d and %s", rec.Code, rec.Body, tc.query, http.StatusOK, tc.body)
+		}
+		if link := rec.Header().Get("Link"); link != tc.link {
+			t.Errorf("got Link %q for %q, want %q", link, tc.query, tc.link)
+		}
+	}
+
+	for _, query := range []string{"?n=-1", "?n=x"} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/tags/list"+query, nil))
+		if rec.Code != http.StatusBadRequest || !strings.Contains(rec.Body.String(), errCodePaginationNumberInvalid) {
+			t.Errorf("got status %d for %q, want %d %s: %s", rec.Code, query, http.StatusBadRequest, errCodePaginationNumberInvalid, rec.Body)
+		}
+	}
+}
+
+func TestListTagsOfTaglessRepository(t *testing.T) {
+	for _, noCache := range []bool{false, true} {
+		r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = noCache })
+		linkTestManifest(t, r, "foo", testManifest(t, r, "layer"))
+		router := newTestRouter(t, r)
+
+		if got := getTestTags(t, router, "foo"); got == nil || len(got) != 0 {
+			t.Errorf("got tags %#v with no-cache %v, want an empty list", got, noCache)
+		}
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/bar/tags/list", nil))
+		if rec.Code != http.StatusNotFound || !strings.Contains(rec.Body.String(), errCodeNameUnknown) {
+			t.Errorf("got status %d for an unknown repository with no-cache %v, want %d %s: %s", rec.Code, noCache, http.StatusNotFound, errCodeNameUnknown, rec.Body)
+		}
+	}
+}
+
+func TestRoutesAnchorOnSuffix(t *testing.T) {
+	r := newTestRegistry(t)
+	blob := putTestBlob(t, r, []byte("blob"))
+	for _, name := range []string{"a/blobs/b", "x/manifests", "t/tags/list"} {
+		pushTestImage(t, r, name, "latest", "layer")
+	}
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		target string
+		status int
+		body   string
+	}{
+		{target: "/v2/a/blobs/b/manifests/latest", status: http.StatusOK},
+		{target: "/v2/a/blobs/b/blobs/" + blob.Digest.String(), status: http.StatusOK, body: "blob"},
+		{target: "/v2/x/manifests/manifests/latest", status: http.StatusOK},
+		{target: "/v2/x/manifests/tags/list", status: http.StatusOK, body: `{"name":"x/manifests","tags":["latest"]}`},
+		{target: "/v2/t/tags/list/tags/list", status: http.StatusOK, body: `{"name":"t/tags/list","tags":["latest"]}`},
+		{target: "/v2/x/manifests/manifests/latest/extra", status: http.StatusNotFound},
+		{target: "/v2/a/blobs/b/blobs/" + blob.Digest.String() + "/extra", status: http.StatusNotFound},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, tc.target, nil))
+		if rec.Code != tc.status {
+			t.Errorf("got status %d for %s, want %d: %s", rec.Code, tc.target, tc.status, rec.Body)
+			continue
+		}
+		if tc.body != "" && rec.Body.String() != tc.body {
+			t.Errorf("got %s for %s, want %s", rec.Body, tc.target, tc.body)
+		}
+	}
+}
+
+func TestDeleteTag(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.EnableDelete = true })
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+	pushTestImage(t, r, "foo", "stable", "layer")
+	router := newTestRouter(t, r)
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodDelete, "/v2/foo/manifests/latest", nil))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d deleting the tag, want %d: %s", rec.Code, http.StatusAccepted, rec.Body)
+	}
+
+	for _, tc := range []struct {
+		method string
+		target string
+		status int
+	}{
+		{method: http.MethodGet, target: "/v2/foo/manifests/latest", status: http.StatusNotFound},
+		{method: http.MethodGet, target: "/v2/foo/manifests/" + dgst.String(), status: http.StatusOK},
+		{method: http.MethodGet, target: "/v2/foo/manifests/stable", status: http.StatusOK},
+		{method: http.MethodDelete, target: "/v2/foo/manifests/latest", status: http.StatusNotFound},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(tc.method, tc.target, nil))
+		if rec.Code != tc.status {
+			t.Errorf("got status %d for %s %s, want %d", rec.Code, tc.method, tc.target, tc.status)
+		}
+	}
+	if got, want := getTestTags(t, router, "foo"), []string{"stable"}; !reflect.DeepEqual(got, want) {
+		t.Errorf("got tags %v, want %v", got, want)
+	}
+}
+
+func TestDeleteTagDisabled(t *testing.T) {
+	r := newTestRegistry(t)
+	pushTestImage(t, r, "foo", "latest", "layer")
+	router := newTestRouter(t, r)
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodDelete, "/v2/foo/manifests/latest", nil))
+	if rec.Code != http.StatusMethodNotAllowed {
+		t.Fatalf("got status %d, want %d", rec.Code, http.StatusMethodNotAllowed)
+	}
+	if got, want := getTestTags(t, router, "foo"), []string{"latest"}; !reflect.DeepEqual(got, want) {
+		t.Fatalf("got tags %v, want %v", got, want)
+	}
+}
+
+func TestManifestServedVerbatim(t *testing.T) {
+	r := newTestRegistry(t)
+	var manifest map[string]any
+	if err := json.Unmarshal(testManifest(t, r, "layer"), &manifest); err != nil {
+		t.Fatalf("failed to unmarshal manifest: %v", err)
+	}
+	// NOTICE: indented, which re-encoding the manifest would undo
+	manifestBytes, err := json.MarshalIndent(manifest, "", "   ")
+	if err != nil {
+		t.Fatalf("failed to marshal manifest: %v", err)
+	}
+	dgst := pushTestManifest(t, r, "foo", "latest", manifestBytes)
+	router := newTestRouter(t, r)
+
+	for _, target := range []string{"/v2/foo/manifests/latest", "/v2/foo/manifests/" + dgst.String()} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, target, nil))
+		if rec.Code != http.StatusOK {
+			t.Fatalf("got status %d for %s, want %d", rec.Code, target, http.StatusOK)
+		}
+		if rec.Body.String() != string(manifestBytes) {
+			t.Fatalf("got manifest %s for %s, want %s", rec.Body, target, manifestBytes)
+		}
+		if got, want := rec.Header().Get("Content-Length"), strconv.Itoa(len(manifestBytes)); got != want {
+			t.Fatalf("got Content-Length %s for %s, want %s", got, target, want)
+		}
+	}
+}
+
+func TestMaxManifestBytes(t *testing.T) {
+	const limit = 2048
+	r := newTestRegistry(t, func(cfg *Config) { cfg.MaxManifestBytes = limit })
+	small := testManifest(t, r, "layer")
+	var manifest map[string]any
+	if err := json.Unmarshal(small, &manifest); err != nil {
+		t.Fatalf("failed to unmarshal manifest: %v", err)
+	}
+	large, err := json.MarshalIndent(manifest, "", strings.Repeat(" ", limit))
+	if err != nil {
+		t.Fatalf("failed to marshal manifest: %v", err)
+	}
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		tag           string
+		manifestBytes []byte
+		status        int
+	}{
+		{tag: "small", manifestBytes: small, status: http.StatusCreated},
+		{tag: "large", manifestBytes: large, status: http.StatusRequestEntityTooLarge},
+	} {
+		req := httptest.NewRequest(http.MethodPut, "/v2/foo/manifests/"+tc.tag, strings.NewReader(string(tc.manifestBytes)))
+		rec := serveTestRequest(router, req)
+		if rec.Code != tc.status {
+			t.Errorf("got status %d pushing %d bytes, want %d: %s", rec.Code, len(tc.manifestBytes), tc.status, rec.Body)
+		}
+	}
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/large", nil))
+	if rec.Code != http.StatusNotFound {
+		t.Errorf("got status %d for the rejected manifest, want %d", rec.Code, http.StatusNotFound)
+	}
+
+	// NOTICE: written by another registry, the oversized manifest is only read from storage
+	retargetTestTag(t, r, "foo", "large", large)
+	if _, _, err := r.getManifest(context.Background(), "foo", "large"); !errors.Is(err, ErrManifestTooLarge) {
+		t.Errorf("got error %v reading the oversized manifest from storage, want %v", err, ErrManifestTooLarge)
+	}
+}
+
+func TestInvalidateCache(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.AdminToken = testAdminToken })
+	pushTestImage(t, r, "foo", "latest", "old layer")
+	pushTestImage(t, r, "foo", "stable", "old layer")
+	router := newTestRouter(t, r)
+	getDigest := func(reference string) string {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/"+reference, nil))
+		if rec.Code != http.StatusOK {
+			t.Fatalf("got status %d getting foo:%s, want %d: %s", rec.Code, reference, http.StatusOK, rec.Body)
+		}
+		return rec.Header().Get("Docker-Content-Digest")
+	}
+	for _, reference := range []string{"latest", "stable"} {
+		getDigest(reference)
+	}
+	newManifest := testManifest(t, r, "new layer")
+	want := retargetTestTag(t, r, "foo", "latest", newManifest).String()
+	retargetTestTag(t, r, "foo", "stable", newManifest)
+
+	if got := getDigest("latest"); got == want {
+		t.Fatalf("got the retargeted manifest before invalidating the cache")
+	}
+	rec := serveTestRequest(router, newTestAdminRequest(http.MethodDelete, "/admin/cache/foo/manifests/latest"))
+	if rec.Code != http.StatusOK {
+		t.Fatalf("got status %d invalidating foo:latest, want %d: %s", rec.Code, http.StatusOK, rec.Body)
+	}
+	if got := getDigest("latest"); got != want {
+		t.Errorf("got manifest %s after invalidating foo:latest, want %s", got, want)
+	}
+	if got := getDigest("stable"); got == want {
+		t.Errorf("invalidating foo:latest dropped foo:stable too")
+	}
+
+	rec = serveTestRequest(router, newTestAdminRequest(http.MethodDelete, "/admin/cache/foo"))
+	if rec.Code != http.StatusOK {
+		t.Fatalf("got status %d invalidating foo, want %d: %s", rec.Code, http.StatusOK, rec.Body)
+	}
+	if got := getDigest("stable"); got != want {
+		t.Errorf("got manifest %s after invalidating foo, want %s", got, want)
+	}
+
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodDelete, "/admin/cache/foo", nil))
+	if rec.Code != http.StatusUnauthorized {
+		t.Errorf("got status %d without the admin token, want %d", rec.Code, http.StatusUnauthorized)
+	}
+}
+
+func TestSearchRepositories(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.AdminToken = testAdminToken })
+	for _, repo := range []string{"team/b", "team/a", "team-c", "other"} {
+		pushTestImage(t, r, repo, "latest", "layer")
+	}
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		query  string
+		status int
+		body   string
+	}{
+		{query: "?prefix=team/", status: http.StatusOK, body: `{"repositories":["team/a","team/b"]}`},
+		{query: "?prefix=team&n=2", status: http.StatusOK, body: `{"repositories":["team-c","team/a"]}`},
+		{query: "?prefix=none", status: http.StatusOK, body: `{"repositories":[]}`},
+		{query: "?n=-1", status: http.StatusBadRequest},
+	} {
+		rec := serveTestRequest(router, newTestAdminRequest(http.MethodGet, "/admin/search"+tc.query))
+		if rec.Code != tc.status {
+			t.Errorf("got status %d for %q, want %d: %s", rec.Code, tc.query, tc.status, rec.Body)
+			continue
+		}
+		if tc.body != "" && rec.Body.String() != tc.body {
+			t.Errorf("got %s for %q, want %s", rec.Body, tc.query, tc.body)
+		}
+	}
+}
+
+func TestManifestFields(t *testing.T) {
+	cfg := testConfig(t.TempDir())
+	r := newTestRegistryWithStorage(t, newTestFSStorage(t, cfg), cfg)
+	manifestBytes := testManifest(t, r, "layer")
+	dgst := pushTestManifest(t, r, "foo", "latest", manifestBytes)
+	// NOTICE: sharing the database, a registry whose storage fails any call serves cached manifests alone
+	router := newTestRouter(t, newTestRegistryWithStorage(t, panickingStorage{}, cfg))
+
+	for _, tc := range []struct {
+		fields string
+		status int
+		body   string
+	}{
+		{fields: "digest,mediaType,size", status: http.StatusOK, body: fmt.Sprintf(`{"digest":%q,"mediaType":"application/vnd.oci.image.manifest.v1+json","size":%d}`, dgst, len(manifestBytes))},
+		{fields: " size ", status: http.StatusOK, body: fmt.Sprintf(`{"size":%d}`, len(manifestBytes))},
+		{fields: "digest,annotations", status: http.StatusBadRequest},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest?fields="+url.QueryEscape(tc.fields), nil))
+		if rec.Code != tc.status {
+			t.Errorf("got status %d for fields %q, want %d: %s", rec.Code, tc.fields, tc.status, rec.Body)
+			continue
+		}
+		if tc.body != "" && rec.Body.String() != tc.body {
+			t.Errorf("got %s for fields %q, want %s", rec.Body, tc.fields, tc.body)
+		}
+	}
+}
+
+func TestExportManifests(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.AdminToken = testAdminToken
+		cfg.CompressCache = true
+	})
+	latest := pushTestImage(t, r, "foo", "latest", "layer")
+	v1Digest := pushTestImage(t, r, "bar", "v1", "bar layer")
+	untagged := linkTestManifest(t, r, "foo", testManifest(t, r, "untagged layer"))
+	router := newTestRouter(t, r)
+	if rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/"+untagged.String(), nil)); rec.Code != http.StatusOK {
+		t.Fatalf("got status %d pulling the untagged manifest, want %d", rec.Code, http.StatusOK)
+	}
+
+	export := func(query string) []ExportedManifest {
+		t.Helper()
+		rec := serveTestRequest(router, newTestAdminRequest(http.MethodGet, "/admin/export"+query))
+		if rec.Code != http.StatusOK || rec.Header().Get("Content-Type") != "application/x-ndjson" {
+			t.Fatalf("got status %d and Content-Type %q exporting %q, want %d", rec.Code, rec.Header().Get("Content-Type"), query, http.StatusOK)
+		}
+		var manifests []ExportedManifest
+		decoder := json.NewDecoder(rec.Body)
+		for decoder.More() {
+			var manifest ExportedManifest
+			if err := decoder.Decode(&manifest); err != nil {
+				t.Fatalf("failed to decode exported manifest: %v", err)
+			}
+			if got := digest.FromBytes(manifest.Manifest); got.String() != manifest.Digest {
+				t.Errorf("got manifest hashing to %s exported as %s", got, manifest.Digest)
+			}
+			manifests = append(manifests, manifest)
+		}
+		return manifests
+	}
+
+	var got []string
+	for _, manifest := range export("") {
+		got = append(got, manifest.Repository+":"+manifest.Tag+"@"+manifest.Digest)
+	}
+	want := []string{"bar:v1@" + v1Digest.String(), "foo:@" + untagged.String(), "foo:latest@" + latest.String()}
+	if !reflect.DeepEqual(got, want) {
+		t.Errorf("exported %v, want %v", got, want)
+	}
+	if manifests := export("?repo=bar"); len(manifests) != 1 || manifests[0].Repository != "bar" {
+		t.Errorf("exported %v for bar alone", manifests)
+	}
+	if manifests := export("?repo=baz"); len(manifests) != 0 {
+		t.Errorf("exported %v for an unknown repository, want nothing", manifests)
+	}
+	if rec := serveTestRequest(router, newTestAdminRequest(http.MethodGet, "/admin/export?repo=Foo")); rec.Code != http.StatusBadRequest {
+		t.Errorf("got status %d exporting an invalid repository, want %d", rec.Code, http.StatusBadRequest)
+	}
+}
+
+func TestReferrers(t *testing.T) {
+	r := newTestRegistry(t)
+	subjectBytes := testManifest(t, r, "layer")
+	subject := pushTestManifest(t, r, "foo", "latest", subjectBytes)
+	router := newTestRouter(t, r)
+
+	referrerManifest := func(artifactType string, configMediaType string, layer string) []byte {
+		config := putTestBlob(t, r, []byte("{}"))
+		config.MediaType = configMediaType
+		manifestBytes, err := json.Marshal(v1.Manifest{
+			Versioned:    specs.Versioned{SchemaVersion: 2},
+			MediaType:    v1.MediaTypeImageManifest,
+			ArtifactType: artifactType,
+			Config:       config,
+			Layers:       []v1.Descriptor{putTestBlob(t, r, []byte(layer))},
+			Subject:      &v1.Descriptor{MediaType: v1.MediaTypeImageManifest, Digest: subject, Size: int64(len(subjectBytes))},
+			Annotations:  map[string]string{"org.example.layer": layer},
+		})
+		if err != nil {
+			t.Fatalf("failed to marshal manifest: %v", err)
+		}
+		return manifestBytes
+	}
+	signature := referrerManifest("application/vnd.example.signature", v1.MediaTypeEmptyJSON, "signature")
+	// NOTICE: without an artifactType, the config media type stands in for it
+	sbom := referrerManifest("", "application/vnd.example.sbom", "sbom")
+	for _, manifestBytes := range [][]byte{signature, sbom} {
+		dgst := digest.FromBytes(manifestBytes)
+		req := httptest.NewRequest(http.MethodPut, "/v2/foo/manifests/"+dgst.String(), bytes.NewReader(manifestBytes))
+		req.Header.Set("Content-Type", v1.MediaTypeImageManifest)
+		rec := serveTestRequest(router, req)
+		if rec.Code != http.StatusCreated {
+			t.Fatalf("got status %d pushing a referrer, want %d: %s", rec.Code, http.StatusCreated, rec.Body)
+		}
+		if got := rec.Header().Get("OCI-Subject"); got != subject.String() {
+			t.Errorf("got OCI-Subject %q, want %s", got, subject)
+		}
+	}
+
+	for _, tc := range []struct {
+		query    string
+		filtered bool
+		want     []digest.Digest
+	}{
+		{query: "", want: []digest.Digest{digest.FromBytes(signature), digest.FromBytes(sbom)}},
+		{query: "?artifactType=application/vnd.example.signature", filtered: true, want: []digest.Digest{digest.FromBytes(signature)}},
+		{query: "?artifactType=application/vnd.example.sbom", filtered: true, want: []digest.Digest{digest.FromBytes(sbom)}},
+		{query: "?artifactType=application/vnd.example.other", filtered: true},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/referrers/"+subject.String()+tc.query, nil))
+		if rec.Code != http.StatusOK {
+			t.Fatalf("got status %d listing referrers %q, want %d: %s", rec.Code, tc.query, http.StatusOK, rec.Body)
+		}
+		if filtered := rec.Header().Get("OCI-Filters-Applied") == "artifactType"; filtered != tc.filtered {
+			t.Errorf("got OCI-Filters-Applied %q for %q", rec.Header().Get("OCI-Filters-Applied"), tc.query)
+		}
+		var index v1.Index
+		if err := json.Unmarshal(rec.Body.Bytes(), &index); err != nil {
+			t.Fatalf("failed to decode referrers: %v", err)
+		}
+		got := map[digest.Digest]bool{}
+		for _, referrer := range index.Manifests {
+			got[referrer.Digest] = true
+			if referrer.ArtifactType == "" || referrer.Annotations["org.example.layer"] == "" {
+				t.Errorf("got referrer %+v without its artifactType or annotations", referrer)
+			}
+		}
+		if len(got) != len(tc.want) {
+			t.Errorf("got referrers %v for %q, want %v", index.Manifests, tc.query, tc.want)
+		}
+		for _, dgst := range tc.want {
+			if !got[dgst] {
+				t.Errorf("referrer %s missing for %q", dgst, tc.query)
+			}
+		}
+	}
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/referrers/"+digest.FromString("unknown").String(), nil))
+	if rec.Code != http.StatusOK || strings.Contains(rec.Body.String(), `"manifests":null`) {
+		t.Errorf("got status %d and %s for an unknown subject, want %d and no referrers", rec.Code, rec.Body, http.StatusOK)
+	}
+}
+
+func TestRootAndVersion(t *testing.T) {
+	router := newTestRouter(t, newTestRegistry(t))
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/", nil))
+	var root map[string]string
+	if err := json.Unmarshal(rec.Body.Bytes(), &root); rec.Code != http.StatusOK || err != nil {
+		t.Fatalf("got status %d and %s (%v) for /, want %d and JSON", rec.Code, rec.Body, err, http.StatusOK)
+	}
+	if root["service"] != "reg" || root["api"] != "/v2/" || root["version"] != ReadBuildInfo().Version {
+		t.Errorf("got %v for /", root)
+	}
+	if rec := serveTestRequest(router, httptest.NewRequest(http.MethodHead, "/", nil)); rec.Code != http.StatusOK {
+		t.Errorf("got status %d for HEAD /, want %d", rec.Code, http.StatusOK)
+	}
+
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/version", nil))
+	var version BuildInfo
+	if err := json.Unmarshal(rec.Body.Bytes(), &version); rec.Code != http.StatusOK || err != nil {
+		t.Fatalf("got status %d and %s (%v) for /version, want %d and JSON", rec.Code, rec.Body, err, http.StatusOK)
+	}
+	if version.GoVersion != runtime.Version() {
+		t.Errorf("got Go version %q, want %q", version.GoVersion, runtime.Version())
+	}
+
+	// NOTICE: the API root is still the one clients probe
+	if rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/", nil)); rec.Code != http.StatusOK {
+		t.Errorf("got status %d for /v2/, want %d", rec.Code, http.StatusOK)
+	}
+}
+
+func TestDanglingTag(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = true })
+	pushTestImage(t, r, "foo", "latest", "layer")
+	missing := digest.FromString("deleted manifest")
+	linkKey := fmt.Sprintf("%s/repositories/foo/_manifests/tags/dangling/current/link", r.prefix)
+	if err := r.storage.PutObject(context.Background(), linkKey, []byte(missing.String())); err != nil {
+		t.Fatalf("failed to put tag link: %v", err)
+	}
+	router := newTestRouter(t, r)
+
+	if _, _, err := r.getManifest(context.Background(), "foo", "dangling"); !errors.Is(err, ErrDanglingTag) || !errors.Is(err, fs.ErrNotExist) {
+		t.Errorf("got error %v, want %v wrapping %v", err, ErrDanglingTag, fs.ErrNotExist)
+	}
+	for _, tc := range []struct {
+		tag      string
+		dangling bool
+	}{
+		{tag: "dangling", dangling: true},
+		{tag: "missing", dangling: false},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/"+tc.tag, nil))
+		if rec.Code != http.StatusNotFound || !strings.Contains(rec.Body.String(), errCodeManifestUnknown) {
+			t.Errorf("got status %d for foo:%s, want %d %s: %s", rec.Code, tc.tag, http.StatusNotFound, errCodeManifestUnknown, rec.Body)
+		}
+		if dangling := strings.Contains(rec.Body.String(), "dangling tag"); dangling != tc.dangling {
+			t.Errorf("got %s for foo:%s, want it reported as dangling: %v", rec.Body, tc.tag, tc.dangling)
+		}
+	}
+}
@@ -0,0 +1,220 @@
+package reg
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"net/http"
+	"net/http/httptest"
+	"os"
+	"path/filepath"
+	"sync/atomic"
+	"testing"
+	"time"
+
+	"github.com/opencontainers/go-digest"
+	specs "github.com/opencontainers/image-spec/specs-go"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+// testConfig is a registry on a filesystem storage and a database in dir.
+func testConfig(dir string) Config {
+	return Config{
+		Storage:           StorageFS,
+		StorageRoot:       filepath.Join(dir, "storage"),
+		DBPath:            filepath.Join(dir, "registry.db"),
+		DBBusyTimeout:     time.Second,
+		MaxManifestBytes:  defaultMaxManifestBytes,
+		ManifestCacheSize: 16,
+	}
+}
+
+func newTestRegistry(t *testing.T, opts ...func(*Config)) *Registry {
+	t.Helper()
+	cfg := testConfig(t.TempDir())
+	for _, opt := range opts {
+		opt(&cfg)
+	}
+	return newTestRegistryWithStorage(t, newTestFSStorage(t, cfg), cfg)
+}
+
+// newCountingTestRegistry is newTestRegistry with a countingStorage over the filesystem storage.
+func newCountingTestRegistry(t *testing.T, opts ...func(*Config)) (*Registry, *countingStorage) {
+	t.Helper()
+	cfg := testConfig(t.TempDir())
+	for _, opt := range opts {
+		opt(&cfg)
+	}
+	storage := &countingStorage{Storage: newTestFSStorage(t, cfg)}
+	return newTestRegistryWithStorage(t, storage, cfg), storage
+}
+
+// newTestFSStorage creates the filesystem storage of cfg, which has to exist beforehand.
+func newTestFSStorage(t *testing.T, cfg Config) *fsStorage {
+	t.Helper()
+	if err := os.MkdirAll(cfg.StorageRoot, 0o755); err != nil {
+		t.Fatalf("failed to create storage root: %v", err)
+	}
+	storage, err := newFSStorage(cfg.StorageRoot)
+	if err != nil {
+		t.Fatalf("failed to create storage: %v", err)
+	}
+	return storage
+}
+
+func newTestRegistryWithStorage(t *testing.T, storage Storage, cfg Config) *Registry {
+	t.Helper()
+	r, err := NewRegistryWithStorage(storage, cfg)
+	if err != nil {
+		t.Fatalf("failed to create registry: %v", err)
+	}
+	t.Cleanup(func() { _ = r.Close() })
+	return r
+}
+
+// putTestBlob writes data straight to the blob store, as a finished upload would.
+func putTestBlob(t *testing.T, r *Registry, data []byte) v1.Descriptor {
+	t.Helper()
+	dgst := digest.FromBytes(data)
+	key, err := r.blobKey(dgst.String())
+	if err != nil {
+		t.Fatalf("failed to get blob key: %v", err)
+	}
+	if err := r.storage.PutObject(context.Background(), key, data); err != nil {
+		t.Fatalf("failed to put blob: %v", err)
+	}
+	return v1.Descriptor{MediaType: v1.MediaTypeImageLayer, Digest: dgst, Size: int64(len(data))}
+}
+
+// testManifest builds an image manifest of a config and a single layer holding layer.
+func testManifest(t *testing.T, r *Registry, layer string) []byte {
+	t.Helper()
+	config := putTestBlob(t, r, []byte(`{"architecture":"amd64","os":"linux"}`))
+	config.MediaType = v1.MediaTypeImageConfig
+	manifestBytes, err := json.Marshal(v1.Manifest{
+		Versioned: specs.Versioned{SchemaVersion: 2},
+		MediaType: v1.MediaTypeImageManifest,
+		Config:    config,
+		Layers:    []v1.Descriptor{putTestBlob(t, r, []byte(layer))},
+	})
+	if err != nil {
+		t.Fatalf("failed to marshal manifest: %v", err)
+	}
+	return manifestBytes
+}
+
+// pushTestImage pushes an image with a single layer holding layer as name:tag.
+func pushTestImage(t *testing.T, r *Registry, name string, tag string, layer string) digest.Digest {
+	t.Helper()
+	return pushTestManifest(t, r, name, tag, testManifest(t, r, layer))
+}
+
+func pushTestManifest(t *testing.T, r *Registry, name string, reference string, manifestBytes []byte) digest.Digest {
+	t.Helper()
+	dgst, err := r.putManifest(context.Background(), name, reference, manifestBytes)
+	if err != nil {
+		t.Fatalf("failed to push %s:%s: %v", name, reference, err)
+	}
+	return dgst
+}
+
+// countingStorage counts the keys listed and the URLs presigned by the storage it wraps,
+// and presigns without asking it, as the filesystem storage can't.
+type countingStorage struct {
+	Storage
+	listedKeys atomic.Int64
+	presigns   atomic.Int64
+}
+
+func (s *countingStorage) List(ctx context.Context, prefix string, fn func(ObjectInfo) error) error {
+	return s.Storage.List(ctx, prefix, func(obj ObjectInfo) error {
+		s.listedKeys.Add(1)
+		return fn(obj)
+	})
+}
+
+func (s *countingStorage) ListPrefixes(ctx context.Context, prefix string, fn func(string) error) error {
+	return s.Storage.ListPrefixes(ctx, prefix, func(p string) error {
+		s.listedKeys.Add(1)
+		return fn(p)
+	})
+}
+
+func (s *countingStorage) Presign(_ context.Context, method string, key string, _ time.Duration) (string, error) {
+	n := s.presigns.Add(1)
+	return fmt.Sprintf("https://storage.test/%s?method=%s&n=%d", key, method, n), nil
+}
+
+// panickingStorage fails the test on any call, through its nil Storage.
+type panickingStorage struct {
+	Storage
+}
+
+// linkTestManifest writes a manifest to storage alone and links it to name, as another
+// registry writing to the same bucket would, leaving the database behind.
+func linkTestManifest(t *testing.T, r *Registry, name string, manifestBytes []byte) digest.Digest {
+	t.Helper()
+	ctx := context.Background()
+	dgst := digest.FromBytes(manifestBytes)
+	key, err := r.blobKey(dgst.String())
+	if err != nil {
+		t.Fatalf("failed to get blob key: %v", err)
+	}
+	if err := r.storage.PutObject(ctx, key, manifestBytes); err != nil {
+		t.Fatalf("failed to put manifest: %v", err)
+	}
+	revisionKey := fmt.Sprintf("%s/repositories/%s/_manifests/revisions/%s/%s/link", r.prefix, name, dgst.Algorithm(), dgst.Hex())
+	if err := r.storage.PutObject(ctx, revisionKey, []byte(dgst.String())); err != nil {
+		t.Fatalf("failed to put revision link: %v", err)
+	}
+	return dgst
+}
+
+// retargetTestTag points name:tag at manifestBytes in storage alone, like linkTestManifest.
+func retargetTestTag(t *testing.T, r *Registry, name string, tag string, manifestBytes []byte) digest.Digest {
+	t.Helper()
+	dgst := linkTestManifest(t, r, name, manifestBytes)
+	linkKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/current/link", r.prefix, name, tag)
+	if err := r.storage.PutObject(context.Background(), linkKey, []byte(dgst.String())); err != nil {
+		t.Fatalf("failed to put tag link: %v", err)
+	}
+	return dgst
+}
+
+func newTestRouter(t *testing.T, r *Registry) http.Handler {
+	t.Helper()
+	router, err := NewRouter(context.Background(), r)
+	if err != nil {
+		t.Fatalf("failed to create router: %v", err)
+	}
+	return router
+}
+
+func serveTestRequest(router http.Handler, req *http.Request) *httptest.ResponseRecorder {
+	rec := httptest.NewRecorder()
+	router.ServeHTTP(rec, req)
+	return rec
+}
+
+// getTestTags lists the tags of name through the API.
+func getTestTags(t *testing.T, router http.Handler, name string) []string {
+	t.Helper()
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/"+name+"/tags/list", nil))
+	if rec.Code != http.StatusOK {
+		t.Fatalf("got status %d listing the tags of %s, want %d: %s", rec.Code, name, http.StatusOK, rec.Body)
+	}
+	var list tags
+	if err := json.Unmarshal(rec.Body.Bytes(), &list); err != nil {
+		t.Fatalf("failed to decode tags: %v", err)
+	}
+	return list.Tags
+}
+
+// testAdminToken authorizes the admin requests of registries configured with it.
+const testAdminToken = "admin-token"
+
+func newTestAdminRequest(method string, target string) *http.Request {
+	req := httptest.NewRequest(method, target, nil)
+	req.Header.Set("Authorization", "Bearer "+testAdminToken)
+	return req
+}
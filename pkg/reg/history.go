@@ -0,0 +1,78 @@
+package reg
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"io"
+	"time"
+
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+// maxImageConfigBytes bounds the image configs read into memory, which are usually a few KiB
+// but grow with the number of history entries and labels.
+const maxImageConfigBytes = 16 << 20
+
+// ImageHistoryEntry is a step of an image build, along with the layer it produced, if any.
+type ImageHistoryEntry struct {
+	Digest     string     `json:"digest,omitempty"`
+	Size       int64      `json:"size,omitempty"`
+	CreatedBy  string     `json:"created_by,omitempty"`
+	Created    *time.Time `json:"created,omitempty"`
+	Comment    string     `json:"comment,omitempty"`
+	EmptyLayer bool       `json:"empty_layer,omitempty"`
+}
+
+// imageHistory joins the layers of an image manifest with the history of its config, from the
+// base layer to the top one. History entries marked as empty_layer (ENV, LABEL...) produced no
+// layer, every other one is matched with the next layer in order. Layers left over when the
+// history is shorter (e.g. squashed images or configs without history) are listed without it.
+func (r *Registry) imageHistory(ctx context.Context, name string, reference string) ([]ImageHistoryEntry, error) {
+	manifest, _, err := r.getManifest(ctx, name, reference)
+	if err != nil {
+		return nil, err
+	}
+	if manifest.Config.Digest == "" {
+		return nil, fmt.Errorf("%w: %s is not an image manifest", ErrManifestInvalid, reference)
+	}
+
+	blobKey, err := r.blobKey(manifest.Config.Digest.String())
+	if err != nil {
+		return nil, err
+	}
+	body, err := r.storageFor(name).GetObject(ctx, blobKey)
+	if err != nil {
+		return nil, fmt.Errorf("failed to get config %s: %w", manifest.Config.Digest, err)
+	}
+	defer body.Close()
+	configBytes, err := io.ReadAll(io.LimitReader(body, maxImageConfigBytes))
+	if err != nil {
+		return nil, fmt.Errorf("failed to read config %s: %w", manifest.Config.Digest, err)
+	}
+	var config v1.Image
+	if err := json.Unmarshal(configBytes, &config); err != nil {
+		return nil, fmt.Errorf("%w: malformed config %s: %w", ErrManifestInvalid, manifest.Config.Digest, err)
+	}
+
+	entries := make([]ImageHistoryEntry, 0, max(len(config.History), len(manifest.Layers)))
+	layer := 0
+	for _, history := range config.History {
+		entry := ImageHistoryEntry{
+			CreatedBy:  history.CreatedBy,
+			Created:    history.Created,
+			Comment:    history.Comment,
+			EmptyLayer: history.EmptyLayer,
+		}
+		if !history.EmptyLayer && layer < len(manifest.Layers) {
+			entry.Digest = manifest.Layers[layer].Digest.String()
+			entry.Size = manifest.Layers[layer].Size
+			layer++
+		}
+		entries = append(entries, entry)
+	}
+	for _, descriptor := range manifest.Layers[layer:] {
+		entries = append(entries, ImageHistoryEntry{Digest: descriptor.Digest.String(), Size: descriptor.Size})
+	}
+	return entries, nil
+}
@@ -0,0 +1,149 @@
+package reg
+
+import (
+	"encoding/json"
+	"errors"
+	"fmt"
+	"slices"
+
+	"github.com/opencontainers/go-digest"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+const (
+	mediaTypeDockerSchema1       = "application/vnd.docker.distribution.manifest.v1+json"
+	mediaTypeDockerSchema1Signed = "application/vnd.docker.distribution.manifest.v1+prettyjws"
+	mediaTypeDockerSchema2       = "application/vnd.docker.distribution.manifest.v2+json"
+	mediaTypeDockerManifestList  = "application/vnd.docker.distribution.manifest.list.v2+json"
+	mediaTypeDockerImageConfig   = "application/vnd.docker.container.image.v1+json"
+)
+
+var supportedManifestMediaTypes = map[string]bool{
+	v1.MediaTypeImageManifest:    true,
+	v1.MediaTypeImageIndex:       true,
+	mediaTypeDockerSchema2:       true,
+	mediaTypeDockerManifestList:  true,
+	mediaTypeDockerSchema1:       true,
+	mediaTypeDockerSchema1Signed: true,
+}
+
+// ErrManifestMediaTypeNotAllowed marks manifests outside of --allow-media-type.
+var ErrManifestMediaTypeNotAllowed = errors.New("manifest media type not allowed")
+
+// mediaTypeAllowed reports whether manifests of a media type may be stored and served,
+// by default all the types the registry knows how to handle.
+func (r *Registry) mediaTypeAllowed(mediaType string) bool {
+	if len(r.cfg.AllowMediaTypes) == 0 {
+		return supportedManifestMediaTypes[mediaType]
+	}
+	return slices.Contains(r.cfg.AllowMediaTypes, mediaType)
+}
+
+// schema1Manifest covers the parts of the legacy Docker image manifest (schemaVersion 1)
+// we care about. It lists layers as fsLayers, from the top layer down, and has no config blob.
+type schema1Manifest struct {
+	SchemaVersion int `json:"schemaVersion"`
+	FSLayers      []struct {
+		BlobSum digest.Digest `json:"blobSum"`
+	} `json:"fsLayers"`
+	Signatures []json.RawMessage `json:"signatures,omitempty"`
+}
+
+// manifestMediaType determines the media type to serve a manifest with. The top-level mediaType
+// is optional for OCI manifests, so without it the type is sniffed from the manifest's shape.
+func manifestMediaType(manifestBytes []byte, manifest *v1.Manifest) string {
+	if manifest.MediaType != "" {
+		return manifest.MediaType
+	}
+	if manifest.SchemaVersion == 1 {
+		var m schema1Manifest
+		if err := json.Unmarshal(manifestBytes, &m); err == nil && len(m.Signatures) > 0 {
+			return mediaTypeDockerSchema1Signed
+		}
+		return mediaTypeDockerSchema1
+	}
+
+	var index v1.Index
+	if err := json.Unmarshal(manifestBytes, &index); err == nil && len(index.Manifests) > 0 {
+		return v1.MediaTypeImageIndex
+	}
+	switch manifest.Config.MediaType {
+	case mediaTypeDockerImageConfig:
+		return mediaTypeDockerSchema2
+	case "":
+	default:
+		return v1.MediaTypeImageManifest
+	}
+	return mediaTypeDockerSchema2
+}
+
+// manifestReferences returns the digests of all blobs a manifest needs to be pullable:
+// its config and layers or, for indexes, the child manifests.
+func manifestReferences(manifestBytes []byte, manifest *v1.Manifest) ([]string, error) {
+	refs, err := manifestLayerDigests(manifestBytes, manifest)
+	if err != nil {
+		return nil, err
+	}
+	if manifest.Config.Digest != "" {
+		refs = append(refs, manifest.Config.Digest.String())
+	}
+
+	var index v1.Index
+	if err := json.Unmarshal(manifestBytes, &index); err != nil {
+		return nil, fmt.Errorf("failed to parse manifest: %w", err)
+	}
+	for _, child := range index.Manifests {
+		refs = append(refs, child.Digest.String())
+	}
+	return refs, nil
+}
+
+// manifestReferrer returns the descriptor a manifest is listed with by the referrers API of its subject.
+// Without an explicit artifactType, the config media type stands in for it, as required by the spec.
+func manifestReferrer(manifestBytes []byte, manifest *v1.Manifest) v1.Descriptor {
+	artifactType := manifest.ArtifactType
+	if artifactType == "" {
+		artifactType = manifest.Config.MediaType
+	}
+	return v1.Descriptor{
+		MediaType:    manifestMediaType(manifestBytes, manifest),
+		ArtifactType: artifactType,
+		Digest:       digest.FromBytes(manifestBytes),
+		Size:         int64(len(manifestBytes)),
+		Annotations:  manifest.Annotations,
+	}
+}
+
+// manifestChildren returns the digests of the manifests an index points at, none for image manifests.
+func manifestChildren(manifestBytes []byte) ([]digest.Digest, error) {
+	var index v1.Index
+	if err := json.Unmarshal(manifestBytes, &index); err != nil {
+		return nil, fmt.Errorf("failed to parse manifest: %w", err)
+	}
+	children := make([]digest.Digest, 0, len(index.Manifests))
+	for _, child := range index.Manifests {
+		children = append(children, child.Digest)
+	}
+	return children, nil
+}
+
+// manifestLayerDigests returns the layer digests referenced by the manifest, ordered from the base layer up.
+func manifestLayerDigests(manifestBytes []byte, manifest *v1.Manifest) ([]string, error) {
+	if manifest.SchemaVersion != 1 {
+		digests := make([]string, 0, len(manifest.Layers))
+		for _, layer := range manifest.Layers {
+			digests = append(digests, layer.Digest.String())
+		}
+		return digests, nil
+	}
+
+	var m schema1Manifest
+	if err := json.Unmarshal(manifestBytes, &m); err != nil {
+		return nil, fmt.Errorf("failed to parse schema1 manifest: %w", err)
+	}
+	digests := make([]string, 0, len(m.FSLayers))
+	for i := len(m.FSLayers) - 1; i >= 0; i-- {
+		digests = append(digests, m.FSLayers[i].BlobSum.String())
+	}
+	return digests, nil
+}
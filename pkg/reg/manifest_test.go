@@ -0,0 +1,62 @@
+package reg
+
+import (
+	"encoding/json"
+	"net/http"
+	"net/http/httptest"
+	"testing"
+
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+func TestManifestMediaType(t *testing.T) {
+	for name, tc := range map[string]struct {
+		manifest string
+		want     string
+	}{
+		"top-level":          {manifest: `{"schemaVersion":2,"mediaType":"application/vnd.custom+json"}`, want: "application/vnd.custom+json"},
+		"oci config":         {manifest: `{"schemaVersion":2,"config":{"mediaType":"` + v1.MediaTypeImageConfig + `"}}`, want: v1.MediaTypeImageManifest},
+		"docker config":      {manifest: `{"schemaVersion":2,"config":{"mediaType":"` + mediaTypeDockerImageConfig + `"}}`, want: mediaTypeDockerSchema2},
+		"artifact config":    {manifest: `{"schemaVersion":2,"config":{"mediaType":"application/vnd.custom.config"}}`, want: v1.MediaTypeImageManifest},
+		"index":              {manifest: `{"schemaVersion":2,"manifests":[{"digest":"sha256:abc"}]}`, want: v1.MediaTypeImageIndex},
+		"schema 1":           {manifest: `{"schemaVersion":1}`, want: mediaTypeDockerSchema1},
+		"signed schema 1":    {manifest: `{"schemaVersion":1,"signatures":[{}]}`, want: mediaTypeDockerSchema1Signed},
+		"nothing to go with": {manifest: `{"schemaVersion":2}`, want: mediaTypeDockerSchema2},
+	} {
+		t.Run(name, func(t *testing.T) {
+			var manifest v1.Manifest
+			if err := json.Unmarshal([]byte(tc.manifest), &manifest); err != nil {
+				t.Fatalf("failed to unmarshal manifest: %v", err)
+			}
+			if got := manifestMediaType([]byte(tc.manifest), &manifest); got != tc.want {
+				t.Fatalf("got media type %q, want %q", got, tc.want)
+			}
+		})
+	}
+}
+
+func TestManifestWithoutMediaTypeContentType(t *testing.T) {
+	r := newTestRegistry(t)
+	var manifest map[string]any
+	if err := json.Unmarshal(testManifest(t, r, "layer"), &manifest); err != nil {
+		t.Fatalf("failed to unmarshal manifest: %v", err)
+	}
+	delete(manifest, "mediaType")
+	manifestBytes, err := json.Marshal(manifest)
+	if err != nil {
+		t.Fatalf("failed to marshal manifest: %v", err)
+	}
+	retargetTestTag(t, r, "foo", "latest", manifestBytes)
+	router := newTestRouter(t, r)
+
+	// NOTICE: the first request resolves the tag from storage, the second is served from the cache
+	for range 2 {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest", nil))
+		if rec.Code != http.StatusOK {
+			t.Fatalf("got status %d, want %d: %s", rec.Code, http.StatusOK, rec.Body)
+		}
+		if got := rec.Header().Get("Content-Type"); got != v1.MediaTypeImageManifest {
+			t.Fatalf("got Content-Type %q, want %q", got, v1.MediaTypeImageManifest)
+		}
+	}
+}
@@ -0,0 +1,186 @@
+package reg
+
+import (
+	"compress/gzip"
+	"context"
+	"encoding/json"
+	"io"
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+	"time"
+)
+
+// slowStorage takes delay to start reading an object or write a part, unless the context ends first.
+type slowStorage struct {
+	Storage
+	delay time.Duration
+}
+
+func (s slowStorage) wait(ctx context.Context) error {
+	select {
+	case <-time.After(s.delay):
+		return nil
+	case <-ctx.Done():
+		return ctx.Err()
+	}
+}
+
+func (s slowStorage) GetObject(ctx context.Context, key string) (io.ReadCloser, error) {
+	if err := s.wait(ctx); err != nil {
+		return nil, err
+	}
+	return s.Storage.GetObject(ctx, key)
+}
+
+func (s slowStorage) GetObjectRange(ctx context.Context, key string, offset int64, length int64) (io.ReadCloser, error) {
+	if err := s.wait(ctx); err != nil {
+		return nil, err
+	}
+	return s.Storage.GetObjectRange(ctx, key, offset, length)
+}
+
+func (s slowStorage) UploadPart(ctx context.Context, key string, uploadID string, partNumber int32, data []byte) error {
+	if err := s.wait(ctx); err != nil {
+		return err
+	}
+	return s.Storage.UploadPart(ctx, key, uploadID, partNumber, data)
+}
+
+func TestRequestTimeoutSparesBlobTransfers(t *testing.T) {
+	cfg := testConfig(t.TempDir())
+	cfg.NoCache = true
+	cfg.RequestTimeout = 20 * time.Millisecond
+	fsStorage := newTestFSStorage(t, cfg)
+	r := newTestRegistryWithStorage(t, slowStorage{Storage: fsStorage, delay: 200 * time.Millisecond}, cfg)
+	blob := putTestBlob(t, r, []byte("blob"))
+	router := newTestRouter(t, r)
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest", nil))
+	if rec.Code != http.StatusGatewayTimeout {
+		t.Fatalf("got status %d for a slow manifest, want %d", rec.Code, http.StatusGatewayTimeout)
+	}
+
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/blobs/"+blob.Digest.String(), nil))
+	if rec.Code != http.StatusOK || rec.Body.String() != "blob" {
+		t.Fatalf("got status %d and %q for a slow blob, want %d: %s", rec.Code, rec.Body, http.StatusOK, rec.Body)
+	}
+
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+	}
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodPatch, rec.Header().Get("Location"), strings.NewReader("chunk")))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d for a slow chunk, want %d: %s", rec.Code, http.StatusAccepted, rec.Body)
+	}
+}
+
+func TestAcceptsGzip(t *testing.T) {
+	for header, want := range map[string]bool{
+		"gzip":                   true,
+		"deflate, gzip;q=0.5":    true,
+		"*":                      true,
+		"":                       false,
+		"deflate, br":            false,
+		"gzip;q=0":               false,
+		"br, gzip; q=0, deflate": false,
+	} {
+		if got := acceptsGzip(header); got != want {
+			t.Errorf("acceptsGzip(%q) = %v, want %v", header, got, want)
+		}
+	}
+}
+
+func TestCompressListings(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.Compress = true
+	})
+	pushTestImage(t, r, "foo", "latest", "layer")
+	router := newTestRouter(t, r)
+
+	req := httptest.NewRequest(http.MethodGet, "/v2/foo/tags/list", nil)
+	req.Header.Set("Accept-Encoding", "gzip")
+	rec := serveTestRequest(router, req)
+	if rec.Code != http.StatusOK || rec.Header().Get("Content-Encoding") != "gzip" {
+		t.Fatalf("got status %d and Content-Encoding %q, want %d gzip", rec.Code, rec.Header().Get("Content-Encoding"), http.StatusOK)
+	}
+	if vary := rec.Header().Get("Vary"); vary != "Accept-Encoding" {
+		t.Errorf("got Vary %q, want Accept-Encoding", vary)
+	}
+	reader, err := gzip.NewReader(rec.Body)
+	if err != nil {
+		t.Fatalf("failed to read compressed tags: %v", err)
+	}
+	var list tags
+	if err := json.NewDecoder(reader).Decode(&list); err != nil {
+		t.Fatalf("failed to decode compressed tags: %v", err)
+	}
+	if len(list.Tags) != 1 || list.Tags[0] != "latest" {
+		t.Errorf("got tags %v, want [latest]", list.Tags)
+	}
+
+	req = httptest.NewRequest(http.MethodGet, "/v2/foo/tags/list", nil)
+	req.Header.Set("Accept-Encoding", "gzip;q=0")
+	if rec := serveTestRequest(router, req); rec.Header().Get("Content-Encoding") != "" {
+		t.Errorf("got Content-Encoding %q for a client refusing gzip", rec.Header().Get("Content-Encoding"))
+	}
+
+	req = httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest", nil)
+	req.Header.Set("Accept-Encoding", "gzip")
+	if rec := serveTestRequest(router, req); rec.Header().Get("Content-Encoding") != "" {
+		t.Errorf("got Content-Encoding %q for a manifest, want it served verbatim", rec.Header().Get("Content-Encoding"))
+	}
+}
+
+func TestMethodNotAllowed(t *testing.T) {
+	router := newTestRouter(t, newTestRegistry(t))
+	blobDigest := "sha256:" + strings.Repeat("0", 64)
+
+	for _, tc := range []struct {
+		method string
+		target string
+		allow  string
+	}{
+		{method: http.MethodPost, target: "/v2/foo/manifests/latest", allow: "GET, HEAD, PUT, DELETE"},
+		{method: http.MethodPut, target: "/v2/foo/blobs/" + blobDigest, allow: "GET, HEAD, DELETE"},
+		{method: http.MethodDelete, target: "/v2/foo/tags/list", allow: "GET"},
+		{method: http.MethodPost, target: "/v2/_catalog", allow: "GET"},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(tc.method, tc.target, nil))
+		if rec.Code != http.StatusMethodNotAllowed || !strings.Contains(rec.Body.String(), errCodeUnsupported) {
+			t.Errorf("got status %d for %s %s, want %d %s: %s", rec.Code, tc.method, tc.target, http.StatusMethodNotAllowed, errCodeUnsupported, rec.Body)
+		}
+		if allow := rec.Header().Get("Allow"); allow != tc.allow {
+			t.Errorf("got Allow %q for %s %s, want %q", allow, tc.method, tc.target, tc.allow)
+		}
+	}
+}
+
+func TestListingCacheControl(t *testing.T) {
+	for _, tc := range []struct {
+		seconds int
+		want    string
+	}{
+		{seconds: 30, want: "max-age=30"},
+		{seconds: 0, want: ""},
+	} {
+		r := newTestRegistry(t, func(cfg *Config) { cfg.ListingCacheSeconds = tc.seconds })
+		pushTestImage(t, r, "foo", "latest", "layer")
+		router := newTestRouter(t, r)
+
+		for _, target := range []string{"/v2/_catalog", "/v2/foo/tags/list", "/v2/stats"} {
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, target, nil))
+			if got := rec.Header().Get("Cache-Control"); rec.Code != http.StatusOK || got != tc.want {
+				t.Errorf("got status %d and Cache-Control %q for %s, want %d and %q", rec.Code, got, target, http.StatusOK, tc.want)
+			}
+		}
+		// NOTICE: neither errors nor manifests, which are revalidated with their ETag, are cacheable
+		for _, target := range []string{"/v2/bar/tags/list", "/v2/foo/manifests/latest"} {
+			if got := serveTestRequest(router, httptest.NewRequest(http.MethodGet, target, nil)).Header().Get("Cache-Control"); got != "" {
+				t.Errorf("got Cache-Control %q for %s, want none", got, target)
+			}
+		}
+	}
+}
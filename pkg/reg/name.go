@@ -0,0 +1,65 @@
+package reg
+
+import (
+	"net/http"
+	"regexp"
+
+	"github.com/gorilla/mux"
+	"github.com/opencontainers/go-digest"
+)
+
+const maxRepositoryNameLength = 255
+
+// repositoryNamePattern follows the distribution grammar: lowercase alphanumeric path components,
+// optionally joined by '.', '_', '__' or runs of '-', separated by '/'.
+var repositoryNamePattern = regexp.MustCompile(
+	`^[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*)*$`,
+)
+
+// tagPattern is the OCI distribution tag grammar.
+var tagPattern = regexp.MustCompile(`^[a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}$`)
+
+func validRepositoryName(name string) bool {
+	return len(name) <= maxRepositoryNameLength && repositoryNamePattern.MatchString(name)
+}
+
+func validTag(tag string) bool {
+	return tagPattern.MatchString(tag)
+}
+
+// validateRepositoryNames rejects requests naming an invalid repository before they reach storage,
+// where a name like "../etc" would otherwise end up spliced into an object key.
+func validateRepositoryNames(next http.Handler) http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		vars := mux.Vars(r)
+		for _, key := range []string{"name", "other_name"} {
+			name, ok := vars[key]
+			if !ok || validRepositoryName(name) {
+				continue
+			}
+			writeOCIError(w, r, http.StatusBadRequest, errCodeNameInvalid, "invalid repository name", map[string]string{"name": name})
+			return
+		}
+		next.ServeHTTP(w, r)
+	})
+}
+
+func validReference(reference string) bool {
+	if _, err := digest.Parse(reference); err == nil {
+		return true
+	}
+	return validTag(reference)
+}
+
+// validateReferences rejects manifest references which are neither a tag nor a digest, since tags
+// end up in object keys (.../tags/<tag>/current/link) just like repository names.
+func validateReferences(next http.Handler) http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		reference, ok := mux.Vars(r)["reference"]
+		if ok && !validReference(reference) {
+			writeOCIError(w, r, http.StatusBadRequest, errCodeTagInvalid, "invalid tag or digest", map[string]string{"reference": reference})
+			return
+		}
+		next.ServeHTTP(w, r)
+	})
+}
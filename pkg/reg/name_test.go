@@ -0,0 +1,86 @@
+package reg
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+)
+
+func TestValidRepositoryName(t *testing.T) {
+	for name, want := range map[string]bool{
+		"foo":                    true,
+		"foo/bar":                true,
+		"team/project/app":       true,
+		"foo.bar/baz_qux/a--b":   true,
+		"foo__bar":               true,
+		strings.Repeat("a", 255): true,
+		strings.Repeat("a", 256): false,
+		"":                       false,
+		"Foo":                    false,
+		"../etc":                 false,
+		"foo/../bar":             false,
+		"foo//bar":               false,
+		"foo/":                   false,
+		"-foo":                   false,
+		"foo___bar":              false,
+		"foo bar":                false,
+	} {
+		if got := validRepositoryName(name); got != want {
+			t.Errorf("validRepositoryName(%q) = %v, want %v", name, got, want)
+		}
+	}
+}
+
+func TestInvalidRepositoryNameRejected(t *testing.T) {
+	// NOTICE: invalid names never get as far as storage
+	router := newTestRouter(t, newTestRegistryWithStorage(t, panickingStorage{}, testConfig(t.TempDir())))
+	for _, target := range []string{
+		"/v2/Foo/manifests/latest",
+		"/v2/foo..bar/tags/list",
+		"/v2/foo/-bar/blobs/sha256:" + strings.Repeat("0", 64),
+		"/v2/foo/blobs/uploads/?mount=sha256:" + strings.Repeat("0", 64) + "&from=Bar",
+	} {
+		method := http.MethodGet
+		if strings.Contains(target, "/uploads/") {
+			method = http.MethodPost
+		}
+		rec := serveTestRequest(router, httptest.NewRequest(method, target, nil))
+		if rec.Code != http.StatusBadRequest || !strings.Contains(rec.Body.String(), errCodeNameInvalid) {
+			t.Errorf("got status %d for %s, want %d %s: %s", rec.Code, target, http.StatusBadRequest, errCodeNameInvalid, rec.Body)
+		}
+	}
+}
+
+func TestValidReference(t *testing.T) {
+	for reference, want := range map[string]bool{
+		"latest":                            true,
+		"v1.0.0-rc_1":                       true,
+		"_private":                          true,
+		strings.Repeat("a", 128):            true,
+		"sha256:" + strings.Repeat("a", 64): true,
+		strings.Repeat("a", 129):            false,
+		"":                                  false,
+		".hidden":                           false,
+		"-flag":                             false,
+		"latest\n":                          false,
+		"a b":                               false,
+		"a:b":                               false,
+		"sha256:" + strings.Repeat("a", 63): false,
+		"sha256:" + strings.Repeat("A", 64): false,
+	} {
+		if got := validReference(reference); got != want {
+			t.Errorf("validReference(%q) = %v, want %v", reference, got, want)
+		}
+	}
+}
+
+func TestInvalidReferenceRejected(t *testing.T) {
+	router := newTestRouter(t, newTestRegistryWithStorage(t, panickingStorage{}, testConfig(t.TempDir())))
+	for _, reference := range []string{".hidden", "a%0Ab", "a:b", "sha256:abc", strings.Repeat("a", 129)} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/"+reference, nil))
+		if rec.Code != http.StatusBadRequest || !strings.Contains(rec.Body.String(), errCodeTagInvalid) {
+			t.Errorf("got status %d for %q, want %d %s: %s", rec.Code, reference, http.StatusBadRequest, errCodeTagInvalid, rec.Body)
+		}
+	}
+}
@@ -0,0 +1,111 @@
+package reg
+
+import (
+	"context"
+	"encoding/json"
+	"io"
+	"log/slog"
+	"sync"
+	"sync/atomic"
+	"time"
+)
+
+const (
+	ProgressFormatText = "text"
+	ProgressFormatJSON = "json"
+)
+
+// bootstrapProgressInterval is how often progress is reported even if nothing changed,
+// so that wrappers can tell a stalled bootstrap from a finished one.
+const bootstrapProgressInterval = 10 * time.Second
+
+// BootstrapProgress is a snapshot of a running bootstrap, emitted as one JSON object
+// per line with --progress-format json. The last one has Event "summary".
+type BootstrapProgress struct {
+	Event           string  `json:"event"`
+	Repo            string  `json:"repo,omitempty"`
+	TagsFound       uint64  `json:"tags_found"`
+	TagsSkipped     uint64  `json:"tags_skipped"`
+	TagsDone        uint64  `json:"tags_done"`
+	TagsFailed      uint64  `json:"tags_failed"`
+	ManifestsCached uint64  `json:"manifests_cached"`
+	ElapsedSeconds  float64 `json:"elapsed_seconds"`
+}
+
+// bootstrapTracker counts the tags of a bootstrap, updated from the listing and the workers
+// concurrently, and reports them in the configured format.
+type bootstrapTracker struct {
+	format  string
+	out     io.Writer
+	started time.Time
+
+	mu   sync.Mutex
+	repo string
+
+	found, skipped, done, failed atomic.Uint64
+}
+
+func newBootstrapTracker(format string, out io.Writer) *bootstrapTracker {
+	return &bootstrapTracker{format: format, out: out, started: time.Now()}
+}
+
+func (t *bootstrapTracker) setRepo(repo string) {
+	t.mu.Lock()
+	t.repo = repo
+	t.mu.Unlock()
+}
+
+func (t *bootstrapTracker) snapshot(event string) BootstrapProgress {
+	t.mu.Lock()
+	repo := t.repo
+	t.mu.Unlock()
+	done, failed := t.done.Load(), t.failed.Load()
+	return BootstrapProgress{
+		Event:           event,
+		Repo:            repo,
+		TagsFound:       t.found.Load(),
+		TagsSkipped:     t.skipped.Load(),
+		TagsDone:        done,
+		TagsFailed:      failed,
+		ManifestsCached: done - failed,
+		ElapsedSeconds:  time.Since(t.started).Seconds(),
+	}
+}
+
+func (t *bootstrapTracker) report(ctx context.Context, event string) {
+	progress := t.snapshot(event)
+	if t.format != ProgressFormatJSON {
+		slog.InfoContext(ctx, "Bootstrap "+event, "repo", progress.Repo, "found", progress.TagsFound, "skipped", progress.TagsSkipped,
+			"done", progress.TagsDone, "failed", progress.TagsFailed, "elapsed", time.Since(t.started).Round(time.Second))
+		return
+	}
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	if err := json.NewEncoder(t.out).Encode(progress); err != nil {
+		slog.WarnContext(ctx, "error writing bootstrap progress", "error", err)
+	}
+}
+
+// reportPeriodically reports progress every bootstrapProgressInterval until the returned
+// function is called.
+func (t *bootstrapTracker) reportPeriodically(ctx context.Context) func() {
+	stop := make(chan struct{})
+	stopped := make(chan struct{})
+	go func() {
+		defer close(stopped)
+		ticker := time.NewTicker(bootstrapProgressInterval)
+		defer ticker.Stop()
+		for {
+			select {
+			case <-stop:
+				return
+			case <-ticker.C:
+				t.report(ctx, "progress")
+			}
+		}
+	}()
+	return func() {
+		close(stop)
+		<-stopped
+	}
+}
@@ -0,0 +1,60 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"fmt"
+	"io/fs"
+	"log/slog"
+	"slices"
+	"sync"
+
+	"golang.org/x/sync/errgroup"
+)
+
+const reconcileConcurrency = 16
+
+type ReconcileResult struct {
+	Repo    string            `json:"repo"`
+	Checked int               `json:"checked"`
+	Missing []string          `json:"missing"`
+	Failed  map[string]string `json:"failed,omitempty"`
+}
+
+// ReconcileRepository checks that every layer and config referenced by the cached manifests
+// of a repository exists in storage, e.g. after copying a bucket or to find incomplete pushes.
+func (r *Registry) ReconcileRepository(ctx context.Context, name string) (*ReconcileResult, error) {
+	blobs, err := r.db.ListRepositoryBlobs(name)
+	if err != nil {
+		return nil, fmt.Errorf("failed to list referenced blobs: %w", err)
+	}
+
+	result := &ReconcileResult{Repo: name, Checked: len(blobs), Missing: []string{}}
+	var mu sync.Mutex
+	var group errgroup.Group
+	group.SetLimit(reconcileConcurrency)
+	for _, blob := range blobs {
+		group.Go(func() error {
+			_, err := r.statBlob(ctx, name, blob.Digest)
+			if err == nil {
+				return nil
+			}
+			mu.Lock()
+			defer mu.Unlock()
+			if errors.Is(err, fs.ErrNotExist) {
+				result.Missing = append(result.Missing, blob.Digest)
+				return nil
+			}
+			slog.WarnContext(ctx, "error checking blob", "repo", name, "digest", blob.Digest, "error", err)
+			if result.Failed == nil {
+				result.Failed = make(map[string]string)
+			}
+			result.Failed[blob.Digest] = err.Error()
+			return nil
+		})
+	}
+	_ = group.Wait()
+
+	slices.Sort(result.Missing)
+	return result, nil
+}
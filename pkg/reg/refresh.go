@@ -0,0 +1,118 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"fmt"
+	"io/fs"
+	"log/slog"
+	"time"
+
+	"golang.org/x/sync/errgroup"
+)
+
+const (
+	// refreshBatchSize caps the tags checked per tick, each costing at least one storage call,
+	// so that a large database is walked over several ticks instead of flooding storage at once.
+	refreshBatchSize   = 256
+	refreshConcurrency = 8
+)
+
+// RunTagRefresh periodically re-resolves cached tags from storage until ctx is done,
+// so that tags moved behind the registry's back don't stay stale in the database.
+func (r *Registry) RunTagRefresh(ctx context.Context, interval time.Duration) {
+	ticker := time.NewTicker(interval)
+	defer ticker.Stop()
+
+	var cursor *string
+	for {
+		select {
+		case <-ctx.Done():
+			return
+		case <-ticker.C:
+		}
+		next, err := r.refreshTags(ctx, cursor)
+		if err != nil {
+			slog.ErrorContext(ctx, "error refreshing tags", "error", err)
+			continue
+		}
+		cursor = next
+	}
+}
+
+// refreshTags checks the batch of cached tags following cursor against storage and returns
+// the cursor for the next batch, nil once all tags have been checked.
+func (r *Registry) refreshTags(ctx context.Context, cursor *string) (*string, error) {
+	entries, next, err := r.db.ListAllTags(cursor, refreshBatchSize)
+	if err != nil {
+		return cursor, fmt.Errorf("failed to list cached tags: %w", err)
+	}
+
+	var updated, removed, failed int
+	results := make([]error, len(entries))
+	changes := make([]string, len(entries))
+	group, groupCtx := errgroup.WithContext(ctx)
+	group.SetLimit(refreshConcurrency)
+	for i, entry := range entries {
+		group.Go(func() error {
+			changes[i], results[i] = r.refreshTag(groupCtx, entry["repository"], entry["tag"])
+			return nil
+		})
+	}
+	_ = group.Wait()
+
+	for i, err := range results {
+		switch {
+		case err != nil:
+			slog.WarnContext(ctx, "error refreshing tag", "repo", entries[i]["repository"], "tag", entries[i]["tag"], "error", err)
+			failed++
+		case changes[i] == "updated":
+			updated++
+		case changes[i] == "removed":
+			removed++
+		}
+	}
+	slog.InfoContext(ctx, "refreshed cached tags", "checked", len(entries), "updated", updated, "removed", removed, "failed", failed)
+
+	if len(entries) < refreshBatchSize {
+		return nil, nil
+	}
+	return next, nil
+}
+
+// refreshTag brings the cached manifest of a tag in line with storage, reporting "updated"
+// or "removed" when it had to change anything.
+func (r *Registry) refreshTag(ctx context.Context, name string, tag string) (string, error) {
+	sha, err := r.getManifestSHA(ctx, name, tag)
+	if errors.Is(err, fs.ErrNotExist) {
+		r.evictManifest(name, tag)
+		if _, err := r.db.DeleteTag(name, tag); err != nil {
+			return "", fmt.Errorf("failed to delete stale tag: %w", err)
+		}
+		return "removed", nil
+	}
+	if err != nil {
+		return "", err
+	}
+
+	cachedManifestJSON, err := r.db.GetManifest(name, tag)
+	if err == nil && sha.Algorithm().FromString(cachedManifestJSON) == sha {
+		return "", nil
+	}
+
+	// NOTICE: fetched like any pull, so that the media type allowlist applies to refreshed tags too
+	manifest, manifestBytes, err := r.fetchManifest(ctx, name, sha)
+	if err != nil {
+		return "", fmt.Errorf("failed to get manifest %s: %w", sha, err)
+	}
+	if sha.Algorithm().FromBytes(manifestBytes) != sha {
+		return "", fmt.Errorf("%w: manifest %s", ErrDigestMismatch, sha)
+	}
+
+	if err := r.db.PutManifest(name, tag, string(manifestBytes), manifest); err != nil {
+		return "", fmt.Errorf("failed to store manifest: %w", err)
+	}
+	r.storeChildManifests(ctx, name, manifestBytes)
+	r.evictManifest(name, tag)
+	return "updated", nil
+}
@@ -0,0 +1,117 @@
+package reg
+
+import (
+	"context"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"testing"
+
+	specs "github.com/opencontainers/image-spec/specs-go"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+func TestListAllTagsNestedRepositories(t *testing.T) {
+	r := newTestRegistry(t)
+
+	// NOTICE: "foo-bar" and "foo/bar" sort between "foo" and "foo:..." tokens
+	want := map[string]bool{}
+	for _, repo := range []string{"foo", "foo/bar", "foo-bar"} {
+		var tags []string
+		for i := range 100 {
+			tag := fmt.Sprintf("v%03d", i)
+			tags = append(tags, tag)
+			want[repo+":"+tag] = true
+		}
+		if err := r.db.PutTags(repo, tags); err != nil {
+			t.Fatalf("failed to put tags: %v", err)
+		}
+	}
+
+	seen := map[string]bool{}
+	var cursor *string
+	for pages := 0; ; pages++ {
+		if pages > len(want) {
+			t.Fatal("listing does not terminate")
+		}
+		entries, next, err := r.db.ListAllTags(cursor, refreshBatchSize)
+		if err != nil {
+			t.Fatalf("failed to list tags: %v", err)
+		}
+		if next == nil {
+			break
+		}
+		for _, entry := range entries {
+			key := entry["repository"] + ":" + entry["tag"]
+			if seen[key] {
+				t.Fatalf("tag %s listed twice", key)
+			}
+			seen[key] = true
+		}
+		cursor = next
+	}
+
+	for key := range want {
+		if !seen[key] {
+			t.Errorf("tag %s was never listed", key)
+		}
+	}
+	if len(seen) != len(want) {
+		t.Errorf("listed %d tags, want %d", len(seen), len(want))
+	}
+}
+
+func TestRefreshTagUpdatesManifest(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t)
+	pushTestImage(t, r, "foo", "latest", "old layer")
+	want := testManifest(t, r, "new layer")
+	retargetTestTag(t, r, "foo", "latest", want)
+
+	change, err := r.refreshTag(ctx, "foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to refresh tag: %v", err)
+	}
+	if change != "updated" {
+		t.Fatalf("got change %q, want updated", change)
+	}
+	cached, err := r.db.GetManifest("foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to get cached manifest: %v", err)
+	}
+	if cached != string(want) {
+		t.Fatalf("cached manifest was not updated: %s", cached)
+	}
+}
+
+func TestRefreshTagRejectsDisallowedMediaType(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.AllowMediaTypes = []string{v1.MediaTypeImageManifest}
+	})
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+	before, err := r.db.GetManifest("foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to get cached manifest: %v", err)
+	}
+	index, err := json.Marshal(v1.Index{
+		Versioned: specs.Versioned{SchemaVersion: 2},
+		MediaType: v1.MediaTypeImageIndex,
+		Manifests: []v1.Descriptor{{MediaType: v1.MediaTypeImageManifest, Digest: dgst, Size: int64(len(before))}},
+	})
+	if err != nil {
+		t.Fatalf("failed to marshal index: %v", err)
+	}
+	retargetTestTag(t, r, "foo", "latest", index)
+
+	if _, err := r.refreshTag(ctx, "foo", "latest"); !errors.Is(err, ErrManifestMediaTypeNotAllowed) {
+		t.Fatalf("got error %v, want %v", err, ErrManifestMediaTypeNotAllowed)
+	}
+	after, err := r.db.GetManifest("foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to get cached manifest: %v", err)
+	}
+	if after != before {
+		t.Fatalf("disallowed manifest was cached: %s", after)
+	}
+}
@@ -3,23 +3,27 @@ package reg
 import (
 	"bytes"
 	"context"
+	"crypto/sha256"
+	"encoding"
 	"encoding/json"
 	"errors"
 	"fmt"
+	"hash"
 	"io"
 	"io/fs"
 	"log/slog"
 	"net/http"
+	"os"
+	"path"
 	"runtime"
+	"slices"
 	"strings"
+	"sync"
 	"sync/atomic"
 	"time"
 
-	"github.com/aws/aws-sdk-go-v2/aws"
-	v4 "github.com/aws/aws-sdk-go-v2/aws/signer/v4"
-	"github.com/aws/aws-sdk-go-v2/config"
-	"github.com/aws/aws-sdk-go-v2/service/s3"
-	"github.com/aws/aws-sdk-go-v2/service/s3/types"
+	lru "github.com/hashicorp/golang-lru/v2"
+	"github.com/hashicorp/golang-lru/v2/expirable"
 	_ "github.com/mattn/go-sqlite3"
 	"github.com/opencontainers/go-digest"
 	v1 "github.com/opencontainers/image-spec/specs-go/v1"
@@ -27,153 +31,592 @@ import (
 )
 
 type Registry struct {
-	s3Client *s3.Client
-	bucket   string
-	db       *RegistryDB
+	storage Storage
+	routes  []storageRoute
+	db      *RegistryDB
+	cfg     Config
+	acl     *ACL
+	// prefix is the root of the distribution layout in storage, without a trailing slash
+	prefix string
+
+	aliasesMu sync.RWMutex
+	aliases   map[string]string
+
+	manifestCache       *lru.Cache[string, cachedManifest]
+	manifestCacheHits   atomic.Uint64
+	manifestCacheMisses atomic.Uint64
+
+	childSlots chan struct{}
+
+	ttlOverrides []CacheTTLOverride
+
+	presignCache       *expirable.LRU[presignCacheKey, presignedBlob]
+	presignCacheHits   atomic.Uint64
+	presignCacheMisses atomic.Uint64
+
+	repositoryCache *expirable.LRU[string, bool]
 }
 
-var forcePathStyle = func(o *s3.Options) {
-	o.UsePathStyle = true
+func NewRegistry(ctx context.Context, cfg Config) (*Registry, error) {
+	storage, routes, err := newStorages(ctx, cfg)
+	if err != nil {
+		return nil, err
+	}
+
+	r, err := NewRegistryWithStorage(storage, cfg)
+	if err != nil {
+		return nil, err
+	}
+	r.routes = routes
+	return r, nil
 }
 
-func NewRegistry(ctx context.Context, bucket string) (*Registry, error) {
-	cfg, err := config.LoadDefaultConfig(ctx)
+// newStorages creates the default storage and the ones repositories are routed to with --bucket-route.
+func newStorages(ctx context.Context, cfg Config) (Storage, []storageRoute, error) {
+	if cfg.DBOnly {
+		return disabledStorage{}, nil, nil
+	}
+	var limiter *s3RequestLimiter
+	if cfg.MaxS3Concurrency > 0 {
+		limiter = newS3RequestLimiter(cfg.MaxS3Concurrency)
+	}
+	var storage Storage
+	var err error
+	switch cfg.Storage {
+	case StorageS3, "":
+		storage, err = newS3Storage(ctx, cfg, limiter)
+	case StorageFS:
+		storage, err = newFSStorage(cfg.StorageRoot)
+	default:
+		return nil, nil, fmt.Errorf("unknown storage backend: %s", cfg.Storage)
+	}
 	if err != nil {
-		return nil, fmt.Errorf("unable to load SDK config, %v", err)
+		return nil, nil, err
+	}
+
+	bucketRoutes, err := ParseBucketRoutes(cfg.BucketRoutes)
+	if err != nil {
+		return nil, nil, err
+	}
+	var routes []storageRoute
+	for _, route := range bucketRoutes {
+		routeCfg := cfg
+		routeCfg.Bucket = route.Bucket
+		routeStorage, err := newS3Storage(ctx, routeCfg, limiter)
+		if err != nil {
+			return nil, nil, fmt.Errorf("failed to create storage for bucket %s: %w", route.Bucket, err)
+		}
+		routes = append(routes, storageRoute{prefix: route.Prefix, storage: routeStorage})
+	}
+	return storage, routes, nil
+}
+
+type storageRoute struct {
+	prefix  string
+	storage Storage
+}
+
+// storageFor returns the storage holding a repository: the one routed by the longest matching
+// prefix, or the default one.
+func (r *Registry) storageFor(name string) Storage {
+	for _, route := range r.routes {
+		if strings.HasPrefix(name, route.prefix) {
+			return route.storage
+		}
+	}
+	return r.storage
+}
+
+// storages returns all configured storages, the default one first.
+func (r *Registry) storages() []Storage {
+	storages := []Storage{r.storage}
+	for _, route := range r.routes {
+		storages = append(storages, route.storage)
+	}
+	return storages
+}
+
+func NewRegistryWithStorage(storage Storage, cfg Config) (*Registry, error) {
+	var acl *ACL
+	if cfg.ACLPath != "" {
+		var err error
+		acl, err = LoadACL(cfg.ACLPath)
+		if err != nil {
+			return nil, fmt.Errorf("invalid acl %s: %w", cfg.ACLPath, err)
+		}
 	}
-	cfg.ResponseChecksumValidation = aws.ResponseChecksumValidationWhenRequired
-	s3Client := s3.NewFromConfig(cfg, forcePathStyle)
 
-	db, err := initSQLite("registry.db")
+	pragmas, err := ParseSQLitePragmas(cfg.SQLitePragmas)
+	if err != nil {
+		return nil, err
+	}
+	db, err := initSQLite(cfg.DBPath, cfg.DBBusyTimeout, pragmas)
 	if err != nil {
 		return nil, fmt.Errorf("failed to initialize database: %w", err)
 	}
+	db.compressManifests = cfg.CompressCache
+
+	r := &Registry{
+		storage: storage,
+		db:      db,
+		cfg:     cfg,
+		acl:     acl,
+		prefix:  cfg.storagePrefix(),
+	}
+	if cfg.ManifestCacheSize > 0 && !cfg.NoCache {
+		r.manifestCache, err = lru.New[string, cachedManifest](cfg.ManifestCacheSize)
+		if err != nil {
+			return nil, fmt.Errorf("failed to create manifest cache: %w", err)
+		}
+	}
+	if cfg.PrefetchChildren {
+		r.childSlots = make(chan struct{}, prefetchConcurrency)
+	}
+	if cfg.PresignCacheSize > 0 {
+		// NOTICE: entries expire halfway through the URL's validity, so that a cached URL
+		// always leaves clients a comfortable margin to follow the redirect.
+		r.presignCache = expirable.NewLRU[presignCacheKey, presignedBlob](cfg.PresignCacheSize, nil, presignExpiry/2)
+	}
+	if cfg.ListingCacheSeconds > 0 {
+		r.repositoryCache = expirable.NewLRU[string, bool](repositoryCacheSize, nil, time.Duration(cfg.ListingCacheSeconds)*time.Second)
+	}
+	if r.ttlOverrides, err = ParseCacheTTLOverrides(cfg.CacheTTLOverrides); err != nil {
+		return nil, err
+	}
+	if err := r.loadAliases(); err != nil {
+		return nil, fmt.Errorf("failed to load repository aliases: %w", err)
+	}
+	return r, nil
+}
 
-	return &Registry{
-		s3Client: s3Client,
-		bucket:   bucket,
-		db:       db,
-	}, nil
+// repositoryCacheSize bounds the repositories whose existence in storage is remembered.
+const repositoryCacheSize = 4096
+
+// prefetchConcurrency bounds the indexes having their children stored in the background at once, registry-wide.
+const prefetchConcurrency = 8
+
+func manifestCacheKey(name string, reference string) string {
+	return name + ":" + reference
+}
+
+type cachedManifest struct {
+	manifestBytes []byte
+	// checkedAt is when the manifest was last resolved from storage, for --cache-ttl
+	checkedAt time.Time
 }
 
-func (r *Registry) getBlobRedirect(ctx context.Context, name string, digest string, method string) (string, error) {
-	algo, hex, found := strings.Cut(digest, ":")
-	if !found {
-		return "", fmt.Errorf("invalid digest format")
+// getCachedManifest returns a manifest from the in-memory cache, unless it was resolved from
+// storage more than ttl ago. A ttl of 0 means no limit.
+func (r *Registry) getCachedManifest(name string, reference string, ttl time.Duration) ([]byte, bool) {
+	if r.manifestCache == nil {
+		return nil, false
+	}
+	cached, ok := r.manifestCache.Get(manifestCacheKey(name, reference))
+	if ok && ttl > 0 && time.Since(cached.checkedAt) > ttl {
+		ok = false
 	}
+	if ok {
+		r.manifestCacheHits.Add(1)
+	} else {
+		r.manifestCacheMisses.Add(1)
+	}
+	return cached.manifestBytes, ok
+}
 
-	blobKey := fmt.Sprintf("docker/registry/v2/blobs/%s/%s/%s/data", algo, hex[0:2], hex)
-	slog.Debug("getBlob", "name", name, "blobKey", blobKey, "method", method)
+func (r *Registry) cacheManifest(name string, reference string, manifestBytes []byte) {
+	r.cacheManifestCheckedAt(name, reference, manifestBytes, time.Now())
+}
+
+func (r *Registry) cacheManifestCheckedAt(name string, reference string, manifestBytes []byte, checkedAt time.Time) {
+	if r.manifestCache != nil {
+		r.manifestCache.Add(manifestCacheKey(name, reference), cachedManifest{manifestBytes: manifestBytes, checkedAt: checkedAt})
+	}
+}
 
-	expires := 15 * time.Minute
+// manifestTTL returns how long the cached manifests of a repository's tags are served before
+// being resolved from storage again, 0 for no limit, and false if they are never served from
+// the cache. Manifests pulled by digest are immutable, so they are not subject to it.
+func (r *Registry) manifestTTL(name string) (time.Duration, bool) {
+	// NOTICE: with --db-only the database is all there is, an expired tag has nowhere to be resolved from
+	if r.cfg.DBOnly {
+		return 0, true
+	}
+	for _, override := range r.ttlOverrides {
+		if matched, _ := path.Match(override.Pattern, name); matched {
+			return override.TTL, override.TTL > 0
+		}
+	}
+	return r.cfg.CacheTTL, true
+}
 
-	var err error
-	var presignedReq *v4.PresignedHTTPRequest
-	presignClient := s3.NewPresignClient(r.s3Client)
-	switch method {
-	case http.MethodGet:
-		presignedReq, err = presignClient.PresignGetObject(ctx,
-			&s3.GetObjectInput{
-				Bucket: &r.bucket,
-				Key:    &blobKey,
-			},
-			s3.WithPresignExpires(expires),
-			func(opts *s3.PresignOptions) {
-				opts.ClientOptions = append(opts.ClientOptions, forcePathStyle)
-			},
-		)
-	case http.MethodHead:
-		presignedReq, err = presignClient.PresignHeadObject(ctx,
-			&s3.HeadObjectInput{
-				Bucket: &r.bucket,
-				Key:    &blobKey,
-			},
-			s3.WithPresignExpires(expires),
-			func(opts *s3.PresignOptions) {
-				opts.ClientOptions = append(opts.ClientOptions, forcePathStyle)
-			},
-		)
+func (r *Registry) evictManifest(name string, reference string) {
+	if r.manifestCache != nil {
+		r.manifestCache.Remove(manifestCacheKey(name, reference))
+	}
+}
+
+// InvalidateManifest drops a reference of a repository from the in-memory cache and the
+// database, returning the number of cached entries dropped.
+func (r *Registry) InvalidateManifest(_ context.Context, name string, reference string) (int64, error) {
+	var invalidated int64
+	if r.manifestCache != nil && r.manifestCache.Remove(manifestCacheKey(name, reference)) {
+		invalidated++
+	}
+	if r.cfg.NoCache {
+		return invalidated, nil
+	}
+	dropped, err := r.db.InvalidateManifest(name, reference)
+	return invalidated + dropped, err
+}
+
+// InvalidateRepository drops everything cached about a repository, in memory and in the database,
+// returning the number of cached entries dropped.
+func (r *Registry) InvalidateRepository(_ context.Context, name string) (int64, error) {
+	var invalidated int64
+	if r.manifestCache != nil {
+		prefix := manifestCacheKey(name, "")
+		for _, key := range r.manifestCache.Keys() {
+			if strings.HasPrefix(key, prefix) && r.manifestCache.Remove(key) {
+				invalidated++
+			}
+		}
+	}
+	if r.cfg.NoCache {
+		return invalidated, nil
+	}
+	dropped, err := r.db.InvalidateRepository(name)
+	return invalidated + dropped, err
+}
+
+func logDBWriteError(ctx context.Context, msg string, err error) {
+	if errors.Is(err, ErrDatabaseBusy) {
+		slog.WarnContext(ctx, msg, "error", err, "hint", "database is contended, consider raising --db-busy-timeout")
+		return
+	}
+	slog.ErrorContext(ctx, msg, "error", err)
+}
+
+// storeManifest records a manifest in the database, unless it is bypassed with --no-cache
+// or left untouched with --read-only.
+func (r *Registry) storeManifest(ctx context.Context, name string, reference string, manifestBytes []byte, manifest *v1.Manifest) {
+	if r.cfg.NoCache || r.cfg.ReadOnly {
+		return
+	}
+	if err := r.db.PutManifest(name, reference, string(manifestBytes), manifest); err != nil {
+		logDBWriteError(ctx, "error storing manifest in database", err)
+		return
+	}
+	r.storeChildManifests(ctx, name, manifestBytes)
+}
+
+// storeDigestManifest stores a manifest pulled or pushed by digest, so that it is served
+// from the database from then on, even if no tag points at it.
+func (r *Registry) storeDigestManifest(ctx context.Context, name string, manifestBytes []byte, manifest *v1.Manifest) {
+	if r.cfg.NoCache || r.cfg.ReadOnly {
+		return
+	}
+	if err := r.db.PutDigestManifest(name, string(manifestBytes), manifest); err != nil {
+		logDBWriteError(ctx, "error storing manifest in database", err)
+		return
+	}
+	r.storeChildManifests(ctx, name, manifestBytes)
+}
+
+// storeChildManifests stores the children of an index by digest in the background with
+// --prefetch-children, so that the per-platform manifests a client pulls after the index are
+// served from the database (and the manifest cache), as they have no tag of their own.
+// Nested indexes get their children stored in turn.
+func (r *Registry) storeChildManifests(ctx context.Context, name string, manifestBytes []byte) {
+	if !r.cfg.PrefetchChildren {
+		return
+	}
+	children, err := manifestChildren(manifestBytes)
+	if err != nil || len(children) == 0 {
+		return
+	}
+	// NOTICE: children are only stored ahead of time, a pull stores them anyway - so with every
+	// slot taken (a bootstrap or refresh going through many indexes) they are skipped instead of
+	// piling up goroutines
+	select {
+	case r.childSlots <- struct{}{}:
 	default:
-		return "", fmt.Errorf("Method not allowed: %s", method)
+		slog.DebugContext(ctx, "skipping child manifests, too many being stored", "repo", name)
+		return
 	}
+	ctx = context.WithoutCancel(ctx)
+	go func() {
+		defer func() { <-r.childSlots }()
+		for _, child := range children {
+			if cached, err := r.db.GetManifestByDigest(name, child); err == nil {
+				r.cacheManifest(name, child.String(), []byte(cached))
+				continue
+			}
+			// NOTICE: same as pulls by digest, only children linked to the repository are stored
+			if _, _, err := r.resolveManifestReference(ctx, name, child.String()); err != nil {
+				slog.WarnContext(ctx, "error resolving child manifest", "repo", name, "digest", child, "error", err)
+				continue
+			}
+			manifest, childBytes, err := r.fetchManifest(ctx, name, child)
+			if err != nil {
+				slog.WarnContext(ctx, "error fetching child manifest", "repo", name, "digest", child, "error", err)
+				continue
+			}
+			r.storeDigestManifest(ctx, name, childBytes, manifest)
+			r.cacheManifest(name, child.String(), childBytes)
+		}
+	}()
+}
+
+var (
+	ErrInvalidDigest      = errors.New("invalid digest")
+	ErrDigestMismatch     = errors.New("uploaded content does not match digest")
+	ErrInvalidUploadRange = errors.New("invalid upload range")
+	ErrUploadTooLarge     = errors.New("upload exceeds the size limit")
+
+	ErrManifestInvalid     = errors.New("invalid manifest")
+	ErrManifestBlobUnknown = errors.New("manifest references unknown blob")
+	ErrManifestTooLarge    = errors.New("manifest exceeds the size limit")
+	ErrDanglingTag         = errors.New("tag points at a manifest missing from storage")
+)
+
+// defaultMaxManifestBytes is the limit the distribution spec expects registries to accept at least.
+const defaultMaxManifestBytes = 4 << 20
+
+// readManifest reads a whole manifest into memory, refusing ones over --max-manifest-bytes,
+// so that an oversized (or misplaced) object can't exhaust the memory.
+func (r *Registry) readManifest(body io.Reader) ([]byte, error) {
+	limit := r.cfg.MaxManifestBytes
+	if limit <= 0 {
+		limit = defaultMaxManifestBytes
+	}
+	manifestBytes, err := io.ReadAll(io.LimitReader(body, limit+1))
+	if err != nil {
+		return nil, err
+	}
+	if int64(len(manifestBytes)) > limit {
+		return nil, fmt.Errorf("%w of %d bytes", ErrManifestTooLarge, limit)
+	}
+	return manifestBytes, nil
+}
+
+func (r *Registry) blobKey(dgst string) (string, error) {
+	parsed, err := digest.Parse(dgst)
 	if err != nil {
-		return "", fmt.Errorf("failed to create presigned URL: %w", err)
+		return "", fmt.Errorf("%w %q: %v", ErrInvalidDigest, dgst, err)
 	}
-	return presignedReq.URL, nil
+	hex := parsed.Hex()
+	return fmt.Sprintf("%s/blobs/%s/%s/%s/data", r.prefix, parsed.Algorithm(), hex[0:2], hex), nil
 }
 
-func (r *Registry) hasBlob(ctx context.Context, digest string) (bool, error) {
-	algo, hex, found := strings.Cut(digest, ":")
-	if !found {
-		return false, fmt.Errorf("invalid digest format")
+// parseTagLinkKey extracts the repository and tag from the key of a tag's current link,
+// <prefix>/repositories/<name>/_manifests/tags/<tag>/current/link. Repository names
+// may contain slashes while tags can't, so the tag is exactly the last component before the suffix.
+func (r *Registry) parseTagLinkKey(key string) (string, string, bool) {
+	rest, ok := strings.CutPrefix(key, r.prefix+"/repositories/")
+	if !ok {
+		return "", "", false
+	}
+	rest, ok = strings.CutSuffix(rest, "/current/link")
+	if !ok {
+		return "", "", false
+	}
+	i := strings.LastIndex(rest, "/_manifests/tags/")
+	if i <= 0 {
+		return "", "", false
+	}
+	repo, tag := rest[:i], rest[i+len("/_manifests/tags/"):]
+	if tag == "" || strings.Contains(tag, "/") {
+		return "", "", false
 	}
+	return repo, tag, true
+}
+
+const presignExpiry = 15 * time.Minute
+
+// presignCacheKey identifies a presigned URL; the repository doesn't matter, as blobs are
+// shared by all repositories in a bucket.
+type presignCacheKey struct {
+	storage Storage
+	method  string
+	digest  string
+}
 
-	blobKey := fmt.Sprintf("docker/registry/v2/blobs/%s/%s/%s/data", algo, hex[0:2], hex)
-	_, err := r.s3Client.HeadObject(ctx, &s3.HeadObjectInput{
-		Bucket: &r.bucket,
-		Key:    &blobKey,
-	}, forcePathStyle)
+type presignedBlob struct {
+	url       string
+	expiresAt time.Time
+}
 
+// getBlobRedirect returns a presigned URL for a blob along with the time it stops being valid.
+func (r *Registry) getBlobRedirect(ctx context.Context, name string, digest string, method string) (string, time.Time, error) {
+	blobKey, err := r.blobKey(digest)
 	if err != nil {
-		var nsk *types.NoSuchKey
-		if errors.As(err, &nsk) {
-			return false, nil
-		}
-		var nse *types.NotFound
-		if errors.As(err, &nse) {
-			return false, nil
+		return "", time.Time{}, err
+	}
+	slog.DebugContext(ctx, "getBlob", "name", name, "blobKey", blobKey, "method", method)
+
+	storage := r.storageFor(r.canonicalName(name))
+	cacheKey := presignCacheKey{storage: storage, method: method, digest: digest}
+	if r.presignCache != nil {
+		if presigned, ok := r.presignCache.Get(cacheKey); ok {
+			r.presignCacheHits.Add(1)
+			return presigned.url, presigned.expiresAt, nil
 		}
-		return false, err
+		r.presignCacheMisses.Add(1)
+	}
+
+	// NOTICE: the expiry is taken before signing, so that it errs on the early side
+	expiresAt := time.Now().Add(presignExpiry)
+	presignedURL, err := storage.Presign(ctx, method, blobKey, presignExpiry)
+	if err != nil {
+		return "", time.Time{}, fmt.Errorf("failed to create presigned URL: %w", err)
+	}
+	if r.presignCache != nil {
+		r.presignCache.Add(cacheKey, presignedBlob{url: presignedURL, expiresAt: expiresAt})
+	}
+	return presignedURL, expiresAt, nil
+}
+
+// cachedBlobSize returns the size of a blob if it is a layer of a cached manifest, without going to storage.
+func (r *Registry) cachedBlobSize(_ context.Context, digest string) (int64, bool) {
+	if r.cfg.NoCache {
+		return 0, false
+	}
+	size, err := r.db.GetLayerSize(digest)
+	return size, err == nil
+}
+
+func (r *Registry) statBlob(ctx context.Context, name string, digest string) (int64, error) {
+	blobKey, err := r.blobKey(digest)
+	if err != nil {
+		return 0, err
+	}
+	return r.storageFor(r.canonicalName(name)).StatObject(ctx, blobKey)
+}
+
+func (r *Registry) openBlob(ctx context.Context, name string, digest string, offset int64, length int64) (io.ReadCloser, error) {
+	blobKey, err := r.blobKey(digest)
+	if err != nil {
+		return nil, err
 	}
+	if length == 0 {
+		return io.NopCloser(bytes.NewReader(nil)), nil
+	}
+	return r.storageFor(r.canonicalName(name)).GetObjectRange(ctx, blobKey, offset, length)
+}
 
-	return true, nil
+// maxLinkSize bounds the link objects read, comfortably above the longest digest (sha512, 135 bytes).
+const maxLinkSize = 256
+
+// parseLink parses the content of a link object, "<algorithm>:<hex>", tolerating surrounding
+// whitespace such as a trailing (CR)LF left by tools editing the layout by hand.
+func parseLink(key string, content []byte) (digest.Digest, error) {
+	dgst, err := digest.Parse(strings.TrimSpace(string(content)))
+	if err != nil {
+		return "", fmt.Errorf("malformed link %s (%q): %w", key, content, err)
+	}
+	return dgst, nil
 }
 
 func (r *Registry) getManifestSHA(ctx context.Context, repo string, tag string) (digest.Digest, error) {
-	metaKey := fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/tags/%s/current/link", repo, tag)
-	slog.Debug("getting manifest SHA", "repo", repo, "tag", tag, "metaKey", metaKey)
+	metaKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/current/link", r.prefix, repo, tag)
+	slog.DebugContext(ctx, "getting manifest SHA", "repo", repo, "tag", tag, "metaKey", metaKey)
 
-	obj, err := r.s3Client.GetObject(ctx, &s3.GetObjectInput{
-		Bucket: &r.bucket,
-		Key:    &metaKey,
-	}, forcePathStyle)
+	body, err := r.storageFor(repo).GetObject(ctx, metaKey)
 	if err != nil {
 		return "", fmt.Errorf("error getting sha: %w", err)
 	}
-	defer obj.Body.Close()
-	sha, err := io.ReadAll(obj.Body)
+	defer body.Close()
+	// NOTICE: a link holds just the digest, anything longer is not a valid link anyway
+	content, err := io.ReadAll(io.LimitReader(body, maxLinkSize))
 	if err != nil {
 		return "", fmt.Errorf("error reading response body: %w", err)
 	}
-	return digest.Parse(string(sha))
+	return parseLink(metaKey, content)
 }
 
 func (r *Registry) getManifest(ctx context.Context, name string, reference string) (*v1.Manifest, []byte, error) {
-	readyManifestBytes, err := r.db.GetManifest(name, reference)
-	if err == nil {
-		var manifest v1.Manifest
-		if err := json.Unmarshal([]byte(readyManifestBytes), &manifest); err != nil {
-			return nil, nil, err
+	name = r.canonicalName(name)
+	dgst, parseErr := digest.Parse(reference)
+	ttl, cacheable := time.Duration(0), true
+	if parseErr != nil {
+		ttl, cacheable = r.manifestTTL(name)
+	}
+
+	if cacheable {
+		if cachedManifestBytes, ok := r.getCachedManifest(name, reference, ttl); ok {
+			var manifest v1.Manifest
+			if err := json.Unmarshal(cachedManifestBytes, &manifest); err != nil {
+				return nil, nil, err
+			}
+			return &manifest, cachedManifestBytes, nil
 		}
-		return &manifest, []byte(readyManifestBytes), nil
 	}
 
-	sha, err := r.getManifestSHA(ctx, name, reference)
+	if !r.cfg.NoCache && cacheable {
+		var readyManifestBytes string
+		checkedAt := time.Now()
+		var err error
+		if parseErr == nil {
+			readyManifestBytes, err = r.db.GetManifestByDigest(name, dgst)
+		} else {
+			readyManifestBytes, checkedAt, err = r.db.GetManifestCheckedAt(name, reference)
+			if err == nil && ttl > 0 && time.Since(checkedAt) > ttl {
+				err = fmt.Errorf("cached manifest of %s:%s is older than %s", name, reference, ttl)
+			}
+		}
+		if err == nil {
+			var manifest v1.Manifest
+			if err := json.Unmarshal([]byte(readyManifestBytes), &manifest); err != nil {
+				return nil, nil, err
+			}
+			// NOTICE: cached with the time it was last checked, so the memory cache doesn't extend its TTL
+			r.cacheManifestCheckedAt(name, reference, []byte(readyManifestBytes), checkedAt)
+			return &manifest, []byte(readyManifestBytes), nil
+		}
+	}
+
+	// NOTICE: digests of manifests not cached under any tag are resolved through the repository's
+	// revision links - a manifest is only pullable from the repositories it was pushed to.
+	// NOTICE: missing links already wrap fs.ErrNotExist, anything else (a storage failure,
+	// a malformed link) must not be reported as an unknown manifest
+	sha, isDigest, err := r.resolveManifestReference(ctx, name, reference)
 	if err != nil {
-		return nil, nil, errors.Join(err, fs.ErrNotExist)
+		return nil, nil, err
 	}
-	hex := sha.Hex()
-	blobKey := fmt.Sprintf("docker/registry/v2/blobs/sha256/%s/%s/data", hex[0:2], hex)
-	slog.Debug("getting manifest blob", "blobKey", blobKey)
-	obj, err := r.s3Client.GetObject(ctx, &s3.GetObjectInput{
-		Bucket: &r.bucket,
-		Key:    &blobKey,
-	}, forcePathStyle)
+	manifest, blobData, err := r.fetchManifest(ctx, name, sha)
+	if err != nil {
+		// NOTICE: the tag link exists but its manifest blob doesn't, typically a partially deleted
+		// repository or an interrupted copy - worth a warning, unlike a tag that doesn't exist
+		if !isDigest && errors.Is(err, fs.ErrNotExist) {
+			slog.WarnContext(ctx, "tag links to a missing manifest", "repo", name, "tag", reference, "digest", sha)
+			return nil, nil, fmt.Errorf("%w: %s:%s links to %s: %w", ErrDanglingTag, name, reference, sha, err)
+		}
+		return nil, nil, err
+	}
+
+	if isDigest {
+		r.storeDigestManifest(ctx, name, blobData, manifest)
+	} else {
+		r.storeManifest(ctx, name, reference, blobData, manifest)
+	}
+	r.cacheManifest(name, reference, blobData)
+
+	return manifest, blobData, nil
+}
+
+// fetchManifest reads a manifest blob from storage.
+func (r *Registry) fetchManifest(ctx context.Context, name string, sha digest.Digest) (*v1.Manifest, []byte, error) {
+	blobKey, err := r.blobKey(sha.String())
+	if err != nil {
+		return nil, nil, err
+	}
+	slog.DebugContext(ctx, "getting manifest blob", "blobKey", blobKey)
+	body, err := r.storageFor(name).GetObject(ctx, blobKey)
 	if err != nil {
 		return nil, nil, err
 	}
-	defer obj.Body.Close()
-	blobData, err := io.ReadAll(obj.Body)
+	defer body.Close()
+	blobData, err := r.readManifest(body)
 	if err != nil {
 		return nil, nil, err
 	}
@@ -181,141 +624,316 @@ func (r *Registry) getManifest(ctx context.Context, name string, reference strin
 	if err := json.Unmarshal(blobData, &manifest); err != nil {
 		return nil, nil, err
 	}
-
-	if err := r.db.PutManifest(name, reference, string(blobData), &manifest); err != nil {
-		slog.Error("error storing manifest in database", "error", err)
+	// NOTICE: checked before caching, so that disallowed manifests never make it to the database
+	if mediaType := manifestMediaType(blobData, &manifest); !r.mediaTypeAllowed(mediaType) {
+		return nil, nil, fmt.Errorf("%w: %s", ErrManifestMediaTypeNotAllowed, mediaType)
 	}
-
 	return &manifest, blobData, nil
 }
 
-func (r *Registry) putManifest(ctx context.Context, name string, reference string, manifestBytes []byte) error {
+// resolveManifestReference returns the digest of the manifest a tag points at, or the digest
+// reference itself if the repository links to it.
+func (r *Registry) resolveManifestReference(ctx context.Context, name string, reference string) (digest.Digest, bool, error) {
+	dgst, err := digest.Parse(reference)
+	if err != nil {
+		sha, err := r.getManifestSHA(ctx, name, reference)
+		return sha, false, err
+	}
+	revisionsKey := fmt.Sprintf("%s/repositories/%s/_manifests/revisions/%s/%s/link", r.prefix, name, dgst.Algorithm(), dgst.Hex())
+	if _, err := r.storageFor(name).StatObject(ctx, revisionsKey); err != nil {
+		return "", true, fmt.Errorf("manifest %s not found in %s: %w", dgst, name, err)
+	}
+	return dgst, true, nil
+}
+
+func (r *Registry) putManifest(ctx context.Context, name string, reference string, manifestBytes []byte) (digest.Digest, error) {
+	// NOTICE: pulls through an alias never see what is pushed under its own name
+	if canonical := r.canonicalName(name); canonical != name {
+		return "", fmt.Errorf("%w: %s is an alias of %s", ErrPushToAlias, name, canonical)
+	}
+	storage := r.storageFor(name)
 	sha := digest.FromBytes(manifestBytes)
 	hex := sha.Hex()
-	blobKey := fmt.Sprintf("docker/registry/v2/blobs/sha256/%s/%s/data", hex[0:2], hex)
-	slog.Debug("putting manifest blob", "blobKey", blobKey)
+	blobKey := fmt.Sprintf("%s/blobs/sha256/%s/%s/data", r.prefix, hex[0:2], hex)
+	slog.DebugContext(ctx, "putting manifest blob", "blobKey", blobKey)
 
 	var manifest v1.Manifest
 	if err := json.Unmarshal(manifestBytes, &manifest); err != nil {
-		return fmt.Errorf("error unmarshalling manifest: %w", err)
+		return "", fmt.Errorf("%w: %w", ErrManifestInvalid, err)
 	}
 
-	_, err := r.s3Client.PutObject(ctx, &s3.PutObjectInput{
-		Bucket: &r.bucket,
-		Key:    &blobKey,
-		Body:   strings.NewReader(string(manifestBytes)),
-	}, forcePathStyle)
+	// NOTICE: a digest reference has to match the content, and only tags get tag links
+	refDigest, err := digest.Parse(reference)
+	isDigest := err == nil
+	if isDigest && refDigest != sha {
+		return "", fmt.Errorf("%w: content digest %s does not match reference %s", ErrInvalidDigest, sha, refDigest)
+	}
+
+	refs, err := manifestReferences(manifestBytes, &manifest)
 	if err != nil {
-		return err
+		return "", fmt.Errorf("%w: %w", ErrManifestInvalid, err)
+	}
+	for _, ref := range refs {
+		if _, err := r.statBlob(ctx, name, ref); err != nil {
+			if errors.Is(err, fs.ErrNotExist) || errors.Is(err, ErrInvalidDigest) {
+				return "", fmt.Errorf("%w %s", ErrManifestBlobUnknown, ref)
+			}
+			return "", fmt.Errorf("failed to check referenced blob %s: %w", ref, err)
+		}
+	}
+
+	err = storage.PutObject(ctx, blobKey, manifestBytes)
+	if err != nil {
+		return "", err
+	}
+
+	revisionsKey := fmt.Sprintf("%s/repositories/%s/_manifests/revisions/%s/%s/link", r.prefix, name, sha.Algorithm(), sha.Hex())
+	slog.DebugContext(ctx, "putting manifest revisions meta", "revisionsKey", revisionsKey)
+	err = storage.PutObject(ctx, revisionsKey, []byte(sha.String()))
+	if err != nil {
+		return "", err
+	}
+
+	if manifest.Subject != nil {
+		r.indexReferrer(ctx, name, manifestBytes, &manifest)
+	}
+
+	if isDigest {
+		r.storeDigestManifest(ctx, name, manifestBytes, &manifest)
+		r.cacheManifest(name, reference, manifestBytes)
+		return sha, nil
 	}
 
 	// TODO: check why on earth we need to put the same thing in at least 3 places... come on OCI
-	metaKey := fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/tags/%s/current/link", name, reference)
-	slog.Debug("putting manifest meta", "metaKey", metaKey)
+	metaKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/current/link", r.prefix, name, reference)
+	slog.DebugContext(ctx, "putting manifest meta", "metaKey", metaKey)
 
-	_, err = r.s3Client.PutObject(ctx, &s3.PutObjectInput{
-		Bucket: &r.bucket,
-		Key:    &metaKey,
-		Body:   strings.NewReader(sha.String()),
-	}, forcePathStyle)
+	err = storage.PutObject(ctx, metaKey, []byte(sha.String()))
 	if err != nil {
-		return err
+		return "", err
 	}
 
-	metaIndexKey := fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/tags/%s/index/%s/%s/link", name, reference, sha.Algorithm(), sha.Hex())
-	slog.Debug("putting manifest index meta", "metaIndexKey", metaIndexKey)
-	_, err = r.s3Client.PutObject(ctx, &s3.PutObjectInput{
-		Bucket: &r.bucket,
-		Key:    &metaIndexKey,
-		Body:   strings.NewReader(sha.String()),
-	}, forcePathStyle)
+	metaIndexKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/index/%s/%s/link", r.prefix, name, reference, sha.Algorithm(), sha.Hex())
+	slog.DebugContext(ctx, "putting manifest index meta", "metaIndexKey", metaIndexKey)
+	err = storage.PutObject(ctx, metaIndexKey, []byte(sha.String()))
 	if err != nil {
-		return err
+		return "", err
 	}
 
-	revisionsKey := fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/revisions/%s/%s/link", name, sha.Algorithm(), sha.Hex())
-	slog.Debug("putting manifest revisions meta", "revisionsKey", revisionsKey)
-	_, err = r.s3Client.PutObject(ctx, &s3.PutObjectInput{
-		Bucket: &r.bucket,
-		Key:    &revisionsKey,
-		Body:   strings.NewReader(sha.String()),
-	}, forcePathStyle)
+	r.storeManifest(ctx, name, reference, manifestBytes, &manifest)
+	r.cacheManifest(name, reference, manifestBytes)
+	r.cacheManifest(name, sha.String(), manifestBytes)
+	return sha, nil
+}
+
+// indexReferrer records a pushed manifest under its subject, for the referrers API. It is
+// done even with --no-cache, as the index can't be rebuilt from storage on demand.
+func (r *Registry) indexReferrer(ctx context.Context, name string, manifestBytes []byte, manifest *v1.Manifest) {
+	if err := r.db.PutReferrer(name, manifest.Subject.Digest, manifestReferrer(manifestBytes, manifest)); err != nil {
+		logDBWriteError(ctx, "error indexing referrer", err)
+	}
+}
+
+// listReferrers returns the manifests pushed with subject as their subject, optionally of
+// one artifact type only. An unknown subject has no referrers rather than being an error.
+func (r *Registry) listReferrers(_ context.Context, name string, subject string, artifactType string) ([]v1.Descriptor, error) {
+	dgst, err := digest.Parse(subject)
 	if err != nil {
-		return err
+		return nil, fmt.Errorf("%w: %w", ErrInvalidDigest, err)
+	}
+	return r.db.ListReferrers(r.canonicalName(name), dgst, artifactType)
+}
+
+func (r *Registry) deleteManifest(ctx context.Context, name string, dgst digest.Digest) error {
+	storage := r.storageFor(name)
+	revisionsKey := fmt.Sprintf("%s/repositories/%s/_manifests/revisions/%s/%s/link", r.prefix, name, dgst.Algorithm(), dgst.Hex())
+	if _, err := storage.StatObject(ctx, revisionsKey); err != nil {
+		return fmt.Errorf("manifest %s not found in %s: %w", dgst, name, err)
 	}
 
-	err = r.db.PutManifest(name, reference, string(manifestBytes), &manifest)
+	tagsPrefix := fmt.Sprintf("%s/repositories/%s/_manifests/tags/", r.prefix, name)
+	var tags []string
+	err := storage.List(ctx, tagsPrefix, func(obj ObjectInfo) error {
+		if repo, tag, ok := r.parseTagLinkKey(obj.Key); ok && repo == name {
+			tags = append(tags, tag)
+		}
+		return nil
+	})
 	if err != nil {
-		slog.Error("error storing manifest in database", "error", err)
+		return fmt.Errorf("failed to list tags: %w", err)
 	}
+
+	for _, tag := range tags {
+		sha, err := r.getManifestSHA(ctx, name, tag)
+		if err != nil {
+			slog.WarnContext(ctx, "failed to resolve tag while deleting manifest", "repo", name, "tag", tag, "error", err)
+			continue
+		}
+		if sha != dgst {
+			continue
+		}
+		r.evictManifest(name, tag)
+
+		metaKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/current/link", r.prefix, name, tag)
+		slog.DebugContext(ctx, "deleting manifest meta", "metaKey", metaKey)
+		if err := storage.DeleteObject(ctx, metaKey); err != nil {
+			return fmt.Errorf("failed to delete tag link: %w", err)
+		}
+		metaIndexKey := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/index/%s/%s/link", r.prefix, name, tag, dgst.Algorithm(), dgst.Hex())
+		slog.DebugContext(ctx, "deleting manifest index meta", "metaIndexKey", metaIndexKey)
+		if err := storage.DeleteObject(ctx, metaIndexKey); err != nil && !errors.Is(err, fs.ErrNotExist) {
+			slog.WarnContext(ctx, "failed to delete tag index link", "key", metaIndexKey, "error", err)
+		}
+	}
+
+	slog.DebugContext(ctx, "deleting manifest revisions meta", "revisionsKey", revisionsKey)
+	if err := storage.DeleteObject(ctx, revisionsKey); err != nil {
+		return fmt.Errorf("failed to delete manifest revision link: %w", err)
+	}
+
+	r.evictManifest(name, dgst.String())
+	deleted, err := r.db.DeleteManifest(name, dgst)
+	if err != nil {
+		return fmt.Errorf("failed to delete manifest from database: %w", err)
+	}
+	slog.InfoContext(ctx, "deleted manifest", "repo", name, "digest", dgst, "tags", deleted)
 	return nil
 }
 
-func (r *Registry) startUpload(ctx context.Context, name string, reference string) error {
-	tempKey := fmt.Sprintf("uploads/%s.uploading", reference)
+// deleteTag removes a single tag, leaving the manifest revision it pointed at (and any other tags) in place.
+func (r *Registry) deleteTag(ctx context.Context, name string, tag string) error {
+	storage := r.storageFor(name)
+	tagPrefix := fmt.Sprintf("%s/repositories/%s/_manifests/tags/%s/", r.prefix, name, tag)
+	metaKey := tagPrefix + "current/link"
+	if _, err := storage.StatObject(ctx, metaKey); err != nil {
+		return fmt.Errorf("tag %s not found in %s: %w", tag, name, err)
+	}
+
+	var indexKeys []string
+	err := storage.List(ctx, tagPrefix+"index/", func(obj ObjectInfo) error {
+		indexKeys = append(indexKeys, obj.Key)
+		return nil
+	})
+	if err != nil {
+		return fmt.Errorf("failed to list tag index: %w", err)
+	}
+
+	slog.DebugContext(ctx, "deleting manifest meta", "metaKey", metaKey)
+	if err := storage.DeleteObject(ctx, metaKey); err != nil {
+		return fmt.Errorf("failed to delete tag link: %w", err)
+	}
+	for _, key := range indexKeys {
+		slog.DebugContext(ctx, "deleting manifest index meta", "metaIndexKey", key)
+		if err := storage.DeleteObject(ctx, key); err != nil && !errors.Is(err, fs.ErrNotExist) {
+			slog.WarnContext(ctx, "failed to delete tag index link", "key", key, "error", err)
+		}
+	}
 
-	multipartInput := &s3.CreateMultipartUploadInput{
-		Bucket: &r.bucket,
-		Key:    &tempKey,
+	r.evictManifest(name, tag)
+	if _, err := r.db.DeleteTag(name, tag); err != nil {
+		return fmt.Errorf("failed to delete tag from database: %w", err)
 	}
+	slog.InfoContext(ctx, "deleted tag", "repo", name, "tag", tag)
+	return nil
+}
+
+// ErrMountUnavailable reports a blob which exists, but can't be mounted without copying it.
+var ErrMountUnavailable = errors.New("blob can't be mounted across storages")
 
-	_, err := r.s3Client.CreateMultipartUpload(ctx, multipartInput, forcePathStyle)
+// mountBlob makes a blob of repository from available to name. Blobs are stored once per storage
+// and shared by its repositories, so linking one boils down to checking it is there - as long as
+// both repositories are routed to the same storage.
+func (r *Registry) mountBlob(ctx context.Context, name string, from string, dgst string) error {
+	blobKey, err := r.blobKey(dgst)
+	if err != nil {
+		return err
+	}
+	source := r.storageFor(r.canonicalName(from))
+	if _, err := source.StatObject(ctx, blobKey); err != nil {
+		return fmt.Errorf("blob %s not found in %s: %w", dgst, from, err)
+	}
+	if source != r.storageFor(name) {
+		return fmt.Errorf("%w: %s and %s", ErrMountUnavailable, from, name)
+	}
+	return nil
+}
+
+func (r *Registry) startUpload(ctx context.Context, name string, reference string) error {
+	tempKey := fmt.Sprintf("uploads/%s.uploading", reference)
+
+	s3UploadID, err := r.storageFor(name).CreateMultipartUpload(ctx, tempKey)
 	if err != nil {
 		return fmt.Errorf("failed to create multipart upload: %w", err)
 	}
 
-	return r.db.CreateUploadSession(reference, name, tempKey)
+	return r.db.CreateUploadSession(reference, name, tempKey, s3UploadID)
 }
 
-func (r *Registry) uploadChunk(ctx context.Context, reference string, offset int64, body io.ReadCloser) (int64, error) {
+func (r *Registry) uploadChunk(ctx context.Context, name string, reference string, offset int64, body io.ReadCloser) (int64, error) {
 	defer body.Close()
 
-	s3UploadID, s3Key, uploadedSize, err := r.db.GetUploadSession(reference)
+	session, err := r.getUploadSession(name, reference)
 	if err != nil {
-		return 0, fmt.Errorf("upload session not found: %w", err)
+		return 0, err
 	}
+	storage := r.storageFor(session.Repository)
 
-	if s3UploadID == "" {
-		tempKey := fmt.Sprintf("uploads/%s.uploading", reference)
-		multipartInput := &s3.CreateMultipartUploadInput{
-			Bucket: &r.bucket,
-			Key:    &tempKey,
-		}
-
-		multipartOutput, err := r.s3Client.CreateMultipartUpload(ctx, multipartInput, forcePathStyle)
+	if session.S3UploadID == "" {
+		session.S3UploadID, err = storage.CreateMultipartUpload(ctx, session.S3Key)
 		if err != nil {
 			return 0, fmt.Errorf("failed to create multipart upload: %w", err)
 		}
-		s3UploadID = *multipartOutput.UploadId
 	}
 
-	if offset != uploadedSize {
-		return 0, fmt.Errorf("invalid offset: expected %d, got %d", uploadedSize, offset)
+	if offset != session.UploadedSize {
+		return 0, fmt.Errorf("%w: expected offset %d, got %d", ErrInvalidUploadRange, session.UploadedSize, offset)
 	}
 
-	partNumber := int32((offset / (5 * 1024 * 1024)) + 1)
+	// NOTICE: every chunk becomes its own part, so apart from the last one chunks need to respect
+	// the S3 minimum part size (5MiB) - clients typically send a whole blob in a single chunk anyway.
+	partNumber := session.PartCount + 1
 
+	// NOTICE: the limit bounds the whole blob, which also bounds each chunk buffered below
+	var reader io.Reader = body
+	remaining := int64(-1)
+	if r.cfg.MaxUploadBytes > 0 {
+		remaining = r.cfg.MaxUploadBytes - session.UploadedSize
+		reader = io.LimitReader(body, remaining+1)
+	}
 	buf := &bytes.Buffer{}
-	n, err := io.Copy(buf, body)
+	n, err := io.Copy(buf, reader)
+	var maxBytesErr *http.MaxBytesError
+	if errors.As(err, &maxBytesErr) || (remaining >= 0 && n > remaining) {
+		return 0, fmt.Errorf("%w of %d bytes", ErrUploadTooLarge, r.cfg.MaxUploadBytes)
+	}
 	if err != nil {
 		return 0, fmt.Errorf("failed to read request body: %w", err)
 	}
 
-	uploadPartInput := &s3.UploadPartInput{
-		Bucket:     &r.bucket,
-		Key:        &s3Key,
-		PartNumber: &partNumber,
-		UploadId:   &s3UploadID,
-		Body:       bytes.NewReader(buf.Bytes()),
+	// NOTICE: sessions started before the hash was kept have no state to resume, and are verified
+	// by reading the blob back once complete
+	var hashState []byte
+	if session.hashable() {
+		h, err := resumeUploadHash(session.HashState)
+		if err != nil {
+			return 0, err
+		}
+		h.Write(buf.Bytes())
+		if hashState, err = h.(encoding.BinaryMarshaler).MarshalBinary(); err != nil {
+			return 0, fmt.Errorf("failed to save upload hash: %w", err)
+		}
 	}
 
-	_, err = r.s3Client.UploadPart(ctx, uploadPartInput, forcePathStyle)
-	if err != nil {
-		return 0, fmt.Errorf("failed to upload part: %w", err)
+	// NOTICE: an empty chunk (a chunked body closing the upload, typically) adds no part
+	if n > 0 {
+		err = storage.UploadPart(ctx, session.S3Key, session.S3UploadID, partNumber, buf.Bytes())
+		if err != nil {
+			return 0, fmt.Errorf("failed to upload part: %w", err)
+		}
+		session.PartCount = partNumber
 	}
 
-	newUploadedSize := uploadedSize + n
-	err = r.db.UpdateUploadSession(reference, s3UploadID, newUploadedSize)
+	err = r.db.UpdateUploadSession(reference, session.S3UploadID, session.UploadedSize+n, session.PartCount, hashState)
 	if err != nil {
 		return 0, fmt.Errorf("failed to update upload session: %w", err)
 	}
@@ -323,113 +941,134 @@ func (r *Registry) uploadChunk(ctx context.Context, reference string, offset int
 	return n, nil
 }
 
-func (r *Registry) completeUpload(ctx context.Context, reference string, dig string) error {
-	s3UploadID, s3Key, _, err := r.db.GetUploadSession(reference)
+func (r *Registry) completeUpload(ctx context.Context, name string, reference string, dig string) error {
+	finalBlobKey, err := r.blobKey(dig)
 	if err != nil {
-		return fmt.Errorf("upload session not found: %w", err)
+		return err
 	}
 
-	if s3UploadID == "" {
-		return fmt.Errorf("no active multipart upload found")
+	session, err := r.getUploadSession(name, reference)
+	if err != nil {
+		return err
 	}
+	storage := r.storageFor(session.Repository)
+	s3Key := session.S3Key
 
-	listPartsInput := &s3.ListPartsInput{
-		Bucket:   &r.bucket,
-		Key:      &s3Key,
-		UploadId: &s3UploadID,
+	if session.S3UploadID == "" {
+		return fmt.Errorf("no active multipart upload found")
 	}
 
-	listPartsOutput, err := r.s3Client.ListParts(ctx, listPartsInput)
-	if err != nil {
-		return fmt.Errorf("failed to list parts: %w", err)
+	// NOTICE: S3 can't complete a multipart upload without parts, so an empty blob is written directly
+	if session.PartCount == 0 {
+		if err := storage.PutObject(ctx, s3Key, nil); err != nil {
+			return fmt.Errorf("failed to write empty upload: %w", err)
+		}
+		if err := storage.AbortMultipartUpload(ctx, s3Key, session.S3UploadID); err != nil {
+			slog.WarnContext(ctx, "failed to abort empty multipart upload", "key", s3Key, "error", err)
+		}
+	} else if err := storage.CompleteMultipartUpload(ctx, s3Key, session.S3UploadID); err != nil {
+		return fmt.Errorf("failed to complete multipart upload: %w", err)
 	}
 
-	var completedParts []types.CompletedPart
-	for _, part := range listPartsOutput.Parts {
-		completedParts = append(completedParts, types.CompletedPart{
-			ETag:       part.ETag,
-			PartNumber: part.PartNumber,
-		})
+	if err := r.verifyUpload(ctx, storage, session, digest.Digest(dig)); err != nil {
+		if err := storage.DeleteObject(ctx, s3Key); err != nil {
+			slog.WarnContext(ctx, "failed to delete temporary upload file", "key", s3Key, "error", err)
+		}
+		if err := r.db.DeleteUploadSession(reference); err != nil {
+			slog.WarnContext(ctx, "failed to delete upload session", "reference", reference, "error", err)
+		}
+		return err
 	}
 
-	completeInput := &s3.CompleteMultipartUploadInput{
-		Bucket:   &r.bucket,
-		Key:      &s3Key,
-		UploadId: &s3UploadID,
-		MultipartUpload: &types.CompletedMultipartUpload{
-			Parts: completedParts,
-		},
+	err = storage.CopyObject(ctx, s3Key, finalBlobKey)
+	if err != nil {
+		return fmt.Errorf("failed to copy blob to final location: %w", err)
 	}
 
-	_, err = r.s3Client.CompleteMultipartUpload(ctx, completeInput, forcePathStyle)
+	err = storage.DeleteObject(ctx, s3Key)
 	if err != nil {
-		return fmt.Errorf("failed to complete multipart upload: %w", err)
+		slog.WarnContext(ctx, "failed to delete temporary upload file", "key", s3Key, "error", err)
 	}
 
-	sha, err := digest.Parse(dig)
+	err = r.db.DeleteUploadSession(reference)
 	if err != nil {
-		return fmt.Errorf("failed to parse digest: %w", err)
+		slog.WarnContext(ctx, "failed to delete upload session", "reference", reference, "error", err)
 	}
 
-	hex := sha.Hex()
-	finalBlobKey := fmt.Sprintf("docker/registry/v2/blobs/sha256/%s/%s/data", hex[0:2], hex)
+	slog.DebugContext(ctx, "completed upload", "tempKey", s3Key, "finalKey", finalBlobKey)
+	return nil
+}
 
-	copyInput := &s3.CopyObjectInput{
-		Bucket:     &r.bucket,
-		Key:        &finalBlobKey,
-		CopySource: aws.String(fmt.Sprintf("%s/%s", r.bucket, s3Key)),
+// verifyUpload checks the uploaded blob against dgst, from the hash kept while uploading when it can.
+func (r *Registry) verifyUpload(ctx context.Context, storage Storage, session *UploadSession, dgst digest.Digest) error {
+	if dgst.Algorithm() == digest.SHA256 && session.hashable() {
+		h, err := resumeUploadHash(session.HashState)
+		if err != nil {
+			return err
+		}
+		if digest.NewDigest(digest.SHA256, h) != dgst {
+			return fmt.Errorf("%w %s", ErrDigestMismatch, dgst)
+		}
+		return nil
 	}
 
-	_, err = r.s3Client.CopyObject(ctx, copyInput, forcePathStyle)
+	body, err := storage.GetObject(ctx, session.S3Key)
 	if err != nil {
-		return fmt.Errorf("failed to copy blob to final location: %w", err)
+		return fmt.Errorf("failed to read uploaded blob: %w", err)
 	}
+	defer body.Close()
 
-	deleteInput := &s3.DeleteObjectInput{
-		Bucket: &r.bucket,
-		Key:    &s3Key,
+	verifier := dgst.Verifier()
+	if _, err := io.Copy(verifier, body); err != nil {
+		return fmt.Errorf("failed to read uploaded blob: %w", err)
 	}
-
-	_, err = r.s3Client.DeleteObject(ctx, deleteInput, forcePathStyle)
-	if err != nil {
-		slog.Warn("failed to delete temporary upload file", "key", s3Key, "error", err)
+	if !verifier.Verified() {
+		return fmt.Errorf("%w %s", ErrDigestMismatch, dgst)
 	}
+	return nil
+}
 
-	err = r.db.DeleteUploadSession(reference)
-	if err != nil {
-		slog.Warn("failed to delete upload session", "reference", reference, "error", err)
+// resumeUploadHash restores the sha256 of the bytes uploaded so far from its saved state.
+func resumeUploadHash(state []byte) (hash.Hash, error) {
+	h := sha256.New()
+	if len(state) == 0 {
+		return h, nil
 	}
-
-	slog.Debug("completed upload", "tempKey", s3Key, "finalKey", finalBlobKey)
-	return nil
+	if err := h.(encoding.BinaryUnmarshaler).UnmarshalBinary(state); err != nil {
+		return nil, fmt.Errorf("failed to restore upload hash: %w", err)
+	}
+	return h, nil
 }
 
-func (r *Registry) getUploadSession(uploadID string) (string, string, int64, error) {
-	return r.db.GetUploadSession(uploadID)
+// getUploadSession returns an upload session of repository name. An upload is only reachable
+// through the repository it was started in, the one the name and ACL checks ran against.
+func (r *Registry) getUploadSession(name string, uploadID string) (*UploadSession, error) {
+	session, err := r.db.GetUploadSession(uploadID)
+	if err != nil {
+		return nil, err
+	}
+	if session.Repository != name {
+		return nil, fmt.Errorf("upload session %s not found in %s: %w", uploadID, name, fs.ErrNotExist)
+	}
+	return session, nil
 }
 
 func (r *Registry) abortUpload(ctx context.Context, uploadID string) error {
-	s3UploadID, s3Key, _, err := r.db.GetUploadSession(uploadID)
+	session, err := r.db.GetUploadSession(uploadID)
 	if err != nil {
-		return fmt.Errorf("upload session not found: %w", err)
+		return err
 	}
 
-	if s3UploadID != "" {
-		abortInput := &s3.AbortMultipartUploadInput{
-			Bucket:   &r.bucket,
-			Key:      &s3Key,
-			UploadId: &s3UploadID,
-		}
-
-		_, err = r.s3Client.AbortMultipartUpload(ctx, abortInput, forcePathStyle)
+	if session.S3UploadID != "" {
+		err = r.storageFor(session.Repository).AbortMultipartUpload(ctx, session.S3Key, session.S3UploadID)
 		if err != nil {
-			slog.Warn("failed to abort multipart upload", "uploadID", s3UploadID, "error", err)
+			slog.WarnContext(ctx, "failed to abort multipart upload", "uploadID", session.S3UploadID, "error", err)
 		}
 	}
 
 	err = r.db.DeleteUploadSession(uploadID)
 	if err != nil {
-		slog.Warn("failed to delete upload session", "uploadID", uploadID, "error", err)
+		slog.WarnContext(ctx, "failed to delete upload session", "uploadID", uploadID, "error", err)
 	}
 
 	return nil
@@ -444,122 +1083,394 @@ func (r *Registry) CleanupStaleUploads(ctx context.Context) error {
 	for _, uploadID := range uploadIDs {
 		err := r.abortUpload(ctx, uploadID)
 		if err != nil {
-			slog.Warn("failed to cleanup stale upload", "uploadID", uploadID, "error", err)
+			slog.WarnContext(ctx, "failed to cleanup stale upload", "uploadID", uploadID, "error", err)
 		}
 	}
 
-	slog.Info("cleaned up stale uploads", "count", len(uploadIDs))
+	slog.InfoContext(ctx, "cleaned up stale uploads", "count", len(uploadIDs))
 	return nil
 }
 
+// listTags returns the sorted tags of a repository, which are empty if the repository exists but
+// has no tags left, e.g. after they were all deleted. Unknown repositories are reported as not found.
 func (r *Registry) listTags(ctx context.Context, name string) ([]string, error) {
-	readyTags, err := r.db.ListTags(name)
-	if err == nil && len(readyTags) > 0 {
-		return readyTags, nil
+	name = r.canonicalName(name)
+	if !r.cfg.NoCache {
+		readyTags, err := r.db.ListTags(name)
+		if err == nil && len(readyTags) > 0 {
+			return readyTags, nil
+		}
 	}
 
-	var repoTags []string
-	var continuationToken *string
-	prefix := fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/tags/", name)
-	for {
-		req, err := r.s3Client.ListObjectsV2(ctx, &s3.ListObjectsV2Input{
-			Bucket:            &r.bucket,
-			Prefix:            &prefix,
-			ContinuationToken: continuationToken,
-		}, forcePathStyle)
+	repoTags, err := r.listStorageTags(ctx, name)
+	if err != nil {
+		return nil, err
+	}
+	if len(repoTags) == 0 {
+		exists, err := r.repositoryExists(ctx, name)
 		if err != nil {
 			return nil, err
 		}
-
-		for _, obj := range req.Contents {
-			if strings.HasSuffix(*obj.Key, "current/link") {
-				tag := strings.TrimSuffix(
-					strings.TrimPrefix(
-						*obj.Key,
-						fmt.Sprintf("docker/registry/v2/repositories/%s/_manifests/tags/", name),
-					),
-					"/current/link",
-				)
-				repoTags = append(repoTags, tag)
-			}
+		if !exists {
+			return nil, fmt.Errorf("repository %s not found: %w", name, fs.ErrNotExist)
 		}
-		if req.IsTruncated == nil || !*req.IsTruncated {
-			break
+		return []string{}, nil
+	}
+
+	if !r.cfg.NoCache && !r.cfg.ReadOnly {
+		if err := r.db.PutTags(name, repoTags); err != nil {
+			logDBWriteError(ctx, "error storing tags in database", err)
 		}
-		continuationToken = req.NextContinuationToken
 	}
 
-	err = r.db.PutTags(name, repoTags)
+	return repoTags, nil
+}
+
+// repositoryExists reports whether storage holds anything for a repository: tags, manifest
+// revisions or layer links.
+func (r *Registry) repositoryExists(ctx context.Context, name string) (bool, error) {
+	name = r.canonicalName(name)
+	exists := false
+	prefix := fmt.Sprintf("%s/repositories/%s/", r.prefix, name)
+	err := r.storageFor(name).List(ctx, prefix, func(ObjectInfo) error {
+		exists = true
+		return errStopListing
+	})
+	if err != nil && !errors.Is(err, errStopListing) {
+		return false, err
+	}
+	return exists, nil
+}
+
+// repositoryKnown is repositoryExists for the hot path of a missing manifest: the database is
+// asked first, and storage is only listed for repositories it doesn't know, with the answer kept
+// for --listing-cache-seconds, as a listing may well cost more than the request it answers.
+func (r *Registry) repositoryKnown(ctx context.Context, name string) (bool, error) {
+	name = r.canonicalName(name)
+	if !r.cfg.NoCache {
+		if exists, err := r.db.RepositoryExists(name); err == nil && exists {
+			return true, nil
+		}
+	}
+	if r.repositoryCache != nil {
+		if exists, ok := r.repositoryCache.Get(name); ok {
+			return exists, nil
+		}
+	}
+	exists, err := r.repositoryExists(ctx, name)
 	if err != nil {
-		slog.Error("error storing tags in database", "error", err)
+		return false, err
+	}
+	if r.repositoryCache != nil {
+		r.repositoryCache.Add(name, exists)
 	}
+	return exists, nil
+}
 
+// listStorageTags lists the tag directories of a repository rather than every key under them,
+// which would include a link per revision a tag ever pointed at.
+// NOTICE: like in distribution, a tag is any directory under tags/, its current/link isn't checked
+func (r *Registry) listStorageTags(ctx context.Context, name string) ([]string, error) {
+	var repoTags []string
+	prefix := fmt.Sprintf("%s/repositories/%s/_manifests/tags/", r.prefix, name)
+	err := r.storageFor(name).ListPrefixes(ctx, prefix, func(tagPrefix string) error {
+		if tag := strings.TrimSuffix(strings.TrimPrefix(tagPrefix, prefix), "/"); tag != "" {
+			repoTags = append(repoTags, tag)
+		}
+		return nil
+	})
+	if err != nil {
+		return nil, err
+	}
+	slices.Sort(repoTags)
 	return repoTags, nil
 }
 
-func (r *Registry) listRepositories(_ context.Context, continuationToken *string, n int) ([]string, *string, error) {
-	return r.db.ListRepositories(continuationToken, n)
+// WarmRepository caches all tags of a single repository in the database, like a Bootstrap
+// scoped to one repository.
+func (r *Registry) WarmRepository(ctx context.Context, name string) (map[string]any, error) {
+	repoTags, err := r.listStorageTags(ctx, name)
+	if err != nil {
+		return nil, fmt.Errorf("failed to list tags: %w", err)
+	}
+	if len(repoTags) == 0 {
+		return nil, fmt.Errorf("repository %s not found: %w", name, fs.ErrNotExist)
+	}
+	if err := r.db.PutTags(name, repoTags); err != nil {
+		logDBWriteError(ctx, "error storing tags in database", err)
+	}
+
+	var mu sync.Mutex
+	manifests := make(map[digest.Digest]struct{})
+	layers := make(map[string]struct{})
+	failed := 0
+
+	group, groupCtx := errgroup.WithContext(ctx)
+	group.SetLimit(runtime.NumCPU() * 4)
+	for _, tag := range repoTags {
+		group.Go(func() error {
+			manifest, manifestBytes, err := r.getManifest(groupCtx, name, tag)
+			mu.Lock()
+			defer mu.Unlock()
+			if err != nil {
+				slog.WarnContext(groupCtx, "error warming manifest", "repo", name, "tag", tag, "error", err)
+				failed++
+				return nil
+			}
+			manifests[digest.FromBytes(manifestBytes)] = struct{}{}
+			layerDigests, err := manifestLayerDigests(manifestBytes, manifest)
+			if err != nil {
+				return nil
+			}
+			for _, layerDigest := range layerDigests {
+				layers[layerDigest] = struct{}{}
+			}
+			return nil
+		})
+	}
+	if err := group.Wait(); err != nil {
+		return nil, err
+	}
+
+	slog.InfoContext(ctx, "warmed repository", "repo", name, "tags", len(repoTags), "manifests", len(manifests), "failed", failed)
+	return map[string]any{
+		"name":      name,
+		"tags":      len(repoTags),
+		"manifests": len(manifests),
+		"layers":    len(layers),
+		"failed":    failed,
+	}, nil
 }
 
-func (r *Registry) Bootstrap(ctx context.Context) error {
-	prefix := "docker/registry/v2/repositories/"
-	var continuationToken *string
+type ManifestReference struct {
+	Repo      string `json:"repo"`
+	Reference string `json:"reference"`
+}
 
-	group, ctx := errgroup.WithContext(ctx)
+type ResolvedManifest struct {
+	Repo      string `json:"repo"`
+	Reference string `json:"reference"`
+	Digest    string `json:"digest,omitempty"`
+	MediaType string `json:"media_type,omitempty"`
+	Error     string `json:"error,omitempty"`
+}
+
+// ResolveManifests resolves a batch of manifest references concurrently. Failures are reported
+// per item, so one missing repository doesn't fail the whole batch.
+func (r *Registry) ResolveManifests(ctx context.Context, refs []ManifestReference) []ResolvedManifest {
+	results := make([]ResolvedManifest, len(refs))
+	var group errgroup.Group
 	group.SetLimit(runtime.NumCPU() * 4)
+	for i, ref := range refs {
+		group.Go(func() error {
+			results[i] = r.resolveManifest(ctx, ref)
+			return nil
+		})
+	}
+	_ = group.Wait()
+	return results
+}
+
+func (r *Registry) resolveManifest(ctx context.Context, ref ManifestReference) ResolvedManifest {
+	result := ResolvedManifest{Repo: ref.Repo, Reference: ref.Reference}
+	if !validRepositoryName(ref.Repo) {
+		result.Error = "invalid repository name"
+		return result
+	}
+	if !validReference(ref.Reference) {
+		result.Error = "invalid reference"
+		return result
+	}
+	manifest, manifestBytes, err := r.getManifest(ctx, ref.Repo, ref.Reference)
+	if err != nil {
+		if errors.Is(err, fs.ErrNotExist) {
+			result.Error = "manifest not found"
+			return result
+		}
+		slog.WarnContext(ctx, "error resolving manifest", "repo", ref.Repo, "reference", ref.Reference, "error", err)
+		result.Error = err.Error()
+		return result
+	}
+	result.Digest = digest.FromBytes(manifestBytes).String()
+	result.MediaType = manifestMediaType(manifestBytes, manifest)
+	return result
+}
+
+func (r *Registry) listRepositories(_ context.Context, continuationToken *string, n int) ([]string, *string, error) {
+	if r.acl == nil {
+		return r.db.ListRepositories(continuationToken, n)
+	}
+	last := ""
+	if continuationToken != nil {
+		last = *continuationToken
+	}
+	repos, err := r.allowedRepositories(last, n)
+	if err != nil || len(repos) == 0 {
+		return nil, nil, err
+	}
+	return repos, &repos[len(repos)-1], nil
+}
 
-	found := uint64(0)
-	skipped := uint64(0)
-	processed := uint64(0)
-	processing := int64(0)
-	for {
-		req, err := r.s3Client.ListObjectsV2(ctx, &s3.ListObjectsV2Input{
-			Bucket:            &r.bucket,
-			Prefix:            &prefix,
-			ContinuationToken: continuationToken,
-		}, forcePathStyle)
+// allowedRepositories returns up to n cached repositories following last which the ACL allows.
+// NOTICE: the ACL has to be applied before cutting the page, so that denied repositories
+// neither shorten it nor end the listing early.
+func (r *Registry) allowedRepositories(last string, n int) ([]string, error) {
+	var repos []string
+	for len(repos) < n {
+		page, next, err := r.db.ListRepositories(&last, n)
 		if err != nil {
-			return err
+			return nil, err
 		}
-		for _, obj := range req.Contents {
-			if strings.HasSuffix(*obj.Key, "current/link") {
-				found++
-				noPrefix := strings.TrimPrefix(*obj.Key, "docker/registry/v2/repositories/")
-				repo, tag, ok := strings.Cut(noPrefix, "/_manifests/tags/")
-				if !ok {
-					continue
-				}
-				tag = strings.TrimSuffix(tag, "/current/link")
-				if r.db.Exists(repo, tag) {
-					skipped++
-					if skipped%10000 == 5000 {
-						slog.Info("Bootstrap progress", "skipped", skipped)
-					}
-					continue
-				}
-				group.Go(func() error {
-					atomic.AddInt64(&processing, 1)
-					defer atomic.AddInt64(&processing, -1)
-					_, _, err := r.getManifest(ctx, repo, tag)
-					atomic.AddUint64(&processed, 1)
-					if err != nil {
-						slog.Warn("error getting manifest", "repo", repo, "tag", tag, "error", err)
-					}
-					return nil
-				})
-				if found%1000 == 500 {
-					slog.Info("Bootstrap progress", "found", found, "processed", processed, "processing", processing)
-				}
+		for _, repo := range page {
+			if r.acl.Allowed(repo) {
+				repos = append(repos, repo)
 			}
 		}
-		if req.IsTruncated == nil || !*req.IsTruncated {
+		if next == nil || len(page) < n {
 			break
 		}
-		continuationToken = req.NextContinuationToken
+		last = *next
+	}
+	return repos[:min(len(repos), n)], nil
+}
+
+// listCatalog returns up to n repository names following last in lexical order, and whether
+// more remain. Until the database knows about any repository (or with --no-cache), they are scanned from storage.
+func (r *Registry) listCatalog(ctx context.Context, last string, n int) ([]string, bool, error) {
+	var repos []string
+	if !r.cfg.NoCache && r.db.HasRepositories() {
+		var err error
+		repos, err = r.allowedRepositories(last, n+1)
+		if err != nil {
+			return nil, false, err
+		}
+	} else {
+		for _, storage := range r.storages() {
+			err := r.walkStorageRepositories(ctx, storage, "", last, func(repo string) {
+				if r.storageFor(repo) == storage && r.acl.Allowed(repo) {
+					repos = append(repos, repo)
+				}
+			})
+			if err != nil {
+				return nil, false, err
+			}
+		}
+		// NOTICE: walked directory by directory, "b/c" comes before "b-d"
+		slices.Sort(repos)
+	}
+
+	if len(repos) > n {
+		return repos[:n], true, nil
+	}
+	return repos, false, nil
+}
+
+// walkStorageRepositories calls fn with the repositories following last under the repository
+// directory name ("" for the root), which are the directories holding a _manifests directory.
+// Only directories are listed, and neither repository internals nor subtrees sorting entirely
+// before last are descended into, so that paging the catalog doesn't read every key in storage.
+func (r *Registry) walkStorageRepositories(ctx context.Context, storage Storage, name string, last string, fn func(string)) error {
+	dir := r.prefix + "/repositories/"
+	if name != "" {
+		dir += name + "/"
+	}
+	var children []string
+	err := storage.ListPrefixes(ctx, dir, func(prefix string) error {
+		children = append(children, strings.TrimSuffix(strings.TrimPrefix(prefix, dir), "/"))
+		return nil
+	})
+	if err != nil {
+		return err
+	}
+
+	for _, child := range children {
+		if child == "_manifests" && name > last {
+			fn(name)
+		}
+		if child == "" || strings.HasPrefix(child, "_") {
+			continue
+		}
+		childName := path.Join(name, child)
+		// NOTICE: everything under childName sorts below childName+"0", "0" following "/"
+		if last != "" && childName+"0" <= last {
+			continue
+		}
+		if err := r.walkStorageRepositories(ctx, storage, childName, last, fn); err != nil {
+			return err
+		}
+	}
+	return nil
+}
+
+func (r *Registry) Bootstrap(ctx context.Context) error {
+	prefix := r.prefix + "/repositories/"
+
+	// NOTICE: each tag costs two sequential storage round trips (its link, then the manifest),
+	// so bootstrapping is bound by latency rather than CPU and scales with the number of workers
+	group, ctx := errgroup.WithContext(ctx)
+	workers := r.cfg.BootstrapWorkers
+	if workers < 1 {
+		workers = runtime.NumCPU() * 4
+	}
+	group.SetLimit(workers)
+
+	tracker := newBootstrapTracker(r.cfg.ProgressFormat, os.Stdout)
+	stopReporting := tracker.reportPeriodically(ctx)
+	defer func() {
+		stopReporting()
+		tracker.report(ctx, "summary")
+	}()
+
+	for _, storage := range r.storages() {
+		err := storage.List(ctx, prefix, func(obj ObjectInfo) error {
+			repo, tag, ok := r.parseTagLinkKey(obj.Key)
+			if !ok || r.storageFor(repo) != storage {
+				return nil
+			}
+			tracker.setRepo(repo)
+			if found := tracker.found.Add(1); found%1000 == 500 {
+				tracker.report(ctx, "progress")
+			}
+			if r.db.Exists(repo, tag) {
+				tracker.skipped.Add(1)
+				return nil
+			}
+			group.Go(func() error {
+				_, _, err := r.getManifest(ctx, repo, tag)
+				if err != nil {
+					tracker.failed.Add(1)
+					slog.WarnContext(ctx, "error getting manifest", "repo", repo, "tag", tag, "error", err)
+				}
+				tracker.done.Add(1)
+				return nil
+			})
+			return nil
+		})
+		if err != nil {
+			return errors.Join(err, group.Wait())
+		}
 	}
 	return group.Wait()
 }
 
+var errStopListing = errors.New("stop listing")
+
+// CheckReady verifies that the database and all storage backends are reachable.
+func (r *Registry) CheckReady(ctx context.Context) error {
+	if err := r.db.Ping(ctx); err != nil {
+		return fmt.Errorf("database is unreachable: %w", err)
+	}
+	for _, storage := range r.storages() {
+		err := storage.List(ctx, r.prefix+"/", func(ObjectInfo) error {
+			return errStopListing
+		})
+		if err != nil && !errors.Is(err, errStopListing) {
+			return fmt.Errorf("storage is unreachable: %w", err)
+		}
+	}
+	return nil
+}
+
 func (r *Registry) listAllTags(_ context.Context, continuationToken *string, n int) ([]map[string]string, *string, error) {
 	return r.db.ListAllTags(continuationToken, n)
 }
@@ -572,15 +1483,92 @@ func (r *Registry) listManifests(_ context.Context, continuationToken *string, n
 	return r.db.ListManifests(continuationToken, n)
 }
 
+func (r *Registry) listRepositoryManifests(_ context.Context, name string) ([]map[string]any, error) {
+	return r.db.ListRepositoryManifests(name)
+}
+
+func (r *Registry) getRepositorySize(_ context.Context, name string) (map[string]any, error) {
+	return r.db.GetRepositorySize(name)
+}
+
+func (r *Registry) listRepositoryBlobs(_ context.Context, name string) ([]RepositoryBlob, error) {
+	return r.db.ListRepositoryBlobs(name)
+}
+
+func (r *Registry) listLargestLayers(_ context.Context, name string, n int) ([]RepositoryBlob, error) {
+	return r.db.ListLargestLayers(name, n)
+}
+
+func (r *Registry) exportManifests(ctx context.Context, repo string, fn func(ExportedManifest) error) error {
+	return r.db.ExportManifests(ctx, repo, fn)
+}
+
+func (r *Registry) searchRepositories(_ context.Context, prefix string, n int) ([]string, error) {
+	return r.db.SearchRepositories(prefix, n)
+}
+
 func (r *Registry) listUploadSessions(_ context.Context) ([]map[string]interface{}, error) {
 	return r.db.ListUploadSessions()
 }
 
 func (r *Registry) getRegistryStats(_ context.Context) (map[string]interface{}, error) {
-	return r.db.GetRegistryStats()
+	stats, err := r.db.GetRegistryStats()
+	if err != nil {
+		return nil, err
+	}
+	// NOTICE: with WAL, recent writes live in the -wal file until the next checkpoint
+	var dbSize int64
+	for _, path := range []string{r.cfg.DBPath, r.cfg.DBPath + "-wal"} {
+		if info, err := os.Stat(path); err == nil {
+			dbSize += info.Size()
+		}
+	}
+	stats["db_size_bytes"] = dbSize
+	pragmas, err := r.db.SQLitePragmas()
+	if err != nil {
+		return nil, err
+	}
+	stats["sqlite_pragmas"] = pragmas
+	stats["db_connections"] = r.db.PoolStats()
+	if r.manifestCache != nil {
+		hits, misses := r.manifestCacheHits.Load(), r.manifestCacheMisses.Load()
+		hitRate := 0.0
+		if hits+misses > 0 {
+			hitRate = float64(hits) / float64(hits+misses)
+		}
+		stats["manifest_cache"] = map[string]any{
+			"entries":  r.manifestCache.Len(),
+			"capacity": r.cfg.ManifestCacheSize,
+			"hits":     hits,
+			"misses":   misses,
+			"hit_rate": hitRate,
+		}
+	}
+	// NOTICE: the limiter is shared by all buckets, and routed buckets imply an s3 default storage
+	if storage, ok := r.storage.(*s3Storage); ok && storage.limiter != nil {
+		stats["s3_requests"] = map[string]any{
+			"in_flight": storage.limiter.inFlight.Load(),
+			"queued":    storage.limiter.queued.Load(),
+			"limit":     cap(storage.limiter.slots),
+		}
+	}
+	if r.presignCache != nil {
+		stats["presign_cache"] = map[string]any{
+			"entries":  r.presignCache.Len(),
+			"capacity": r.cfg.PresignCacheSize,
+			"hits":     r.presignCacheHits.Load(),
+			"misses":   r.presignCacheMisses.Load(),
+		}
+	}
+	return stats, nil
 }
 
 func (r *Registry) Close() error {
+	// NOTICE: leave a truncated log behind, so that the next start doesn't replay it; a read-only
+	// registry doesn't write to the database at all
+	if !r.cfg.ReadOnly {
+		r.checkpointWAL(context.Background())
+	}
 	if err := r.db.Close(); err != nil {
 		return fmt.Errorf("failed to close database: %w", err)
 	}
@@ -0,0 +1,334 @@
+package reg
+
+import (
+	"context"
+	"encoding/json"
+	"fmt"
+	"net/http"
+	"net/http/httptest"
+	"slices"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/opencontainers/go-digest"
+	specs "github.com/opencontainers/image-spec/specs-go"
+	v1 "github.com/opencontainers/image-spec/specs-go/v1"
+)
+
+func TestCachedIndexStoresChildren(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.PrefetchChildren = true
+	})
+	child := testManifest(t, r, "layer")
+	childDigest := linkTestManifest(t, r, "foo", child)
+	index, err := json.Marshal(v1.Index{
+		Versioned: specs.Versioned{SchemaVersion: 2},
+		MediaType: v1.MediaTypeImageIndex,
+		Manifests: []v1.Descriptor{{MediaType: v1.MediaTypeImageManifest, Digest: childDigest, Size: int64(len(child))}},
+	})
+	if err != nil {
+		t.Fatalf("failed to marshal index: %v", err)
+	}
+	if _, err := r.putManifest(ctx, "foo", "latest", index); err != nil {
+		t.Fatalf("failed to push index: %v", err)
+	}
+
+	// NOTICE: children are stored in the background
+	deadline := time.Now().Add(5 * time.Second)
+	for {
+		cached, err := r.db.GetManifestByDigest("foo", childDigest)
+		if err == nil {
+			if got := digest.FromString(cached); got != childDigest {
+				t.Fatalf("got child %s, want %s", got, childDigest)
+			}
+			break
+		}
+		if time.Now().After(deadline) {
+			t.Fatalf("child manifest was never stored: %v", err)
+		}
+		time.Sleep(10 * time.Millisecond)
+	}
+}
+
+func TestCachedIndexSkipsChildren(t *testing.T) {
+	for _, tc := range []struct {
+		name     string
+		prefetch bool
+	}{
+		{name: "prefetch disabled", prefetch: false},
+		{name: "every slot taken", prefetch: true},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			ctx := context.Background()
+			r := newTestRegistry(t, func(cfg *Config) {
+				cfg.PrefetchChildren = tc.prefetch
+			})
+			for range cap(r.childSlots) {
+				r.childSlots <- struct{}{}
+			}
+			child := testManifest(t, r, "layer")
+			childDigest := linkTestManifest(t, r, "foo", child)
+			index, err := json.Marshal(v1.Index{
+				Versioned: specs.Versioned{SchemaVersion: 2},
+				MediaType: v1.MediaTypeImageIndex,
+				Manifests: []v1.Descriptor{{MediaType: v1.MediaTypeImageManifest, Digest: childDigest, Size: int64(len(child))}},
+			})
+			if err != nil {
+				t.Fatalf("failed to marshal index: %v", err)
+			}
+			if _, err := r.putManifest(ctx, "foo", "latest", index); err != nil {
+				t.Fatalf("failed to push index: %v", err)
+			}
+			// NOTICE: skipped children never get a goroutine, so there is nothing to wait for
+			if _, err := r.db.GetManifestByDigest("foo", childDigest); err == nil {
+				t.Fatal("child manifest was stored")
+			}
+		})
+	}
+}
+
+func TestManifestTTLOverrides(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.ManifestCacheSize = 0
+		cfg.CacheTTL = time.Hour
+		cfg.CacheTTLOverrides = []string{"short=60"}
+	})
+	pushTestImage(t, r, "short", "latest", "old short layer")
+	longOld := pushTestImage(t, r, "long", "latest", "old long layer")
+
+	// NOTICE: both were last checked 10 minutes ago, past the short TTL but within the default one
+	if _, err := r.db.db.Exec(`UPDATE manifests SET checked_at = datetime('now', '-10 minutes')`); err != nil {
+		t.Fatalf("failed to age cached manifests: %v", err)
+	}
+	shortNew := retargetTestTag(t, r, "short", "latest", testManifest(t, r, "new short layer"))
+	retargetTestTag(t, r, "long", "latest", testManifest(t, r, "new long layer"))
+
+	for _, tc := range []struct {
+		name string
+		want digest.Digest
+	}{
+		{name: "short", want: shortNew},
+		{name: "long", want: longOld},
+	} {
+		_, manifestBytes, err := r.getManifest(ctx, tc.name, "latest")
+		if err != nil {
+			t.Fatalf("failed to get %s:latest: %v", tc.name, err)
+		}
+		if got := digest.FromBytes(manifestBytes); got != tc.want {
+			t.Errorf("got %s:latest %s, want %s", tc.name, got, tc.want)
+		}
+	}
+}
+
+func TestPresignCacheHit(t *testing.T) {
+	ctx := context.Background()
+	r, storage := newCountingTestRegistry(t, func(cfg *Config) { cfg.PresignCacheSize = 16 })
+	dgst := digest.FromString("layer").String()
+
+	first, _, err := r.getBlobRedirect(ctx, "foo", dgst, "GET")
+	if err != nil {
+		t.Fatalf("failed to presign: %v", err)
+	}
+	second, _, err := r.getBlobRedirect(ctx, "foo", dgst, "GET")
+	if err != nil {
+		t.Fatalf("failed to presign: %v", err)
+	}
+	if first != second {
+		t.Fatalf("got a new URL %s, want the cached %s", second, first)
+	}
+	if presigns := storage.presigns.Load(); presigns != 1 {
+		t.Fatalf("presigned %d times, want 1", presigns)
+	}
+	if hits, misses := r.presignCacheHits.Load(), r.presignCacheMisses.Load(); hits != 1 || misses != 1 {
+		t.Fatalf("got %d hits and %d misses, want 1 and 1", hits, misses)
+	}
+
+	// NOTICE: the method is part of the signature, so HEAD gets a URL of its own
+	if _, _, err := r.getBlobRedirect(ctx, "foo", dgst, "HEAD"); err != nil {
+		t.Fatalf("failed to presign: %v", err)
+	}
+	if presigns := storage.presigns.Load(); presigns != 2 {
+		t.Fatalf("presigned %d times, want 2", presigns)
+	}
+}
+
+func TestListStorageTagsDelimited(t *testing.T) {
+	ctx := context.Background()
+	r, storage := newCountingTestRegistry(t)
+	var want []string
+	for i := range 10 {
+		tag := fmt.Sprintf("v%d", i)
+		pushTestImage(t, r, "foo", tag, "layer "+tag)
+		want = append(want, tag)
+	}
+
+	storage.listedKeys.Store(0)
+	tags, err := r.listStorageTags(ctx, "foo")
+	if err != nil {
+		t.Fatalf("failed to list tags: %v", err)
+	}
+	if !slices.Equal(tags, want) {
+		t.Fatalf("got tags %v, want %v", tags, want)
+	}
+	delimited := storage.listedKeys.Swap(0)
+
+	// NOTICE: every tag has at least its current link and an index link under the tags prefix
+	prefix := fmt.Sprintf("%s/repositories/foo/_manifests/tags/", r.prefix)
+	if err := storage.List(ctx, prefix, func(ObjectInfo) error { return nil }); err != nil {
+		t.Fatalf("failed to list keys: %v", err)
+	}
+	if full := storage.listedKeys.Load(); delimited != int64(len(want)) || full < 2*delimited {
+		t.Fatalf("delimited listing fetched %d keys, a full one %d", delimited, full)
+	}
+}
+
+func TestParseTagLinkKey(t *testing.T) {
+	r := newTestRegistry(t)
+	for _, tc := range []struct {
+		key  string
+		repo string
+		tag  string
+		ok   bool
+	}{
+		{key: "docker/registry/v2/repositories/app/_manifests/tags/latest/current/link", repo: "app", tag: "latest", ok: true},
+		{key: "docker/registry/v2/repositories/team/project/app/_manifests/tags/v1.0/current/link", repo: "team/project/app", tag: "v1.0", ok: true},
+		{key: "docker/registry/v2/repositories/a/_manifests/tags/b/_manifests/tags/c/current/link", repo: "a/_manifests/tags/b", tag: "c", ok: true},
+		{key: "docker/registry/v2/repositories/app/_manifests/tags/latest/index/sha256/abc/link"},
+		{key: "docker/registry/v2/repositories/app/_manifests/tags/a/b/current/link"},
+		{key: "docker/registry/v2/repositories/_manifests/tags/latest/current/link"},
+		{key: "docker/registry/v2/repositories/app/_manifests/tags//current/link"},
+		{key: "other/repositories/app/_manifests/tags/latest/current/link"},
+	} {
+		repo, tag, ok := r.parseTagLinkKey(tc.key)
+		if repo != tc.repo || tag != tc.tag || ok != tc.ok {
+			t.Errorf("parseTagLinkKey(%q) = %q, %q, %v, want %q, %q, %v", tc.key, repo, tag, ok, tc.repo, tc.tag, tc.ok)
+		}
+	}
+}
+
+func TestParseLink(t *testing.T) {
+	sha256Digest := digest.FromString("manifest")
+	sha512Digest := digest.SHA512.FromString("manifest")
+	for _, tc := range []struct {
+		content string
+		want    digest.Digest
+	}{
+		{content: sha256Digest.String(), want: sha256Digest},
+		{content: sha256Digest.String() + "\n", want: sha256Digest},
+		{content: sha256Digest.String() + "\r\n", want: sha256Digest},
+		{content: " \t" + sha256Digest.String(), want: sha256Digest},
+		{content: sha512Digest.String() + "\n", want: sha512Digest},
+		{content: ""},
+		{content: "\r\n"},
+		{content: sha256Digest.Hex()},
+		{content: sha256Digest.String()[:20]},
+		{content: "sha256:" + strings.ToUpper(sha256Digest.Hex())},
+		{content: sha256Digest.String() + "\n" + sha256Digest.String()},
+	} {
+		got, err := parseLink("link", []byte(tc.content))
+		if got != tc.want || (err == nil) != (tc.want != "") {
+			t.Errorf("parseLink(%q) = %q, %v, want %q", tc.content, got, err, tc.want)
+		}
+	}
+}
+
+func TestTagLinkContent(t *testing.T) {
+	r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = true })
+	router := newTestRouter(t, r)
+	dgst := linkTestManifest(t, r, "foo", testManifest(t, r, "layer"))
+	for tag, link := range map[string]string{
+		"crlf":      dgst.String() + "\r\n",
+		"malformed": "latest\n",
+	} {
+		linkKey := fmt.Sprintf("%s/repositories/foo/_manifests/tags/%s/current/link", r.prefix, tag)
+		if err := r.storage.PutObject(context.Background(), linkKey, []byte(link)); err != nil {
+			t.Fatalf("failed to put tag link: %v", err)
+		}
+	}
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/crlf", nil))
+	if rec.Code != http.StatusOK || rec.Header().Get("Docker-Content-Digest") != dgst.String() {
+		t.Errorf("got status %d and digest %s, want %d and %s", rec.Code, rec.Header().Get("Docker-Content-Digest"), http.StatusOK, dgst)
+	}
+	// NOTICE: a malformed link is a broken repository, not an unknown manifest
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/malformed", nil))
+	if rec.Code != http.StatusInternalServerError {
+		t.Errorf("got status %d for a malformed tag link, want %d: %s", rec.Code, http.StatusInternalServerError, rec.Body)
+	}
+}
+
+func TestNoCacheServesStorage(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t, func(cfg *Config) { cfg.NoCache = true })
+	pushTestImage(t, r, "foo", "latest", "old layer")
+	want := retargetTestTag(t, r, "foo", "latest", testManifest(t, r, "new layer"))
+
+	_, manifestBytes, err := r.getManifest(ctx, "foo", "latest")
+	if err != nil {
+		t.Fatalf("failed to get manifest: %v", err)
+	}
+	if got := digest.FromBytes(manifestBytes); got != want {
+		t.Fatalf("got manifest %s, want the re-pushed %s", got, want)
+	}
+	if tags, err := r.db.ListTags("foo"); err != nil || len(tags) > 0 {
+		t.Fatalf("got tags %v (%v) in the database, want none", tags, err)
+	}
+}
+
+func TestStorageForLongestPrefix(t *testing.T) {
+	r := newTestRegistry(t)
+	team, app := newTestFSStorage(t, testConfig(t.TempDir())), newTestFSStorage(t, testConfig(t.TempDir()))
+	routes, err := ParseBucketRoutes([]string{"team/=team", "team/app/=app"})
+	if err != nil {
+		t.Fatalf("failed to parse routes: %v", err)
+	}
+	byBucket := map[string]Storage{"team": team, "app": app}
+	for _, route := range routes {
+		r.routes = append(r.routes, storageRoute{prefix: route.Prefix, storage: byBucket[route.Bucket]})
+	}
+
+	for name, want := range map[string]Storage{
+		"team/app/web": app,
+		"team/db":      team,
+		"other":        r.storage,
+		"teams/app":    r.storage,
+	} {
+		if got := r.storageFor(name); got != want {
+			t.Errorf("repository %s routed to the wrong storage", name)
+		}
+	}
+}
+
+func TestCustomStoragePrefix(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t, func(cfg *Config) {
+		cfg.NoCache = true
+		cfg.StoragePrefix = "/registry/prod/"
+	})
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+
+	for _, key := range []string{
+		"registry/prod/repositories/foo/_manifests/tags/latest/current/link",
+		"registry/prod/repositories/foo/_manifests/revisions/sha256/" + dgst.Hex() + "/link",
+		"registry/prod/blobs/sha256/" + dgst.Hex()[:2] + "/" + dgst.Hex() + "/data",
+	} {
+		if _, err := r.storage.StatObject(ctx, key); err != nil {
+			t.Errorf("failed to find %s: %v", key, err)
+		}
+	}
+	if _, err := r.storage.StatObject(ctx, "docker/registry/v2/repositories/foo/_manifests/tags/latest/current/link"); err == nil {
+		t.Errorf("tag link written under the default prefix")
+	}
+
+	router := newTestRouter(t, r)
+	if got := getTestTags(t, router, "foo"); !slices.Equal(got, []string{"latest"}) {
+		t.Errorf("got tags %v, want [latest]", got)
+	}
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, "/v2/foo/manifests/latest", nil))
+	if rec.Code != http.StatusOK || rec.Header().Get("Docker-Content-Digest") != dgst.String() {
+		t.Errorf("got status %d and digest %s, want %d and %s", rec.Code, rec.Header().Get("Docker-Content-Digest"), http.StatusOK, dgst)
+	}
+}
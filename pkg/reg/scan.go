@@ -0,0 +1,86 @@
+package reg
+
+import (
+	"context"
+	"fmt"
+	"log/slog"
+	"strings"
+)
+
+type ScanResult struct {
+	Repositories int   `json:"repositories"`
+	Tags         int   `json:"tags"`
+	Manifests    int   `json:"manifests"`
+	Blobs        int   `json:"blobs"`
+	BlobBytes    int64 `json:"blob_bytes"`
+}
+
+// Scan walks the storage layout and counts what a bootstrap would have to process, using listings
+// only: it neither opens the database nor fetches any manifest. Memory use grows with the number of
+// repositories and manifests, not with the number of objects listed.
+func Scan(ctx context.Context, cfg Config) (*ScanResult, error) {
+	storage, routes, err := newStorages(ctx, cfg)
+	if err != nil {
+		return nil, err
+	}
+	// NOTICE: a registry without a database, only used for routing repositories to storages
+	r := &Registry{storage: storage, routes: routes, cfg: cfg, prefix: cfg.storagePrefix()}
+
+	repositories := make(map[string]struct{})
+	manifests := make(map[string]struct{})
+	result := &ScanResult{}
+	for _, storage := range r.storages() {
+		listed := 0
+		err := storage.List(ctx, r.prefix+"/repositories/", func(obj ObjectInfo) error {
+			listed++
+			if listed%100000 == 0 {
+				slog.InfoContext(ctx, "Scan progress", "objects", listed, "repositories", len(repositories), "tags", result.Tags)
+			}
+			if repo, _, ok := r.parseTagLinkKey(obj.Key); ok && r.storageFor(repo) == storage {
+				repositories[repo] = struct{}{}
+				result.Tags++
+				return nil
+			}
+			if dgst, ok := parseRevisionLinkKey(obj.Key); ok {
+				manifests[dgst] = struct{}{}
+			}
+			return nil
+		})
+		if err != nil {
+			return nil, fmt.Errorf("failed to list repositories: %w", err)
+		}
+
+		prefix := r.prefix + "/blobs/"
+		err = storage.List(ctx, prefix, func(obj ObjectInfo) error {
+			// <prefix>/blobs/<algorithm>/<first two hex chars>/<hex>/data
+			parts := strings.Split(strings.TrimPrefix(obj.Key, prefix), "/")
+			if len(parts) != 4 || parts[3] != "data" {
+				return nil
+			}
+			result.Blobs++
+			result.BlobBytes += obj.Size
+			return nil
+		})
+		if err != nil {
+			return nil, fmt.Errorf("failed to list blobs: %w", err)
+		}
+	}
+	result.Repositories = len(repositories)
+	result.Manifests = len(manifests)
+	return result, nil
+}
+
+// parseRevisionLinkKey extracts the manifest digest from the key of a revision link,
+// <prefix>/repositories/<name>/_manifests/revisions/<algorithm>/<hex>/link.
+func parseRevisionLinkKey(key string) (string, bool) {
+	const marker = "/_manifests/revisions/"
+	i := strings.LastIndex(key, marker)
+	if i < 0 {
+		return "", false
+	}
+	parts := strings.Split(key[i+len(marker):], "/")
+	if len(parts) != 3 || parts[2] != "link" {
+		return "", false
+	}
+	return parts[0] + ":" + parts[1], true
+}
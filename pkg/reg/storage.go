@@ -0,0 +1,393 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"errors"
+	"fmt"
+	"io"
+	"io/fs"
+	"net"
+	"net/http"
+	"sync/atomic"
+	"time"
+
+	"github.com/aws/aws-sdk-go-v2/aws"
+	v4 "github.com/aws/aws-sdk-go-v2/aws/signer/v4"
+	awshttp "github.com/aws/aws-sdk-go-v2/aws/transport/http"
+	"github.com/aws/aws-sdk-go-v2/config"
+	"github.com/aws/aws-sdk-go-v2/credentials"
+	"github.com/aws/aws-sdk-go-v2/service/s3"
+	"github.com/aws/aws-sdk-go-v2/service/s3/types"
+)
+
+// ErrPresignUnsupported is returned by backends which cannot hand out direct URLs to their objects;
+// blobs stored in such backends need to be proxied through the registry instead.
+var ErrPresignUnsupported = errors.New("presigning is not supported by this storage backend")
+
+// ErrStorage marks failures of the storage backend itself, as opposed to missing objects.
+var ErrStorage = errors.New("storage backend error")
+
+// ErrStorageThrottled additionally marks storage failures caused by rate limiting, which are
+// worth retrying later, unlike e.g. denied credentials.
+var ErrStorageThrottled = errors.New("storage backend is throttling requests")
+
+type ObjectInfo struct {
+	Key          string
+	Size         int64
+	LastModified time.Time
+}
+
+// Storage abstracts the object store holding the distribution layout (docker/registry/v2/... by default).
+// Missing objects are reported with errors wrapping fs.ErrNotExist.
+type Storage interface {
+	GetObject(ctx context.Context, key string) (io.ReadCloser, error)
+	GetObjectRange(ctx context.Context, key string, offset int64, length int64) (io.ReadCloser, error)
+	PutObject(ctx context.Context, key string, data []byte) error
+	StatObject(ctx context.Context, key string) (int64, error)
+	DeleteObject(ctx context.Context, key string) error
+	CopyObject(ctx context.Context, srcKey string, dstKey string) error
+	List(ctx context.Context, prefix string, fn func(ObjectInfo) error) error
+	// ListPrefixes calls fn with the distinct prefixes of the keys under prefix which end at
+	// their next slash, slash included - the "subdirectories" of prefix.
+	ListPrefixes(ctx context.Context, prefix string, fn func(string) error) error
+	Presign(ctx context.Context, method string, key string, expires time.Duration) (string, error)
+
+	CreateMultipartUpload(ctx context.Context, key string) (string, error)
+	UploadPart(ctx context.Context, key string, uploadID string, partNumber int32, data []byte) error
+	CompleteMultipartUpload(ctx context.Context, key string, uploadID string) error
+	AbortMultipartUpload(ctx context.Context, key string, uploadID string) error
+}
+
+type s3Storage struct {
+	client  *s3.Client
+	presign *s3.PresignClient
+	bucket  string
+	limiter *s3RequestLimiter
+}
+
+// s3RequestLimiter bounds the S3 requests in flight across all buckets, so that a burst of cold
+// pulls queues up in the registry instead of getting throttled by S3 or running out of sockets.
+// A slot is held until the response headers arrive: streaming a body doesn't count as in flight.
+type s3RequestLimiter struct {
+	slots    chan struct{}
+	inFlight atomic.Int64
+	queued   atomic.Int64
+}
+
+func newS3RequestLimiter(limit int) *s3RequestLimiter {
+	return &s3RequestLimiter{slots: make(chan struct{}, limit)}
+}
+
+// limitedHTTPClient makes every request of the SDK, including retries and each page of
+// a listing, take a slot of the limiter.
+type limitedHTTPClient struct {
+	client  aws.HTTPClient
+	limiter *s3RequestLimiter
+}
+
+func (c limitedHTTPClient) Do(req *http.Request) (*http.Response, error) {
+	c.limiter.queued.Add(1)
+	select {
+	case c.limiter.slots <- struct{}{}:
+		c.limiter.queued.Add(-1)
+	case <-req.Context().Done():
+		c.limiter.queued.Add(-1)
+		return nil, req.Context().Err()
+	}
+	c.limiter.inFlight.Add(1)
+	defer func() {
+		c.limiter.inFlight.Add(-1)
+		<-c.limiter.slots
+	}()
+	return c.client.Do(req)
+}
+
+var forcePathStyle = func(o *s3.Options) {
+	o.UsePathStyle = true
+}
+
+func newS3Storage(ctx context.Context, regCfg Config, limiter *s3RequestLimiter) (*s3Storage, error) {
+	var opts []func(*config.LoadOptions) error
+	if regCfg.AWSRegion != "" {
+		opts = append(opts, config.WithRegion(regCfg.AWSRegion))
+	}
+	if regCfg.AWSProfile != "" {
+		opts = append(opts, config.WithSharedConfigProfile(regCfg.AWSProfile))
+	}
+	var httpClient aws.HTTPClient = awshttp.NewBuildableClient()
+	if regCfg.S3Timeout > 0 {
+		// NOTICE: the timeouts bound connecting and waiting for a response, not reading the body,
+		// so that streaming large blobs is not cut short. They apply per attempt, and the SDK
+		// retries up to 3 times, which puts the worst case at a few multiples of the timeout.
+		timeout := regCfg.S3Timeout
+		httpClient = awshttp.NewBuildableClient().
+			WithDialerOptions(func(d *net.Dialer) {
+				d.Timeout = timeout
+			}).
+			WithTransportOptions(func(tr *http.Transport) {
+				tr.TLSHandshakeTimeout = timeout
+				tr.ResponseHeaderTimeout = timeout
+			})
+	}
+	if limiter != nil {
+		httpClient = limitedHTTPClient{client: httpClient, limiter: limiter}
+	}
+	if tracingEnabled() {
+		// NOTICE: outside of the limiter, so that time spent queued for a slot is part of the span
+		httpClient = tracedHTTPClient{client: httpClient}
+	}
+	opts = append(opts, config.WithHTTPClient(httpClient))
+	if regCfg.AWSAccessKeyID != "" {
+		opts = append(opts, config.WithCredentialsProvider(
+			credentials.NewStaticCredentialsProvider(regCfg.AWSAccessKeyID, regCfg.AWSSecretAccessKey, ""),
+		))
+	}
+
+	cfg, err := config.LoadDefaultConfig(ctx, opts...)
+	if err != nil {
+		return nil, fmt.Errorf("unable to load SDK config, %v", err)
+	}
+	cfg.ResponseChecksumValidation = aws.ResponseChecksumValidationWhenRequired
+
+	// NOTICE: both clients are safe for concurrent use and built once, presigning happens
+	// on every blob pull and would otherwise rebuild the client's middleware stack each time
+	client := s3.NewFromConfig(cfg, forcePathStyle)
+	return &s3Storage{
+		client:  client,
+		presign: s3.NewPresignClient(client),
+		bucket:  regCfg.Bucket,
+		limiter: limiter,
+	}, nil
+}
+
+// s3Error marks missing objects with fs.ErrNotExist and any other failure with ErrStorage,
+// throttled requests (503 SlowDown, 429) also with ErrStorageThrottled.
+func s3Error(err error) error {
+	if err == nil {
+		return nil
+	}
+	var nsk *types.NoSuchKey
+	if errors.As(err, &nsk) {
+		return errors.Join(err, fs.ErrNotExist)
+	}
+	var nse *types.NotFound
+	if errors.As(err, &nse) {
+		return errors.Join(err, fs.ErrNotExist)
+	}
+	var respErr *awshttp.ResponseError
+	if errors.As(err, &respErr) {
+		switch respErr.HTTPStatusCode() {
+		case http.StatusServiceUnavailable, http.StatusTooManyRequests:
+			return fmt.Errorf("%w: %w: %w", ErrStorage, ErrStorageThrottled, err)
+		}
+	}
+	return fmt.Errorf("%w: %w", ErrStorage, err)
+}
+
+func (s *s3Storage) GetObject(ctx context.Context, key string) (io.ReadCloser, error) {
+	obj, err := s.client.GetObject(ctx, &s3.GetObjectInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+	}, forcePathStyle)
+	if err != nil {
+		return nil, s3Error(err)
+	}
+	return obj.Body, nil
+}
+
+func (s *s3Storage) GetObjectRange(ctx context.Context, key string, offset int64, length int64) (io.ReadCloser, error) {
+	obj, err := s.client.GetObject(ctx, &s3.GetObjectInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+		Range:  aws.String(fmt.Sprintf("bytes=%d-%d", offset, offset+length-1)),
+	}, forcePathStyle)
+	if err != nil {
+		return nil, s3Error(err)
+	}
+	return obj.Body, nil
+}
+
+func (s *s3Storage) PutObject(ctx context.Context, key string, data []byte) error {
+	_, err := s.client.PutObject(ctx, &s3.PutObjectInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+		Body:   bytes.NewReader(data),
+	}, forcePathStyle)
+	return s3Error(err)
+}
+
+func (s *s3Storage) StatObject(ctx context.Context, key string) (int64, error) {
+	obj, err := s.client.HeadObject(ctx, &s3.HeadObjectInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+	}, forcePathStyle)
+	if err != nil {
+		return 0, s3Error(err)
+	}
+	return aws.ToInt64(obj.ContentLength), nil
+}
+
+func (s *s3Storage) DeleteObject(ctx context.Context, key string) error {
+	_, err := s.client.DeleteObject(ctx, &s3.DeleteObjectInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+	}, forcePathStyle)
+	return s3Error(err)
+}
+
+func (s *s3Storage) CopyObject(ctx context.Context, srcKey string, dstKey string) error {
+	_, err := s.client.CopyObject(ctx, &s3.CopyObjectInput{
+		Bucket:     &s.bucket,
+		Key:        &dstKey,
+		CopySource: aws.String(fmt.Sprintf("%s/%s", s.bucket, srcKey)),
+	}, forcePathStyle)
+	return s3Error(err)
+}
+
+func (s *s3Storage) List(ctx context.Context, prefix string, fn func(ObjectInfo) error) error {
+	var continuationToken *string
+	for {
+		req, err := s.client.ListObjectsV2(ctx, &s3.ListObjectsV2Input{
+			Bucket:            &s.bucket,
+			Prefix:            &prefix,
+			ContinuationToken: continuationToken,
+		}, forcePathStyle)
+		if err != nil {
+			return s3Error(err)
+		}
+		for _, obj := range req.Contents {
+			if err := fn(ObjectInfo{
+				Key:          aws.ToString(obj.Key),
+				Size:         aws.ToInt64(obj.Size),
+				LastModified: aws.ToTime(obj.LastModified),
+			}); err != nil {
+				return err
+			}
+		}
+		if req.IsTruncated == nil || !*req.IsTruncated {
+			return nil
+		}
+		continuationToken = req.NextContinuationToken
+	}
+}
+
+func (s *s3Storage) ListPrefixes(ctx context.Context, prefix string, fn func(string) error) error {
+	var continuationToken *string
+	for {
+		req, err := s.client.ListObjectsV2(ctx, &s3.ListObjectsV2Input{
+			Bucket:            &s.bucket,
+			Prefix:            &prefix,
+			Delimiter:         aws.String("/"),
+			ContinuationToken: continuationToken,
+		}, forcePathStyle)
+		if err != nil {
+			return s3Error(err)
+		}
+		for _, commonPrefix := range req.CommonPrefixes {
+			if err := fn(aws.ToString(commonPrefix.Prefix)); err != nil {
+				return err
+			}
+		}
+		if req.IsTruncated == nil || !*req.IsTruncated {
+			return nil
+		}
+		continuationToken = req.NextContinuationToken
+	}
+}
+
+func (s *s3Storage) Presign(ctx context.Context, method string, key string, expires time.Duration) (string, error) {
+	var err error
+	var presignedReq *v4.PresignedHTTPRequest
+	presignClient := s.presign
+	switch method {
+	case http.MethodGet:
+		presignedReq, err = presignClient.PresignGetObject(ctx,
+			&s3.GetObjectInput{
+				Bucket: &s.bucket,
+				Key:    &key,
+			},
+			s3.WithPresignExpires(expires),
+			func(opts *s3.PresignOptions) {
+				opts.ClientOptions = append(opts.ClientOptions, forcePathStyle)
+			},
+		)
+	case http.MethodHead:
+		presignedReq, err = presignClient.PresignHeadObject(ctx,
+			&s3.HeadObjectInput{
+				Bucket: &s.bucket,
+				Key:    &key,
+			},
+			s3.WithPresignExpires(expires),
+			func(opts *s3.PresignOptions) {
+				opts.ClientOptions = append(opts.ClientOptions, forcePathStyle)
+			},
+		)
+	default:
+		return "", fmt.Errorf("Method not allowed: %s", method)
+	}
+	if err != nil {
+		return "", err
+	}
+	return presignedReq.URL, nil
+}
+
+func (s *s3Storage) CreateMultipartUpload(ctx context.Context, key string) (string, error) {
+	out, err := s.client.CreateMultipartUpload(ctx, &s3.CreateMultipartUploadInput{
+		Bucket: &s.bucket,
+		Key:    &key,
+	}, forcePathStyle)
+	if err != nil {
+		return "", s3Error(err)
+	}
+	return aws.ToString(out.UploadId), nil
+}
+
+func (s *s3Storage) UploadPart(ctx context.Context, key string, uploadID string, partNumber int32, data []byte) error {
+	_, err := s.client.UploadPart(ctx, &s3.UploadPartInput{
+		Bucket:     &s.bucket,
+		Key:        &key,
+		PartNumber: &partNumber,
+		UploadId:   &uploadID,
+		Body:       bytes.NewReader(data),
+	}, forcePathStyle)
+	return s3Error(err)
+}
+
+func (s *s3Storage) CompleteMultipartUpload(ctx context.Context, key string, uploadID string) error {
+	listPartsOutput, err := s.client.ListParts(ctx, &s3.ListPartsInput{
+		Bucket:   &s.bucket,
+		Key:      &key,
+		UploadId: &uploadID,
+	}, forcePathStyle)
+	if err != nil {
+		return fmt.Errorf("failed to list parts: %w", s3Error(err))
+	}
+
+	var completedParts []types.CompletedPart
+	for _, part := range listPartsOutput.Parts {
+		completedParts = append(completedParts, types.CompletedPart{
+			ETag:       part.ETag,
+			PartNumber: part.PartNumber,
+		})
+	}
+
+	_, err = s.client.CompleteMultipartUpload(ctx, &s3.CompleteMultipartUploadInput{
+		Bucket:   &s.bucket,
+		Key:      &key,
+		UploadId: &uploadID,
+		MultipartUpload: &types.CompletedMultipartUpload{
+			Parts: completedParts,
+		},
+	}, forcePathStyle)
+	return s3Error(err)
+}
+
+func (s *s3Storage) AbortMultipartUpload(ctx context.Context, key string, uploadID string) error {
+	_, err := s.client.AbortMultipartUpload(ctx, &s3.AbortMultipartUploadInput{
+		Bucket:   &s.bucket,
+		Key:      &key,
+		UploadId: &uploadID,
+	}, forcePathStyle)
+	return s3Error(err)
+}
@@ -0,0 +1,70 @@
+package reg
+
+import (
+	"context"
+	"fmt"
+	"io"
+	"io/fs"
+	"time"
+)
+
+// errStorageDisabled reports every object as missing with --db-only, so that anything not in
+// the database is unknown to the API (404) rather than a storage failure.
+var errStorageDisabled = fmt.Errorf("storage is disabled with --db-only: %w", fs.ErrNotExist)
+
+// disabledStorage stands in for the storage with --db-only, when the database is the only source
+// of truth and the bucket may not even be reachable anymore, e.g. once credentials were revoked
+// after a bootstrap. It never makes a request: reads find nothing and listings are empty.
+type disabledStorage struct{}
+
+func (disabledStorage) GetObject(context.Context, string) (io.ReadCloser, error) {
+	return nil, errStorageDisabled
+}
+
+func (disabledStorage) GetObjectRange(context.Context, string, int64, int64) (io.ReadCloser, error) {
+	return nil, errStorageDisabled
+}
+
+func (disabledStorage) PutObject(context.Context, string, []byte) error {
+	return errStorageDisabled
+}
+
+func (disabledStorage) StatObject(context.Context, string) (int64, error) {
+	return 0, errStorageDisabled
+}
+
+func (disabledStorage) DeleteObject(context.Context, string) error {
+	return errStorageDisabled
+}
+
+func (disabledStorage) CopyObject(context.Context, string, string) error {
+	return errStorageDisabled
+}
+
+func (disabledStorage) List(context.Context, string, func(ObjectInfo) error) error {
+	return nil
+}
+
+func (disabledStorage) ListPrefixes(context.Context, string, func(string) error) error {
+	return nil
+}
+
+func (disabledStorage) Presign(context.Context, string, string, time.Duration) (string, error) {
+	return "", errStorageDisabled
+}
+
+func (disabledStorage) CreateMultipartUpload(context.Context, string) (string, error) {
+	return "", errStorageDisabled
+}
+
+func (disabledStorage) UploadPart(context.Context, string, string, int32, []byte) error {
+	return errStorageDisabled
+}
+
+func (disabledStorage) CompleteMultipartUpload(context.Context, string, string) error {
+	return errStorageDisabled
+}
+
+func (disabledStorage) AbortMultipartUpload(context.Context, string, string) error {
+	return errStorageDisabled
+}
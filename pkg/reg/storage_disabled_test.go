@@ -0,0 +1,76 @@
+package reg
+
+import (
+	"context"
+	"slices"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/opencontainers/go-digest"
+)
+
+func TestDBOnlyNeverReachesStorage(t *testing.T) {
+	ctx := context.Background()
+	r := newTestRegistry(t)
+	dgst := pushTestImage(t, r, "foo", "latest", "layer")
+	if err := r.Close(); err != nil {
+		t.Fatalf("failed to close registry: %v", err)
+	}
+
+	// NOTICE: the TTLs would send both lookups to storage if they weren't ignored
+	cfg := r.cfg
+	cfg.DBOnly = true
+	cfg.ReadOnly = true
+	cfg.ManifestCacheSize = 0
+	cfg.CacheTTL = time.Nanosecond
+	cfg.CacheTTLOverrides = []string{"foo=0"}
+	dbOnly := newTestRegistryWithStorage(t, panickingStorage{}, cfg)
+
+	for range 2 {
+		_, manifestBytes, err := dbOnly.getManifest(ctx, "foo", "latest")
+		if err != nil {
+			t.Fatalf("failed to get manifest: %v", err)
+		}
+		if got := digest.FromBytes(manifestBytes); got != dgst {
+			t.Fatalf("got manifest %s, want %s", got, dgst)
+		}
+	}
+	tags, err := dbOnly.listTags(ctx, "foo")
+	if err != nil {
+		t.Fatalf("failed to list tags: %v", err)
+	}
+	if !slices.Equal(tags, []string{"latest"}) {
+		t.Fatalf("got tags %v, want [latest]", tags)
+	}
+}
+
+func TestValidateDBOnlyCacheTTL(t *testing.T) {
+	base := testConfig(t.TempDir())
+	base.Listen = ":5000"
+	base.BootstrapWorkers = 1
+	base.ReadOnly = true
+	base.DBOnly = true
+	if err := base.ValidateServe(); err != nil && strings.Contains(err.Error(), "db-only") {
+		t.Fatalf("unexpected db-only error: %v", err)
+	}
+
+	for name, modify := range map[string]func(*Config){
+		"cache-ttl":          func(c *Config) { c.CacheTTL = time.Minute },
+		"cache-ttl-override": func(c *Config) { c.CacheTTLOverrides = []string{"bar=60", "foo/*=0"} },
+	} {
+		t.Run(name, func(t *testing.T) {
+			cfg := base
+			modify(&cfg)
+			err := cfg.ValidateServe()
+			if err == nil || !strings.Contains(err.Error(), "db-only: conflicts with "+name) {
+				t.Fatalf("got error %v, want a db-only conflict with %s", err, name)
+			}
+		})
+	}
+
+	base.CacheTTLOverrides = []string{"foo=60"}
+	if err := base.ValidateServe(); err != nil && strings.Contains(err.Error(), "db-only") {
+		t.Fatalf("unexpected db-only error for a non-zero override: %v", err)
+	}
+}
@@ -0,0 +1,291 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"errors"
+	"fmt"
+	"io"
+	"io/fs"
+	"os"
+	"path"
+	"path/filepath"
+	"strings"
+	"time"
+
+	"github.com/google/uuid"
+)
+
+// fsStorage serves the distribution layout from a local directory, e.g. an rsync'd
+// or mounted copy of a bucket. It cannot presign, so blobs are proxied by the registry.
+type fsStorage struct {
+	root string
+}
+
+func newFSStorage(root string) (*fsStorage, error) {
+	root, err := filepath.Abs(root)
+	if err != nil {
+		return nil, fmt.Errorf("failed to resolve storage root: %w", err)
+	}
+	info, err := os.Stat(root)
+	if err != nil {
+		return nil, fmt.Errorf("failed to open storage root: %w", err)
+	}
+	if !info.IsDir() {
+		return nil, fmt.Errorf("storage root %s is not a directory", root)
+	}
+	return &fsStorage{root: root}, nil
+}
+
+func (s *fsStorage) path(key string) (string, error) {
+	p := filepath.Join(s.root, filepath.FromSlash(key))
+	if p != s.root && !strings.HasPrefix(p, s.root+string(filepath.Separator)) {
+		return "", fmt.Errorf("key %q escapes the storage root", key)
+	}
+	return p, nil
+}
+
+func (s *fsStorage) writeFile(p string, r io.Reader) error {
+	if err := os.MkdirAll(filepath.Dir(p), 0o755); err != nil {
+		return err
+	}
+	tmp, err := os.CreateTemp(filepath.Dir(p), ".tmp-*")
+	// NOTICE: a concurrent delete may have removed the directory as empty in the meantime
+	if errors.Is(err, fs.ErrNotExist) {
+		if err := os.MkdirAll(filepath.Dir(p), 0o755); err != nil {
+			return err
+		}
+		tmp, err = os.CreateTemp(filepath.Dir(p), ".tmp-*")
+	}
+	if err != nil {
+		return err
+	}
+	defer os.Remove(tmp.Name())
+	if _, err := io.Copy(tmp, r); err != nil {
+		tmp.Close()
+		return err
+	}
+	if err := tmp.Close(); err != nil {
+		return err
+	}
+	return os.Rename(tmp.Name(), p)
+}
+
+func (s *fsStorage) GetObject(_ context.Context, key string) (io.ReadCloser, error) {
+	p, err := s.path(key)
+	if err != nil {
+		return nil, err
+	}
+	return os.Open(p)
+}
+
+func (s *fsStorage) GetObjectRange(_ context.Context, key string, offset int64, length int64) (io.ReadCloser, error) {
+	p, err := s.path(key)
+	if err != nil {
+		return nil, err
+	}
+	f, err := os.Open(p)
+	if err != nil {
+		return nil, err
+	}
+	if _, err := f.Seek(offset, io.SeekStart); err != nil {
+		f.Close()
+		return nil, err
+	}
+	return struct {
+		io.Reader
+		io.Closer
+	}{io.LimitReader(f, length), f}, nil
+}
+
+func (s *fsStorage) PutObject(_ context.Context, key string, data []byte) error {
+	p, err := s.path(key)
+	if err != nil {
+		return err
+	}
+	return s.writeFile(p, bytes.NewReader(data))
+}
+
+func (s *fsStorage) StatObject(_ context.Context, key string) (int64, error) {
+	p, err := s.path(key)
+	if err != nil {
+		return 0, err
+	}
+	info, err := os.Stat(p)
+	if err != nil {
+		return 0, err
+	}
+	if info.IsDir() {
+		return 0, fmt.Errorf("%s is a directory: %w", key, fs.ErrNotExist)
+	}
+	return info.Size(), nil
+}
+
+func (s *fsStorage) DeleteObject(_ context.Context, key string) error {
+	p, err := s.path(key)
+	if err != nil {
+		return err
+	}
+	if err := os.Remove(p); err != nil {
+		return err
+	}
+	// NOTICE: in S3 a prefix is gone with its last object, so the directories left empty are
+	// removed too - a deleted tag would otherwise still be listed through its directory
+	for dir := filepath.Dir(p); dir != s.root; dir = filepath.Dir(dir) {
+		if os.Remove(dir) != nil {
+			break
+		}
+	}
+	return nil
+}
+
+func (s *fsStorage) CopyObject(_ context.Context, srcKey string, dstKey string) error {
+	src, err := s.path(srcKey)
+	if err != nil {
+		return err
+	}
+	dst, err := s.path(dstKey)
+	if err != nil {
+		return err
+	}
+	f, err := os.Open(src)
+	if err != nil {
+		return err
+	}
+	defer f.Close()
+	return s.writeFile(dst, f)
+}
+
+func (s *fsStorage) List(_ context.Context, prefix string, fn func(ObjectInfo) error) error {
+	// NOTICE: prefixes are plain key prefixes like in S3, so start walking from the deepest
+	// directory they fully name and filter the rest by string comparison.
+	dir := prefix
+	if !strings.HasSuffix(dir, "/") {
+		dir = filepath.ToSlash(filepath.Dir(dir))
+	}
+	start, err := s.path(dir)
+	if err != nil {
+		return err
+	}
+	err = filepath.WalkDir(start, func(p string, d fs.DirEntry, err error) error {
+		if err != nil {
+			return err
+		}
+		if d.IsDir() {
+			return nil
+		}
+		rel, err := filepath.Rel(s.root, p)
+		if err != nil {
+			return err
+		}
+		key := filepath.ToSlash(rel)
+		if !strings.HasPrefix(key, prefix) || strings.Contains(key, "/.tmp-") {
+			return nil
+		}
+		info, err := d.Info()
+		if err != nil {
+			return err
+		}
+		return fn(ObjectInfo{Key: key, Size: info.Size(), LastModified: info.ModTime()})
+	})
+	if errors.Is(err, fs.ErrNotExist) {
+		return nil
+	}
+	return err
+}
+
+func (s *fsStorage) ListPrefixes(_ context.Context, prefix string, fn func(string) error) error {
+	dir, namePrefix := path.Split(prefix)
+	start, err := s.path(dir)
+	if err != nil {
+		return err
+	}
+	entries, err := os.ReadDir(start)
+	if errors.Is(err, fs.ErrNotExist) {
+		return nil
+	}
+	if err != nil {
+		return err
+	}
+	for _, entry := range entries {
+		if !entry.IsDir() || !strings.HasPrefix(entry.Name(), namePrefix) || strings.HasPrefix(entry.Name(), ".tmp-") {
+			continue
+		}
+		if err := fn(dir + entry.Name() + "/"); err != nil {
+			return err
+		}
+	}
+	return nil
+}
+
+func (s *fsStorage) Presign(context.Context, string, string, time.Duration) (string, error) {
+	return "", ErrPresignUnsupported
+}
+
+func (s *fsStorage) partsDir(key string, uploadID string) (string, error) {
+	return s.path(fmt.Sprintf("%s.parts/%s", key, uploadID))
+}
+
+func (s *fsStorage) CreateMultipartUpload(_ context.Context, key string) (string, error) {
+	uploadID := uuid.New().String()
+	dir, err := s.partsDir(key, uploadID)
+	if err != nil {
+		return "", err
+	}
+	if err := os.MkdirAll(dir, 0o755); err != nil {
+		return "", err
+	}
+	return uploadID, nil
+}
+
+func (s *fsStorage) UploadPart(_ context.Context, key string, uploadID string, partNumber int32, data []byte) error {
+	dir, err := s.partsDir(key, uploadID)
+	if err != nil {
+		return err
+	}
+	return s.writeFile(filepath.Join(dir, fmt.Sprintf("%05d", partNumber)), bytes.NewReader(data))
+}
+
+func (s *fsStorage) CompleteMultipartUpload(_ context.Context, key string, uploadID string) error {
+	dir, err := s.partsDir(key, uploadID)
+	if err != nil {
+		return err
+	}
+	p, err := s.path(key)
+	if err != nil {
+		return err
+	}
+	entries, err := os.ReadDir(dir)
+	if err != nil {
+		return fmt.Errorf("failed to list parts: %w", err)
+	}
+
+	readers := make([]io.Reader, 0, len(entries))
+	for _, entry := range entries {
+		if strings.HasPrefix(entry.Name(), ".tmp-") {
+			continue
+		}
+		f, err := os.Open(filepath.Join(dir, entry.Name()))
+		if err != nil {
+			return err
+		}
+		defer f.Close()
+		readers = append(readers, f)
+	}
+	if err := s.writeFile(p, io.MultiReader(readers...)); err != nil {
+		return err
+	}
+	if err := os.RemoveAll(dir); err != nil {
+		return err
+	}
+	_ = os.Remove(filepath.Dir(dir))
+	return nil
+}
+
+func (s *fsStorage) AbortMultipartUpload(_ context.Context, key string, uploadID string) error {
+	dir, err := s.partsDir(key, uploadID)
+	if err != nil {
+		return err
+	}
+	return os.RemoveAll(dir)
+}
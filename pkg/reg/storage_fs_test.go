@@ -0,0 +1,65 @@
+package reg
+
+import (
+	"net/http"
+	"net/http/httptest"
+	"os"
+	"path/filepath"
+	"reflect"
+	"testing"
+
+	"github.com/opencontainers/go-digest"
+)
+
+// writeTestTree lays out files under root, keyed by their path relative to it.
+func writeTestTree(t *testing.T, root string, files map[string]string) {
+	t.Helper()
+	for key, content := range files {
+		p := filepath.Join(root, filepath.FromSlash(key))
+		if err := os.MkdirAll(filepath.Dir(p), 0o755); err != nil {
+			t.Fatalf("failed to create directory: %v", err)
+		}
+		if err := os.WriteFile(p, []byte(content), 0o644); err != nil {
+			t.Fatalf("failed to write %s: %v", key, err)
+		}
+	}
+}
+
+func TestFSStorageServesRegistryTree(t *testing.T) {
+	cfg := testConfig(t.TempDir())
+	cfg.NoCache = true
+	manifest := `{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json"}`
+	layer := "layer"
+	manifestDigest, layerDigest := digest.FromString(manifest), digest.FromString(layer)
+	blobPath := func(dgst digest.Digest) string {
+		return "docker/registry/v2/blobs/sha256/" + dgst.Hex()[:2] + "/" + dgst.Hex() + "/data"
+	}
+	repo := "docker/registry/v2/repositories/team/app/_manifests/"
+	writeTestTree(t, cfg.StorageRoot, map[string]string{
+		blobPath(manifestDigest):                                    manifest,
+		blobPath(layerDigest):                                       layer,
+		repo + "tags/v1/current/link":                               manifestDigest.String(),
+		repo + "tags/latest/current/link":                           manifestDigest.String(),
+		repo + "revisions/sha256/" + manifestDigest.Hex() + "/link": manifestDigest.String(),
+	})
+	r := newTestRegistryWithStorage(t, newTestFSStorage(t, cfg), cfg)
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		path string
+		want string
+	}{
+		{path: "/v2/team/app/manifests/latest", want: manifest},
+		{path: "/v2/team/app/manifests/" + manifestDigest.String(), want: manifest},
+		{path: "/v2/team/app/blobs/" + layerDigest.String(), want: layer},
+	} {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodGet, tc.path, nil))
+		if rec.Code != http.StatusOK || rec.Body.String() != tc.want {
+			t.Errorf("got status %d and %q for %s, want %d and %q", rec.Code, rec.Body, tc.path, http.StatusOK, tc.want)
+		}
+	}
+
+	if got, want := getTestTags(t, router, "team/app"), []string{"latest", "v1"}; !reflect.DeepEqual(got, want) {
+		t.Errorf("got tags %v, want %v", got, want)
+	}
+}
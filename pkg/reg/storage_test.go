@@ -0,0 +1,139 @@
+package reg
+
+import (
+	"context"
+	"errors"
+	"io/fs"
+	"net/http"
+	"net/http/httptest"
+	"sync"
+	"sync/atomic"
+	"testing"
+	"time"
+
+	awshttp "github.com/aws/aws-sdk-go-v2/aws/transport/http"
+	"github.com/aws/aws-sdk-go-v2/service/s3/types"
+	smithyhttp "github.com/aws/smithy-go/transport/http"
+	"github.com/opencontainers/go-digest"
+)
+
+// s3ResponseError is an error as returned by the S3 client for a response with status.
+func s3ResponseError(status int) error {
+	return &awshttp.ResponseError{
+		ResponseError: &smithyhttp.ResponseError{
+			Response: &smithyhttp.Response{Response: &http.Response{StatusCode: status}},
+			Err:      errors.New(http.StatusText(status)),
+		},
+	}
+}
+
+// failingStorage fails to stat any object with err.
+type failingStorage struct {
+	Storage
+	err error
+}
+
+func (s failingStorage) StatObject(context.Context, string) (int64, error) {
+	return 0, s.err
+}
+
+func TestStorageErrorStatus(t *testing.T) {
+	for _, tc := range []struct {
+		name       string
+		err        error
+		status     int
+		retryAfter string
+	}{
+		{name: "missing key", err: s3Error(&types.NoSuchKey{}), status: http.StatusNotFound},
+		{name: "missing object", err: s3Error(&types.NotFound{}), status: http.StatusNotFound},
+		{name: "slow down", err: s3Error(s3ResponseError(http.StatusServiceUnavailable)), status: http.StatusServiceUnavailable, retryAfter: "1"},
+		{name: "too many requests", err: s3Error(s3ResponseError(http.StatusTooManyRequests)), status: http.StatusServiceUnavailable, retryAfter: "1"},
+		{name: "access denied", err: s3Error(s3ResponseError(http.StatusForbidden)), status: http.StatusBadGateway},
+		{name: "connection failure", err: s3Error(errors.New("connection refused")), status: http.StatusBadGateway},
+		{name: "other failure", err: errors.New("unexpected"), status: http.StatusInternalServerError},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			cfg := testConfig(t.TempDir())
+			router := newTestRouter(t, newTestRegistryWithStorage(t, failingStorage{err: tc.err}, cfg))
+
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodHead, "/v2/foo/blobs/"+digest.FromString("blob").String(), nil))
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d, want %d", rec.Code, tc.status)
+			}
+			if got := rec.Header().Get("Retry-After"); got != tc.retryAfter {
+				t.Errorf("got Retry-After %q, want %q", got, tc.retryAfter)
+			}
+		})
+	}
+
+	if err := s3Error(s3ResponseError(http.StatusServiceUnavailable)); !errors.Is(err, ErrStorage) || errors.Is(err, fs.ErrNotExist) {
+		t.Errorf("got error %v, want a storage error", err)
+	}
+}
+
+// blockingHTTPClient holds every request until release is closed, recording the most held at once.
+type blockingHTTPClient struct {
+	release  chan struct{}
+	inFlight atomic.Int64
+	peak     atomic.Int64
+}
+
+func (c *blockingHTTPClient) Do(*http.Request) (*http.Response, error) {
+	n := c.inFlight.Add(1)
+	defer c.inFlight.Add(-1)
+	for {
+		peak := c.peak.Load()
+		if n <= peak || c.peak.CompareAndSwap(peak, n) {
+			break
+		}
+	}
+	<-c.release
+	return &http.Response{StatusCode: http.StatusOK, Body: http.NoBody}, nil
+}
+
+func TestS3RequestLimiter(t *testing.T) {
+	const limit, requests = 3, 10
+	backend := &blockingHTTPClient{release: make(chan struct{})}
+	limiter := newS3RequestLimiter(limit)
+	client := limitedHTTPClient{client: backend, limiter: limiter}
+
+	var wg sync.WaitGroup
+	for range requests {
+		wg.Add(1)
+		go func() {
+			defer wg.Done()
+			if _, err := client.Do(httptest.NewRequest(http.MethodGet, "/", nil)); err != nil {
+				t.Errorf("request failed: %v", err)
+			}
+		}()
+	}
+	deadline := time.Now().Add(5 * time.Second)
+	for limiter.inFlight.Load() != limit || limiter.queued.Load() != requests-limit {
+		if time.Now().After(deadline) {
+			t.Fatalf("got %d requests in flight and %d queued, want %d and %d", limiter.inFlight.Load(), limiter.queued.Load(), limit, requests-limit)
+		}
+		time.Sleep(time.Millisecond)
+	}
+	close(backend.release)
+	wg.Wait()
+
+	if peak := backend.peak.Load(); peak > limit {
+		t.Errorf("got %d requests in flight at once, want at most %d", peak, limit)
+	}
+	if limiter.inFlight.Load() != 0 || limiter.queued.Load() != 0 {
+		t.Errorf("got %d requests in flight and %d queued once done", limiter.inFlight.Load(), limiter.queued.Load())
+	}
+
+	// NOTICE: a request queued for a slot gives up along with its context
+	for range limit {
+		limiter.slots <- struct{}{}
+	}
+	ctx, cancel := context.WithCancel(context.Background())
+	cancel()
+	if _, err := client.Do(httptest.NewRequest(http.MethodGet, "/", nil).WithContext(ctx)); !errors.Is(err, context.Canceled) {
+		t.Errorf("got error %v for a cancelled queued request, want %v", err, context.Canceled)
+	}
+	if limiter.queued.Load() != 0 {
+		t.Errorf("got %d requests queued after the cancelled one gave up", limiter.queued.Load())
+	}
+}
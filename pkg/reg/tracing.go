@@ -0,0 +1,361 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"encoding/hex"
+	"encoding/json"
+	"fmt"
+	"log/slog"
+	"math/rand/v2"
+	"net/http"
+	"net/url"
+	"strconv"
+	"strings"
+	"sync"
+	"sync/atomic"
+	"time"
+
+	"github.com/aws/aws-sdk-go-v2/aws"
+	"github.com/gorilla/mux"
+)
+
+// Spans are exported to an OTLP collector over HTTP, using the JSON encoding of the protocol
+// (https://opentelemetry.io/docs/specs/otlp/#otlphttp), which keeps the exporter dependency free.
+const (
+	tracingBatchSize     = 512
+	tracingQueueSize     = 4096
+	tracingFlushInterval = 5 * time.Second
+	tracingExportTimeout = 10 * time.Second
+
+	spanKindServer = 2
+	spanKindClient = 3
+
+	spanStatusError = 2
+)
+
+type otlpValue struct {
+	StringValue *string `json:"stringValue,omitempty"`
+	IntValue    *string `json:"intValue,omitempty"`
+}
+
+type otlpAttribute struct {
+	Key   string    `json:"key"`
+	Value otlpValue `json:"value"`
+}
+
+func stringAttribute(key string, value string) otlpAttribute {
+	return otlpAttribute{Key: key, Value: otlpValue{StringValue: &value}}
+}
+
+func intAttribute(key string, value int64) otlpAttribute {
+	formatted := strconv.FormatInt(value, 10)
+	return otlpAttribute{Key: key, Value: otlpValue{IntValue: &formatted}}
+}
+
+type otlpStatus struct {
+	Code    int    `json:"code,omitempty"`
+	Message string `json:"message,omitempty"`
+}
+
+type otlpSpan struct {
+	TraceID           string          `json:"traceId"`
+	SpanID            string          `json:"spanId"`
+	ParentSpanID      string          `json:"parentSpanId,omitempty"`
+	Name              string          `json:"name"`
+	Kind              int             `json:"kind"`
+	StartTimeUnixNano string          `json:"startTimeUnixNano"`
+	EndTimeUnixNano   string          `json:"endTimeUnixNano"`
+	Attributes        []otlpAttribute `json:"attributes,omitempty"`
+	Status            otlpStatus      `json:"status"`
+}
+
+type spanContext struct {
+	traceID [16]byte
+	spanID  [8]byte
+}
+
+type spanContextKey struct{}
+
+// span is an in-progress span, nil when tracing is disabled - all its methods are no-ops then.
+type span struct {
+	tracer   *tracer
+	context  spanContext
+	parentID [8]byte
+	name     string
+	kind     int
+	start    time.Time
+	attrs    []otlpAttribute
+	status   otlpStatus
+}
+
+func (s *span) setAttributes(attrs ...otlpAttribute) {
+	if s != nil {
+		s.attrs = append(s.attrs, attrs...)
+	}
+}
+
+func (s *span) setError(message string) {
+	if s != nil {
+		s.status = otlpStatus{Code: spanStatusError, Message: message}
+	}
+}
+
+func (s *span) end() {
+	if s == nil {
+		return
+	}
+	exported := otlpSpan{
+		TraceID:           hex.EncodeToString(s.context.traceID[:]),
+		SpanID:            hex.EncodeToString(s.context.spanID[:]),
+		Name:              s.name,
+		Kind:              s.kind,
+		StartTimeUnixNano: strconv.FormatInt(s.start.UnixNano(), 10),
+		EndTimeUnixNano:   strconv.FormatInt(time.Now().UnixNano(), 10),
+		Attributes:        s.attrs,
+		Status:            s.status,
+	}
+	if s.parentID != [8]byte{} {
+		exported.ParentSpanID = hex.EncodeToString(s.parentID[:])
+	}
+	s.tracer.enqueue(exported)
+}
+
+type tracer struct {
+	endpoint string
+	client   *http.Client
+	queue    chan otlpSpan
+	stop     chan struct{}
+	done     chan struct{}
+	dropped  atomic.Uint64
+}
+
+// activeTracer is set by SetupTracing, nil when tracing is disabled.
+var activeTracer atomic.Pointer[tracer]
+
+// SetupTracing starts exporting spans to the OTLP/HTTP collector at endpoint (e.g. http://localhost:4318).
+// The returned function flushes the pending spans and stops the exporter.
+func SetupTracing(endpoint string) (func(context.Context) error, error) {
+	tracesURL, err := otlpTracesURL(endpoint)
+	if err != nil {
+		return nil, err
+	}
+	t := &tracer{
+		endpoint: tracesURL,
+		client:   &http.Client{Timeout: tracingExportTimeout},
+		queue:    make(chan otlpSpan, tracingQueueSize),
+		stop:     make(chan struct{}),
+		done:     make(chan struct{}),
+	}
+	go t.run()
+	activeTracer.Store(t)
+
+	var stopOnce sync.Once
+	return func(ctx context.Context) error {
+		activeTracer.Store(nil)
+		stopOnce.Do(func() { close(t.stop) })
+		select {
+		case <-t.done:
+			return nil
+		case <-ctx.Done():
+			return fmt.Errorf("failed to flush spans: %w", ctx.Err())
+		}
+	}, nil
+}
+
+// otlpTracesURL appends the standard /v1/traces path to endpoints given without one.
+func otlpTracesURL(endpoint string) (string, error) {
+	parsed, err := url.Parse(endpoint)
+	if err != nil || (parsed.Scheme != "http" && parsed.Scheme != "https") || parsed.Host == "" {
+		return "", fmt.Errorf("invalid OTLP endpoint %q, expected http(s)://host[:port][/path]", endpoint)
+	}
+	if parsed.Path == "" || parsed.Path == "/" {
+		parsed.Path = "/v1/traces"
+	}
+	return parsed.String(), nil
+}
+
+func tracingEnabled() bool {
+	return activeTracer.Load() != nil
+}
+
+// startSpan starts a span, a child of the span in ctx if any.
+func startSpan(ctx context.Context, name string, kind int, attrs ...otlpAttribute) (context.Context, *span) {
+	t := activeTracer.Load()
+	if t == nil {
+		return ctx, nil
+	}
+	s := &span{tracer: t, name: name, kind: kind, start: time.Now(), attrs: attrs}
+	if parent, ok := ctx.Value(spanContextKey{}).(spanContext); ok {
+		s.context.traceID = parent.traceID
+		s.parentID = parent.spanID
+	} else {
+		putRandom(s.context.traceID[:])
+	}
+	putRandom(s.context.spanID[:])
+	return context.WithValue(ctx, spanContextKey{}, s.context), s
+}
+
+func putRandom(id []byte) {
+	for i := range id {
+		id[i] = byte(rand.Uint32())
+	}
+}
+
+// NOTICE: spans are dropped rather than blocking requests when the collector can't keep up
+func (t *tracer) enqueue(s otlpSpan) {
+	select {
+	case t.queue <- s:
+	default:
+		if t.dropped.Add(1)%1000 == 1 {
+			slog.Warn("dropping spans, the OTLP collector is not keeping up", "dropped", t.dropped.Load())
+		}
+	}
+}
+
+func (t *tracer) run() {
+	defer close(t.done)
+	ticker := time.NewTicker(tracingFlushInterval)
+	defer ticker.Stop()
+	var batch []otlpSpan
+	for {
+		select {
+		case <-t.stop:
+			// NOTICE: the queue is never closed, spans of requests still in flight may end after shutdown
+			for {
+				select {
+				case s := <-t.queue:
+					batch = append(batch, s)
+				default:
+					t.export(batch)
+					return
+				}
+			}
+		case s := <-t.queue:
+			batch = append(batch, s)
+			if len(batch) >= tracingBatchSize {
+				t.export(batch)
+				batch = nil
+			}
+		case <-ticker.C:
+			t.export(batch)
+			batch = nil
+		}
+	}
+}
+
+func (t *tracer) export(spans []otlpSpan) {
+	if len(spans) == 0 {
+		return
+	}
+	payload := map[string]any{
+		"resourceSpans": []any{map[string]any{
+			"resource": map[string]any{
+				"attributes": []otlpAttribute{stringAttribute("service.name", "reg")},
+			},
+			"scopeSpans": []any{map[string]any{
+				"scope": map[string]string{"name": "github.com/psarna/reg"},
+				"spans": spans,
+			}},
+		}},
+	}
+	body, err := json.Marshal(payload)
+	if err != nil {
+		slog.Warn("error encoding spans", "error", err)
+		return
+	}
+	resp, err := t.client.Post(t.endpoint, "application/json", bytes.NewReader(body))
+	if err != nil {
+		slog.Warn("error exporting spans", "endpoint", t.endpoint, "spans", len(spans), "error", err)
+		return
+	}
+	resp.Body.Close()
+	if resp.StatusCode >= 300 {
+		slog.Warn("OTLP collector rejected spans", "endpoint", t.endpoint, "spans", len(spans), "status", resp.StatusCode)
+	}
+}
+
+// parseTraceparent extracts the remote parent from a W3C traceparent header, e.g.
+// 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01.
+func parseTraceparent(header string) (spanContext, bool) {
+	parts := strings.Split(strings.TrimSpace(header), "-")
+	if len(parts) != 4 || len(parts[1]) != 32 || len(parts[2]) != 16 {
+		return spanContext{}, false
+	}
+	var parent spanContext
+	if _, err := hex.Decode(parent.traceID[:], []byte(parts[1])); err != nil {
+		return spanContext{}, false
+	}
+	if _, err := hex.Decode(parent.spanID[:], []byte(parts[2])); err != nil {
+		return spanContext{}, false
+	}
+	if parent.traceID == [16]byte{} || parent.spanID == [8]byte{} {
+		return spanContext{}, false
+	}
+	return parent, true
+}
+
+// traceRequests wraps every request in a server span, continuing the trace of the client if
+// it sent a traceparent header. It runs as router middleware, so the span is named after the route.
+func traceRequests(next http.Handler) http.Handler {
+	return http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		ctx := r.Context()
+		if parent, ok := parseTraceparent(r.Header.Get("traceparent")); ok {
+			ctx = context.WithValue(ctx, spanContextKey{}, parent)
+		}
+		route := r.URL.Path
+		if current := mux.CurrentRoute(r); current != nil {
+			if template, err := current.GetPathTemplate(); err == nil {
+				route = template
+			}
+		}
+		attrs := []otlpAttribute{
+			stringAttribute("http.request.method", r.Method),
+			stringAttribute("http.route", route),
+			stringAttribute("url.path", r.URL.Path),
+		}
+		vars := mux.Vars(r)
+		for _, key := range []string{"name", "reference", "digest"} {
+			if v, ok := vars[key]; ok {
+				attrs = append(attrs, stringAttribute("registry."+key, v))
+			}
+		}
+		ctx, s := startSpan(ctx, r.Method+" "+route, spanKindServer, attrs...)
+		defer s.end()
+
+		rec := &statusRecorder{ResponseWriter: w}
+		next.ServeHTTP(rec, r.WithContext(ctx))
+		if rec.status == 0 {
+			rec.status = http.StatusOK
+		}
+		s.setAttributes(intAttribute("http.response.status_code", int64(rec.status)))
+		if rec.status >= 500 {
+			s.setError(http.StatusText(rec.status))
+		}
+	})
+}
+
+// tracedHTTPClient wraps every request of the S3 SDK, retries included, in a client span,
+// so that storage latency shows up under the request which caused it.
+type tracedHTTPClient struct {
+	client aws.HTTPClient
+}
+
+func (c tracedHTTPClient) Do(req *http.Request) (*http.Response, error) {
+	ctx, s := startSpan(req.Context(), "s3 "+req.Method, spanKindClient,
+		stringAttribute("http.request.method", req.Method),
+		stringAttribute("server.address", req.URL.Host),
+		stringAttribute("url.path", req.URL.Path),
+	)
+	defer s.end()
+	resp, err := c.client.Do(req.WithContext(ctx))
+	if err != nil {
+		s.setError(err.Error())
+		return resp, err
+	}
+	s.setAttributes(intAttribute("http.response.status_code", int64(resp.StatusCode)))
+	if resp.StatusCode >= 400 {
+		s.setError(resp.Status)
+	}
+	return resp, nil
+}
@@ -0,0 +1,234 @@
+package reg
+
+import (
+	"bytes"
+	"context"
+	"fmt"
+	"io"
+	"net/http"
+	"net/http/httptest"
+	"path"
+	"strings"
+	"testing"
+
+	"github.com/opencontainers/go-digest"
+)
+
+func TestCompleteUpload(t *testing.T) {
+	for _, tc := range []struct {
+		name    string
+		data    []byte
+		chunked bool
+	}{
+		{name: "monolithic", data: []byte("hello")},
+		{name: "chunked", data: []byte("hello"), chunked: true},
+		{name: "empty", data: nil},
+		{name: "empty chunked", data: nil, chunked: true},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			r := newTestRegistry(t)
+			router := newTestRouter(t, r)
+
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+			if rec.Code != http.StatusAccepted {
+				t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+			}
+
+			dgst := digest.FromBytes(tc.data)
+			req := httptest.NewRequest(http.MethodPut, rec.Header().Get("Location")+"?digest="+dgst.String(), bytes.NewReader(tc.data))
+			if tc.chunked {
+				req.ContentLength = -1
+				req.TransferEncoding = []string{"chunked"}
+			}
+			rec = serveTestRequest(router, req)
+			if rec.Code != http.StatusCreated {
+				t.Fatalf("got status %d completing the upload, want %d: %s", rec.Code, http.StatusCreated, rec.Body)
+			}
+
+			key, err := r.blobKey(dgst.String())
+			if err != nil {
+				t.Fatalf("failed to get blob key: %v", err)
+			}
+			body, err := r.storage.GetObject(context.Background(), key)
+			if err != nil {
+				t.Fatalf("blob was not stored: %v", err)
+			}
+			defer body.Close()
+			got, err := io.ReadAll(body)
+			if err != nil {
+				t.Fatalf("failed to read blob: %v", err)
+			}
+			if !bytes.Equal(got, tc.data) {
+				t.Fatalf("got blob %q, want %q", got, tc.data)
+			}
+		})
+	}
+}
+
+func TestMountBlob(t *testing.T) {
+	r := newTestRegistry(t)
+	router := newTestRouter(t, r)
+	mounted := putTestBlob(t, r, []byte("mounted"))
+
+	for _, tc := range []struct {
+		name     string
+		digest   digest.Digest
+		status   int
+		location string
+	}{
+		{name: "existing blob", digest: mounted.Digest, status: http.StatusCreated, location: "/v2/bar/blobs/" + mounted.Digest.String()},
+		{name: "unknown blob", digest: digest.FromString("unknown"), status: http.StatusAccepted, location: "/v2/bar/blobs/uploads/"},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			target := "/v2/bar/blobs/uploads/?mount=" + tc.digest.String() + "&from=foo"
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, target, nil))
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d, want %d: %s", rec.Code, tc.status, rec.Body)
+			}
+			if location := rec.Header().Get("Location"); !strings.HasPrefix(location, tc.location) {
+				t.Fatalf("got Location %q, want %q", location, tc.location)
+			}
+		})
+	}
+}
+
+func TestUploadSessionOfOtherRepository(t *testing.T) {
+	r := newTestRegistry(t)
+	router := newTestRouter(t, r)
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+	}
+	location := strings.Replace(rec.Header().Get("Location"), "/v2/foo/", "/v2/bar/", 1)
+	dgst := digest.FromString("hello")
+
+	for _, req := range []*http.Request{
+		httptest.NewRequest(http.MethodGet, location, nil),
+		httptest.NewRequest(http.MethodPatch, location, strings.NewReader("hello")),
+		httptest.NewRequest(http.MethodPut, location+"?digest="+dgst.String(), strings.NewReader("hello")),
+		httptest.NewRequest(http.MethodDelete, location, nil),
+	} {
+		rec := serveTestRequest(router, req)
+		if rec.Code != http.StatusNotFound || !strings.Contains(rec.Body.String(), errCodeBlobUploadUnknown) {
+			t.Errorf("%s through another repository: got status %d, want %d %s: %s", req.Method, rec.Code, http.StatusNotFound, errCodeBlobUploadUnknown, rec.Body)
+		}
+	}
+	if _, err := r.db.GetUploadSession(path.Base(location)); err != nil {
+		t.Fatalf("upload session is gone: %v", err)
+	}
+}
+
+// unreadableUploadsStorage fails reading back any upload in progress.
+type unreadableUploadsStorage struct {
+	Storage
+}
+
+func (s unreadableUploadsStorage) GetObject(ctx context.Context, key string) (io.ReadCloser, error) {
+	if strings.HasPrefix(key, "uploads/") {
+		return nil, fmt.Errorf("upload %s read back", key)
+	}
+	return s.Storage.GetObject(ctx, key)
+}
+
+func TestCompleteUploadHashesChunks(t *testing.T) {
+	cfg := testConfig(t.TempDir())
+	fsStorage := newTestFSStorage(t, cfg)
+	r := newTestRegistryWithStorage(t, unreadableUploadsStorage{fsStorage}, cfg)
+	router := newTestRouter(t, r)
+
+	for _, tc := range []struct {
+		name   string
+		digest digest.Digest
+		status int
+	}{
+		{name: "matching digest", digest: digest.FromString("hello world"), status: http.StatusCreated},
+		{name: "mismatched digest", digest: digest.FromString("hello"), status: http.StatusBadRequest},
+	} {
+		t.Run(tc.name, func(t *testing.T) {
+			rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+			if rec.Code != http.StatusAccepted {
+				t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+			}
+			location := rec.Header().Get("Location")
+			for _, chunk := range []string{"hello", " world"} {
+				rec := serveTestRequest(router, httptest.NewRequest(http.MethodPatch, location, strings.NewReader(chunk)))
+				if rec.Code != http.StatusAccepted {
+					t.Fatalf("got status %d uploading a chunk, want %d: %s", rec.Code, http.StatusAccepted, rec.Body)
+				}
+			}
+			rec = serveTestRequest(router, httptest.NewRequest(http.MethodPut, location+"?digest="+tc.digest.String(), nil))
+			if rec.Code != tc.status {
+				t.Fatalf("got status %d completing the upload, want %d: %s", rec.Code, tc.status, rec.Body)
+			}
+		})
+	}
+}
+
+func TestUploadRange(t *testing.T) {
+	r := newTestRegistry(t)
+	router := newTestRouter(t, r)
+
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+	}
+	if _, ok := rec.Header()["Range"]; ok {
+		t.Fatalf("got Range %q for an empty upload, want none", rec.Header().Get("Range"))
+	}
+	location := rec.Header().Get("Location")
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodGet, location, nil))
+	if _, ok := rec.Header()["Range"]; ok {
+		t.Fatalf("got Range %q for the status of an empty upload, want none", rec.Header().Get("Range"))
+	}
+
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodPatch, location, strings.NewReader("hello")))
+	if got := rec.Header().Get("Range"); got != "0-4" {
+		t.Fatalf("got Range %q after a chunk, want 0-4", got)
+	}
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodGet, location, nil))
+	if got := rec.Header().Get("Range"); got != "0-4" {
+		t.Fatalf("got Range %q for the upload status, want 0-4", got)
+	}
+}
+
+func TestMaxUploadBytes(t *testing.T) {
+	const limit = 10
+	r := newTestRegistry(t, func(cfg *Config) { cfg.MaxUploadBytes = limit })
+	router := newTestRouter(t, r)
+	startUpload := func() string {
+		rec := serveTestRequest(router, httptest.NewRequest(http.MethodPost, "/v2/foo/blobs/uploads/", nil))
+		if rec.Code != http.StatusAccepted {
+			t.Fatalf("got status %d starting the upload, want %d", rec.Code, http.StatusAccepted)
+		}
+		return rec.Header().Get("Location")
+	}
+	expectTooLarge := func(rec *httptest.ResponseRecorder, what string) {
+		t.Helper()
+		if rec.Code != http.StatusRequestEntityTooLarge || !strings.Contains(rec.Body.String(), errCodeSizeInvalid) {
+			t.Errorf("got status %d for %s, want %d %s: %s", rec.Code, what, http.StatusRequestEntityTooLarge, errCodeSizeInvalid, rec.Body)
+		}
+	}
+
+	// NOTICE: refused upfront from its Content-Length
+	expectTooLarge(serveTestRequest(router, httptest.NewRequest(http.MethodPatch, startUpload(), strings.NewReader("01234567890"))), "an announced oversized chunk")
+
+	req := httptest.NewRequest(http.MethodPatch, startUpload(), strings.NewReader("01234567890"))
+	req.ContentLength = -1
+	req.TransferEncoding = []string{"chunked"}
+	expectTooLarge(serveTestRequest(router, req), "a streamed oversized chunk")
+
+	// NOTICE: every chunk fits, but not the blob they add up to
+	location := startUpload()
+	rec := serveTestRequest(router, httptest.NewRequest(http.MethodPatch, location, strings.NewReader("012345")))
+	if rec.Code != http.StatusAccepted {
+		t.Fatalf("got status %d for the first chunk, want %d: %s", rec.Code, http.StatusAccepted, rec.Body)
+	}
+	expectTooLarge(serveTestRequest(router, httptest.NewRequest(http.MethodPatch, location, strings.NewReader("67890"))), "chunks adding up to an oversized blob")
+
+	data := []byte("0123456789")
+	rec = serveTestRequest(router, httptest.NewRequest(http.MethodPut, startUpload()+"?digest="+digest.FromBytes(data).String(), bytes.NewReader(data)))
+	if rec.Code != http.StatusCreated {
+		t.Errorf("got status %d for a blob at the limit, want %d: %s", rec.Code, http.StatusCreated, rec.Body)
+	}
+}